@@ -82,21 +82,44 @@ impl AABB {
         }
     }
 
+    pub fn surface_area(&self) -> f64 {
+        let (x, y, z) = (self.x.size(), self.y.size(), self.z.size());
+        2.0 * (x * y + y * z + z * x)
+    }
+
+    pub fn centroid(&self) -> Point3f {
+        Point3f::new(
+            0.5 * (self.x.min + self.x.max),
+            0.5 * (self.y.min + self.y.max),
+            0.5 * (self.z.min + self.z.max)
+        )
+    }
+
     pub fn hit(&self, ray: &Ray, ray_t: &Interval) -> bool {
-        let ray_orig: &Point3f = ray.origin();
-        let ray_dir: &Vec3f  = ray.direction();
+        let ray_dir: &Vec3f = ray.direction();
+        let inverse_direction: Vec3f = Vec3f::new(
+            1.0 / ray_dir.component(Axis::X),
+            1.0 / ray_dir.component(Axis::Y),
+            1.0 / ray_dir.component(Axis::Z)
+        );
+        self.hit_inverse(ray.origin(), &inverse_direction, ray_t)
+    }
 
+    pub fn hit_inverse(&self, origin: &Point3f, inverse_direction: &Vec3f, ray_t: &Interval) -> bool {
+        // Slab test narrowing the ray interval axis by axis. Callers testing many boxes against
+        // one ray can compute the reciprocal of its direction once.
+        let mut t_min: f64 = ray_t.min;
+        let mut t_max: f64 = ray_t.max;
         for &axis in Axis::iterator() {
             let ax_ivl: Interval = self.axis_interval(axis);
-            let ad_inv: f64 = 1.0 / ray_dir.component(axis);
+            let ad_inv: f64 = inverse_direction.component(axis);
 
-            let t0 = (ax_ivl.min - ray_orig.component(axis)) * ad_inv;
-            let t1 = (ax_ivl.max - ray_orig.component(axis)) * ad_inv;
- 
-            let min_check: f64 = f64::max(ray_t.min, f64::min(t0, t1));
-            let max_check: f64 = f64::min(ray_t.max, f64::max(t0, t1));
+            let t0 = (ax_ivl.min - origin.component(axis)) * ad_inv;
+            let t1 = (ax_ivl.max - origin.component(axis)) * ad_inv;
 
-            if max_check <= min_check {
+            t_min = f64::max(t_min, f64::min(t0, t1));
+            t_max = f64::min(t_max, f64::max(t0, t1));
+            if t_max <= t_min {
                 return false;
             }
         }
@@ -118,10 +141,10 @@ impl AABB {
     }
 }
 
-impl<'v, 'bb> Add<&'v Vec3f> for &'bb AABB {
+impl Add<&Vec3f> for &AABB {
     type Output = AABB;
 
-    fn add(self, other: &'v Vec3f) -> AABB {
+    fn add(self, other: &Vec3f) -> AABB {
         AABB { 
             x: self.x + other.component(Axis::X), 
             y: self.y + other.component(Axis::Y), 
@@ -150,7 +173,7 @@ impl<'v> Add<&'v Vec3f> for AABB {
     }
 }
 
-impl<'bb> Add<Vec3f> for &'bb AABB {    
+impl Add<Vec3f> for &AABB {    
     type Output = AABB;
 
     #[inline]
@@ -160,11 +183,11 @@ impl<'bb> Add<Vec3f> for &'bb AABB {
     }
 }
 
-impl<'bb, 'v> Add<&'bb AABB> for &'v Vec3f {
+impl Add<&AABB> for &Vec3f {
     type Output = AABB;
 
     #[inline]
-    fn add(self, other: &'bb AABB) -> AABB {
+    fn add(self, other: &AABB) -> AABB {
         other + self
     }
 }
@@ -189,7 +212,7 @@ impl<'bb> Add<&'bb AABB> for Vec3f {
     }
 }
 
-impl<'v> Add<AABB> for &'v Vec3f {
+impl Add<AABB> for &Vec3f {
     type Output = AABB;
 
     #[inline]
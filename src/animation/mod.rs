@@ -0,0 +1,375 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::camera::Camera;
+use crate::hittable::Hittable;
+use crate::hittable_list::HittableList;
+use crate::quaternion::Quaternion;
+use crate::shutter::Shutter;
+use crate::transform::{MotionTransformed, Transform, Transformed};
+use crate::vec3::{Axis, Point3f, Vec3f};
+
+// Keyframe animation of object transforms and camera parameters over scene time, in seconds. Scene
+// time is separate from Ray::time, which only spans a single frame.
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Interpolation {
+    #[default]
+    Linear,
+    // Eases in and out of every keyframe.
+    Smoothstep,
+    // Catmull-Rom spline through the keyframes. Rotations use slerp instead.
+    Spline,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Keyframe<T> {
+    pub time: f64,
+    pub value: T,
+}
+
+pub trait Interpolate: Copy {
+    fn lerp(a: &Self, b: &Self, t: f64) -> Self;
+
+    fn spline(_p0: &Self, p1: &Self, p2: &Self, _p3: &Self, t: f64) -> Self {
+        Self::lerp(p1, p2, t)
+    }
+}
+
+impl Interpolate for f64 {
+    fn lerp(a: &f64, b: &f64, t: f64) -> f64 {
+        (1.0 - t) * a + t * b
+    }
+
+    fn spline(p0: &f64, p1: &f64, p2: &f64, p3: &f64, t: f64) -> f64 {
+        // Uniform Catmull-Rom segment from p1 to p2.
+        let t2: f64 = t * t;
+        let t3: f64 = t2 * t;
+        0.5 * (2.0 * p1 + (p2 - p0) * t + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * t2 + (3.0 * (p1 - p2) + p3 - p0) * t3)
+    }
+}
+
+impl Interpolate for Vec3f {
+    fn lerp(a: &Vec3f, b: &Vec3f, t: f64) -> Vec3f {
+        (1.0 - t) * a + t * b
+    }
+
+    fn spline(p0: &Vec3f, p1: &Vec3f, p2: &Vec3f, p3: &Vec3f, t: f64) -> Vec3f {
+        let component = |axis: Axis| f64::spline(&p0.component(axis), &p1.component(axis), &p2.component(axis), &p3.component(axis), t);
+        Vec3f::new(component(Axis::X), component(Axis::Y), component(Axis::Z))
+    }
+}
+
+impl Interpolate for Quaternion {
+    fn lerp(a: &Quaternion, b: &Quaternion, t: f64) -> Quaternion {
+        a.slerp(b, t)
+    }
+}
+
+
+pub struct Track<T> {
+    keyframes: Vec<Keyframe<T>>,
+    interpolation: Interpolation
+}
+
+impl<T: Interpolate> Track<T> {
+    pub fn new(keyframes: Vec<Keyframe<T>>, interpolation: Interpolation) -> Result<Self, String> {
+        if keyframes.is_empty() {
+            return Err("an animation track needs at least one keyframe".to_string());
+        }
+        if let Some(pair) = keyframes.windows(2).find(|pair| pair[0].time.partial_cmp(&pair[1].time) != Some(Ordering::Less)) {
+            return Err(format!("keyframe times must increase, but {} is followed by {}", pair[0].time, pair[1].time));
+        }
+        Ok(Self { keyframes, interpolation })
+    }
+
+    pub fn keyframes(&self) -> &[Keyframe<T>] {
+        &self.keyframes
+    }
+
+    pub fn start(&self) -> f64 {
+        self.keyframes[0].time
+    }
+
+    pub fn end(&self) -> f64 {
+        self.keyframes[self.keyframes.len() - 1].time
+    }
+
+    pub fn at(&self, time: f64) -> T {
+        // Holds the first and last values outside the keyframed range.
+        let next: usize = self.keyframes.partition_point(|keyframe| keyframe.time <= time);
+        if next == 0 {
+            return self.keyframes[0].value;
+        }
+        if next == self.keyframes.len() {
+            return self.keyframes[next - 1].value;
+        }
+
+        let (a, b) = (&self.keyframes[next - 1], &self.keyframes[next]);
+        let t: f64 = (time - a.time) / (b.time - a.time);
+        match self.interpolation {
+            Interpolation::Linear => T::lerp(&a.value, &b.value, t),
+            Interpolation::Smoothstep => T::lerp(&a.value, &b.value, t * t * (3.0 - 2.0 * t)),
+            Interpolation::Spline => {
+                // The end keyframes are repeated to close off the first and last segments.
+                let before: &T = &self.keyframes[(next - 1).saturating_sub(1)].value;
+                let after: &T = &self.keyframes[(next + 1).min(self.keyframes.len() - 1)].value;
+                T::spline(before, &a.value, &b.value, after, t)
+            }
+        }
+    }
+}
+
+
+// Scales, then rotates, then translates an object; tracks that are left out do not change it.
+#[derive(Default)]
+pub struct TransformAnimation {
+    pub scale: Option<Track<Vec3f>>,
+    pub rotation: Option<Track<Quaternion>>,
+    pub translation: Option<Track<Vec3f>>
+}
+
+impl TransformAnimation {
+    pub fn at(&self, time: f64) -> Result<Transform, String> {
+        let mut transform: Transform = Transform::IDENTITY;
+        if let Some(scale) = &self.scale {
+            transform = Transform::scale(&scale.at(time))?;
+        }
+        if let Some(rotation) = &self.rotation {
+            transform = transform.then(&Transform::rotate_quaternion(&rotation.at(time)));
+        }
+        if let Some(translation) = &self.translation {
+            transform = transform.then(&Transform::translate(&translation.at(time)));
+        }
+        Ok(transform)
+    }
+}
+
+
+pub struct AnimatedObject {
+    object: Arc<dyn Hittable>,
+    animation: TransformAnimation,
+    current: Arc<dyn Hittable>
+}
+
+impl AnimatedObject {
+    pub fn new(object: Arc<dyn Hittable>, animation: TransformAnimation) -> Result<Self, String> {
+        let current: Arc<dyn Hittable> = Arc::new(Transformed::new(object.clone(), &animation.at(0.0)?));
+        Ok(Self { object, animation, current })
+    }
+
+    // Placements sampled over the open shutter to blur a moving object.
+    const MOTION_KEYS: usize = 8;
+
+    pub fn current(&self) -> &Arc<dyn Hittable> {
+        // The object placed at the time last set on the animation, starting at time 0.
+        &self.current
+    }
+
+    fn placed(&self, time: f64, frame_duration: f64, shutter: &Shutter) -> Result<Arc<dyn Hittable>, String> {
+        // Ray times are fractions of the frame, so the motion keys map them back to scene time.
+        let exposure: f64 = (shutter.close() - shutter.open()) * frame_duration;
+        let start: Transform = self.animation.at(time + shutter.open() * frame_duration)?;
+        if exposure <= 0.0 {
+            return Ok(Arc::new(Transformed::new(self.object.clone(), &start)));
+        }
+
+        let mut keyframes: Vec<Keyframe<Transform>> = vec![Keyframe { time: shutter.open(), value: start }];
+        for i in 1..=Self::MOTION_KEYS {
+            let fraction: f64 = i as f64 / Self::MOTION_KEYS as f64;
+            let value: Transform = self.animation.at(time + shutter.open() * frame_duration + fraction * exposure)?;
+            keyframes.push(Keyframe { time: shutter.open() + fraction * (shutter.close() - shutter.open()), value });
+        }
+        if keyframes.iter().all(|keyframe| keyframe.value == start) {
+            return Ok(Arc::new(Transformed::new(self.object.clone(), &start)));
+        }
+        Ok(Arc::new(MotionTransformed::new(self.object.clone(), keyframes)?))
+    }
+}
+
+
+#[derive(Default)]
+pub struct CameraAnimation {
+    pub lookfrom: Option<Track<Point3f>>,
+    pub lookat: Option<Track<Point3f>>,
+    pub vertical_fov: Option<Track<f64>>,
+    pub focus_dist: Option<Track<f64>>
+}
+
+impl CameraAnimation {
+    pub fn is_empty(&self) -> bool {
+        self.lookfrom.is_none() && self.lookat.is_none() && self.vertical_fov.is_none() && self.focus_dist.is_none()
+    }
+
+    pub fn apply(&self, cam: &mut Camera, time: f64) {
+        if let Some(lookfrom) = &self.lookfrom {
+            cam.lookfrom = lookfrom.at(time);
+        }
+        if let Some(lookat) = &self.lookat {
+            cam.lookat = lookat.at(time);
+        }
+        if let Some(vertical_fov) = &self.vertical_fov {
+            cam.vertical_fov = vertical_fov.at(time);
+        }
+        if let Some(focus_dist) = &self.focus_dist {
+            cam.focus_dist = focus_dist.at(time);
+        }
+        cam.initialize();
+    }
+}
+
+
+// Everything that moves in a scene. The scene itself holds the current placement of each animated
+// object, which set_time swaps for the next one.
+#[derive(Default)]
+pub struct Animation {
+    pub camera: CameraAnimation,
+    objects: Vec<AnimatedObject>
+}
+
+impl Animation {
+    pub fn add(&mut self, object: AnimatedObject) {
+        self.objects.push(object);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.camera.is_empty() && self.objects.is_empty()
+    }
+
+    pub fn set_time(&mut self, time: f64, frame_duration: f64, shutter: &Shutter) -> Result<Replacements, String> {
+        // Places every animated object for the frame starting at time, blurred over the part of it
+        // the shutter is open. The blur covers the same scene time whatever the frame rate.
+        let mut replacements: Replacements = Replacements { objects: HashMap::new() };
+        for animated in &mut self.objects {
+            let next: Arc<dyn Hittable> = animated.placed(time, frame_duration, shutter)?;
+            let previous: Arc<dyn Hittable> = std::mem::replace(&mut animated.current, next.clone());
+            replacements.objects.insert(Replacements::key(&previous), next);
+        }
+        Ok(replacements)
+    }
+}
+
+
+// Maps the previous placements of animated objects to their new ones, to update any list or BVH
+// holding them.
+pub struct Replacements {
+    objects: HashMap<usize, Arc<dyn Hittable>>
+}
+
+impl Replacements {
+    fn key(object: &Arc<dyn Hittable>) -> usize {
+        Arc::as_ptr(object) as *const () as usize
+    }
+
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
+    pub fn get(&self, object: &Arc<dyn Hittable>) -> Option<Arc<dyn Hittable>> {
+        self.objects.get(&Self::key(object)).cloned()
+    }
+
+    pub fn apply(&self, list: &HittableList) -> HittableList {
+        let mut updated: HittableList = HittableList::new();
+        for object in &list.objects {
+            updated.add(self.get(object).unwrap_or_else(|| object.clone()));
+        }
+        updated
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::animation::*;
+    use crate::color::Color;
+    use crate::hittable::HitRecord;
+    use crate::interval::Interval;
+    use crate::material::Lambertian;
+    use crate::ray::Ray;
+    use crate::shutter::ShutterCurve;
+    use crate::sphere::Sphere;
+
+    fn keyframes<T>(values: &[(f64, T)]) -> Vec<Keyframe<T>> where T: Copy {
+        values.iter().map(|&(time, value)| Keyframe { time, value }).collect()
+    }
+
+    #[test]
+    fn tracks() {
+        let values: Vec<Keyframe<f64>> = keyframes(&[(0.0, 0.0), (1.0, 2.0), (3.0, 4.0)]);
+        let linear: Track<f64> = Track::new(values.clone(), Interpolation::Linear).unwrap();
+        assert_eq!(linear.at(-1.0), 0.0);
+        assert_eq!(linear.at(0.5), 1.0);
+        assert_eq!(linear.at(2.0), 3.0);
+        assert_eq!(linear.at(5.0), 4.0);
+
+        let smooth: Track<f64> = Track::new(values.clone(), Interpolation::Smoothstep).unwrap();
+        assert_eq!(smooth.at(0.25), 2.0 * 0.15625);
+        assert_eq!(smooth.at(0.5), 1.0);
+
+        // The spline passes through every keyframe and is smooth across them.
+        let spline: Track<f64> = Track::new(values, Interpolation::Spline).unwrap();
+        for (time, value) in [(0.0, 0.0), (1.0, 2.0), (3.0, 4.0)] {
+            assert!((spline.at(time) - value).abs() < 1e-12);
+        }
+        assert!((spline.at(0.5) - 0.875).abs() < 1e-12);
+        let slope_before: f64 = (spline.at(1.0) - spline.at(1.0 - 1e-6)) / 1e-6;
+        let slope_after: f64 = (spline.at(1.0 + 2e-6) - spline.at(1.0)) / 2e-6;
+        assert!((slope_before - 2.0 * slope_after).abs() < 1e-3);
+
+        assert!(Track::<f64>::new(Vec::new(), Interpolation::Linear).is_err());
+        assert!(Track::new(keyframes(&[(1.0, 0.0), (1.0, 1.0)]), Interpolation::Linear).is_err());
+    }
+
+    #[test]
+    fn animated_object() {
+        let center: Point3f = Point3f::E1;
+        let sphere: Arc<dyn Hittable> = Arc::new(Sphere::new_stationary(&center, 0.5, Arc::new(Lambertian::from_color(&Color::ONE))));
+        let animation: TransformAnimation = TransformAnimation {
+            rotation: Some(Track::new(keyframes(&[
+                (0.0, Quaternion::IDENTITY), (2.0, Quaternion::from_axis_angle(&Vec3f::E2, 90.0))
+            ]), Interpolation::Linear).unwrap()),
+            translation: Some(Track::new(keyframes(&[(0.0, Vec3f::E1 * 5.0)]), Interpolation::Linear).unwrap()),
+            ..TransformAnimation::default()
+        };
+        let mut scene: Animation = Animation::default();
+        scene.add(AnimatedObject::new(sphere.clone(), animation).unwrap());
+        let start: Arc<dyn Hittable> = scene.objects[0].current().clone();
+        assert!((start.bounding_box().centroid() - Vec3f::E1 * 6.0).length() < 1e-9);
+
+        let list: HittableList = HittableList::from_object(start.clone());
+        let instant: Shutter = Shutter::new(0.0, 0.0, ShutterCurve::Box).unwrap();
+        let replacements: Replacements = scene.set_time(1.0, 0.5, &instant).unwrap();
+        assert_eq!(replacements.len(), 1);
+        assert!(replacements.get(&sphere).is_none());
+        let moved: HittableList = replacements.apply(&list);
+        assert!(Arc::ptr_eq(&moved.objects[0], scene.objects[0].current()));
+        let expected: Point3f = Point3f::new(5.0 + 0.5_f64.sqrt(), 0.0, -(0.5_f64.sqrt()));
+        assert!((moved.bounding_box().centroid() - expected).length() < 1e-9);
+
+        let transform: Transform = scene.objects[0].animation.at(1.0).unwrap();
+        assert!((transform.vector(&Vec3f::E1) - Vec3f::new(1.0, 0.0, -1.0) / 2.0_f64.sqrt()).length() < 1e-12);
+
+        // With the shutter open for the second half of a 0.5s frame, the sphere turns from 1.25s to
+        // 1.5s. Rays at the end of the frame see the second placement.
+        let half_open: Shutter = Shutter::new(0.5, 1.0, ShutterCurve::Box).unwrap();
+        let replacements: Replacements = scene.set_time(1.0, 0.5, &half_open).unwrap();
+        let blurred: Arc<dyn Hittable> = replacements.apply(&moved).objects[0].clone();
+        let opened: Point3f = scene.objects[0].animation.at(1.25).unwrap().point(&center);
+        let closed: Point3f = scene.objects[0].animation.at(1.5).unwrap().point(&center);
+        let ray: Ray = Ray::with_time(&(closed + Vec3f::E2 * 5.0), &-Vec3f::E2, 1.0);
+        let rec: HitRecord = blurred.hit(&ray, &Interval::new(0.001, f64::INFINITY)).unwrap();
+        assert!((rec.t - 4.5).abs() < 1e-9);
+        assert!(blurred.bounding_box().z.max >= opened.component(Axis::Z) + 0.5);
+        assert!(blurred.bounding_box().z.min <= closed.component(Axis::Z) - 0.5);
+    }
+}
@@ -1,19 +1,129 @@
 use std::cmp::Ordering;
 use std::sync::Arc;
 use std::fmt::{Display, Formatter};
+use std::time::{Duration, Instant};
+
+use clap::ValueEnum;
+use log::debug;
+use rayon::prelude::*;
 
 use crate::aabb::AABB;
 use crate::hittable::{HitRecord, Hittable};
 use crate::hittable_list::HittableList;
 use crate::interval::Interval;
 use crate::ray::Ray;
-use crate::vec3::Axis;
+use crate::vec3::{Axis, Vec3f};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum SplitMethod {
+    /// Split at the median object along the longest axis
+    Median,
+    /// Binned surface area heuristic
+    Sah,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct BVHConfig {
+    pub split_method: SplitMethod,
+    // Relative costs of visiting an interior node and of intersecting one object, as used by the
+    // surface area heuristic.
+    pub traversal_cost: f64,
+    pub intersection_cost: f64,
+    pub max_leaf_size: usize,
+    pub bin_count: usize,
+    // A refitted tree is worth rebuilding once its SAH cost exceeds this multiple of the cost it
+    // was built with.
+    pub rebuild_cost_ratio: f64,
+}
+
+impl Default for BVHConfig {
+    fn default() -> Self {
+        Self::sah()
+    }
+}
+
+impl BVHConfig {
+    pub fn median() -> Self {
+        // One object per leaf, as the original builder did.
+        Self {
+            split_method: SplitMethod::Median, traversal_cost: 0.125, intersection_cost: 1.0,
+            max_leaf_size: 1, bin_count: 12, rebuild_cost_ratio: 1.5
+        }
+    }
+
+    pub fn sah() -> Self {
+        Self {
+            split_method: SplitMethod::Sah, traversal_cost: 0.125, intersection_cost: 1.0,
+            max_leaf_size: 4, bin_count: 12, rebuild_cost_ratio: 1.5
+        }
+    }
+
+    pub fn from_method(split_method: SplitMethod) -> Self {
+        match split_method {
+            SplitMethod::Median => Self::median(),
+            SplitMethod::Sah => Self::sah(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct BVHStats {
+    pub node_count: usize,
+    pub leaf_count: usize,
+    pub max_depth: usize,
+    // Expected cost of a ray through the tree under the surface area heuristic, relative to the
+    // costs in the configuration it was built with.
+    pub sah_cost: f64,
+}
 
+impl Display for BVHStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} nodes, {} leaves, depth {}, SAH cost {:.3}", self.node_count, self.leaf_count, self.max_depth, self.sah_cost)
+    }
+}
+
+impl BVHStats {
+    fn add_leaf(&mut self, depth: usize) {
+        self.leaf_count += 1;
+        self.max_depth = self.max_depth.max(depth);
+    }
+
+    fn add_node(&mut self) {
+        self.node_count += 1;
+    }
+}
+
+
+// Deepest allowed leaf, which bounds the traversal stack. Subtrees that would go deeper become
+// leaves, however many objects they hold.
+const MAX_DEPTH: usize = 64;
+
+// Subtrees over fewer objects than this are built on the current thread, as are their bounds and
+// bins; larger ones are split across the rayon thread pool.
+const PARALLEL_THRESHOLD: usize = 4096;
+
+#[derive(Clone, Copy, Debug)]
+struct LinearNode {
+    bounding_box: AABB,
+    // Leaves hold objects[offset..offset + object_count]. Interior nodes have an object count of
+    // 0; their first child is the next node and offset is the index of the second one.
+    offset: u32,
+    object_count: u32,
+    // Axis along which the children are the furthest apart, and whether the second one lies
+    // further along it, to visit the nearer child first.
+    axis: Axis,
+    second_is_higher: bool
+}
+
+// A bounding volume hierarchy stored as one array of nodes in depth-first order, with the objects
+// reordered so that each leaf refers to a contiguous range of them.
 #[derive(Clone)]
 pub struct BVHNode {
-    left: Arc<dyn Hittable>,
-    right: Arc<dyn Hittable>,
-    bounding_box: AABB
+    nodes: Vec<LinearNode>,
+    objects: Vec<Arc<dyn Hittable>>,
+    bounding_box: AABB,
+    config: BVHConfig,
+    built_sah_cost: f64
 }
 
 impl Display for BVHNode {
@@ -24,54 +134,251 @@ impl Display for BVHNode {
 
 impl BVHNode {
     pub fn from_slice(objects: &mut [Arc<dyn Hittable>]) -> Self {
-        let mut bounding_box: AABB = AABB::EMPTY;
-        for object in &mut *objects {
-            bounding_box = AABB::from_bounding_box(&bounding_box, object.bounding_box());
-        }
-
-        let object_span: usize = objects.len();
-
-        let left: Arc<dyn Hittable>;
-        let right: Arc<dyn Hittable>;
-        if object_span == 1 {
-            left = objects[0].clone();
-            right = objects[0].clone();
-        } 
-        else if object_span == 2 {
-            if Self::box_compare(&objects[0], &objects[1], bounding_box.longest_axis()) == Ordering::Less {
-                left = objects[0].clone();
-                right = objects[1].clone();
-            } 
+        Self::build(objects, &BVHConfig::default()).0
+    }
+
+    pub fn from_hittable_list(list: &mut HittableList) -> Self {
+        Self::from_slice(&mut list.objects)
+    }
+
+    pub fn build(objects: &mut [Arc<dyn Hittable>], config: &BVHConfig) -> (Self, BVHStats) {
+        let now: Instant = Instant::now();
+        let builder: Builder = Builder::detached(config, objects, 0, 0);
+
+        let mut stats: BVHStats = builder.stats;
+        let mut bvh: Self = Self {
+            bounding_box: builder.nodes[0].bounding_box,
+            nodes: builder.nodes,
+            objects: objects.to_vec(),
+            config: *config,
+            built_sah_cost: 0.0
+        };
+        stats.sah_cost = bvh.sah_cost();
+        bvh.built_sah_cost = stats.sah_cost;
+
+        let elapsed: Duration = now.elapsed();
+        debug!("Built {:?} BVH over {} objects in {:.2?}: {}", config.split_method, objects.len(), elapsed, stats);
+        (bvh, stats)
+    }
+
+    pub fn objects(&self) -> &[Arc<dyn Hittable>] {
+        // In tree order, which is not the order the BVH was built from.
+        &self.objects
+    }
+
+    pub fn update_objects<F>(&mut self, mut update: F) -> usize
+    where F: FnMut(&Arc<dyn Hittable>) -> Option<Arc<dyn Hittable>> {
+        // Replaces every object for which update returns a new one, such as the next frame of an
+        // animated object, then refits the tree around them. Returns the number of replaced objects.
+        let mut replaced: usize = 0;
+        for object in &mut self.objects {
+            if let Some(new_object) = update(object) {
+                *object = new_object;
+                replaced += 1;
+            }
+        }
+        if replaced > 0 {
+            self.refit();
+        }
+        replaced
+    }
+
+    pub fn refit(&mut self) {
+        // Recomputes the bounding boxes bottom-up for the objects' current bounds, keeping the tree
+        // topology. Children always come after their parent in the node array, so a reverse pass
+        // sees them first. Without objects, the only node is an empty leaf with nothing to refit.
+        if self.objects.is_empty() {
+            return;
+        }
+        for index in (0..self.nodes.len()).rev() {
+            let node: LinearNode = self.nodes[index];
+            self.nodes[index] = if node.object_count > 0 {
+                let start: usize = node.offset as usize;
+                LinearNode { bounding_box: Self::enclosing_box(&self.objects[start..start + node.object_count as usize]), ..node }
+            }
             else {
-                left = objects[1].clone();
-                right = objects[0].clone();  
+                let (left, right) = (&self.nodes[index + 1].bounding_box, &self.nodes[node.offset as usize].bounding_box);
+                let (axis, second_is_higher) = Self::child_order(left, right);
+                LinearNode { bounding_box: AABB::from_bounding_box(left, right), axis, second_is_higher, ..node }
+            };
+        }
+        self.bounding_box = self.nodes[0].bounding_box;
+    }
+
+    pub fn sah_cost(&self) -> f64 {
+        // BVHStats::sah_cost for the current bounding boxes. Every ray enters the unbounded boxes
+        // above an infinite plane, however the tree is built, so only the bounded nodes are
+        // measured, relative to the box around them.
+        let mut bounded_box: AABB = AABB::EMPTY;
+        let mut cost: f64 = 0.0;
+        for node in &self.nodes {
+            let area: f64 = node.bounding_box.surface_area();
+            if !area.is_finite() {
+                continue;
             }
-        } 
-        else {
-            let mid: usize = object_span / 2;
-            let obj_slice = &mut objects[..];   
-            obj_slice.select_nth_unstable_by(mid, 
-                |a, b| {
-                    Self::box_compare(a, b, bounding_box.longest_axis())
-                }
-            );
+            bounded_box = AABB::from_bounding_box(&bounded_box, &node.bounding_box);
+            cost += match node.object_count {
+                0 => self.config.traversal_cost * area,
+                count => self.config.intersection_cost * area * count as f64
+            };
+        }
+        let bounded_area: f64 = bounded_box.surface_area();
+        if bounded_area > 0.0 && bounded_area.is_finite() { cost / bounded_area } else { 0.0 }
+    }
 
-            left = Arc::new(BVHNode::from_slice(&mut objects[..mid]));
-            right = Arc::new(BVHNode::from_slice(&mut objects[mid..]));
+    pub fn needs_rebuild(&self) -> bool {
+        // Refitting keeps boxes tight around their objects, but objects that moved apart leave
+        // large, overlapping boxes behind. The SAH cost measures how much traversal suffers.
+        self.sah_cost() > self.config.rebuild_cost_ratio * self.built_sah_cost
+    }
+
+    pub fn rebuild(&mut self) -> BVHStats {
+        let config: BVHConfig = self.config;
+        let (bvh, stats) = Self::build(&mut self.objects, &config);
+        *self = bvh;
+        stats
+    }
+
+    fn child_order(left: &AABB, right: &AABB) -> (Axis, bool) {
+        let separation = right.centroid() - left.centroid();
+        let axis: Axis = *Axis::iterator().max_by(
+            |&&a, &&b| separation.component(a).abs().total_cmp(&separation.component(b).abs())
+        ).unwrap();
+        (axis, separation.component(axis) >= 0.0)
+    }
+
+    fn enclosing_box(objects: &[Arc<dyn Hittable>]) -> AABB {
+        let union = |a: AABB, b: &AABB| AABB::from_bounding_box(&a, b);
+        if objects.len() < PARALLEL_THRESHOLD {
+            return objects.iter().fold(AABB::EMPTY, |bounding_box, object| union(bounding_box, object.bounding_box()));
         }
+        objects.par_iter().map(|object| *object.bounding_box()).reduce(|| AABB::EMPTY, |a, b| union(a, &b))
+    }
 
-        let bounding_box: AABB = AABB::from_bounding_box(left.bounding_box(), right.bounding_box());
-        Self { left, right, bounding_box }
+    fn split(objects: &mut [Arc<dyn Hittable>], bounding_box: &AABB, config: &BVHConfig, allow_leaf: bool) -> Option<usize> {
+        // Reorders the objects so that the two children are objects[..mid] and objects[mid..], and
+        // returns mid; None when the objects should stay together in a leaf.
+        let fits_leaf: bool = allow_leaf && objects.len() <= config.max_leaf_size;
+        match config.split_method {
+            SplitMethod::Median if fits_leaf => None,
+            SplitMethod::Median => Some(Self::median_split(objects, bounding_box)),
+            SplitMethod::Sah => match Self::sah_split(objects, bounding_box, config) {
+                Some((mid, cost)) if !fits_leaf || cost < config.intersection_cost * objects.len() as f64 => Some(mid),
+                // Degenerate or unbounded objects can leave the heuristic without a usable split.
+                None if !fits_leaf => Some(Self::median_split(objects, bounding_box)),
+                _ => None
+            }
+        }
     }
 
-    pub fn from_hittable_list(list: &mut HittableList) -> Self {
-        Self::from_slice(&mut list.objects)
+    fn median_split(objects: &mut [Arc<dyn Hittable>], bounding_box: &AABB) -> usize {
+        let mid: usize = objects.len() / 2;
+        let axis: Axis = bounding_box.longest_axis();
+        objects.select_nth_unstable_by(mid, |a, b| Self::box_compare(a, b, axis));
+        mid
+    }
+
+    fn centroid_bounds(objects: &[Arc<dyn Hittable>]) -> (Axis, Interval) {
+        // Bounds of the object centroids along the axis where they are the most spread out.
+        let add_centroid = |mut bounds: [Interval; 3], object: &Arc<dyn Hittable>| {
+            let centroid = object.bounding_box().centroid();
+            for &axis in Axis::iterator() {
+                let interval: &mut Interval = &mut bounds[axis as usize];
+                *interval = Interval::new(interval.min.min(centroid.component(axis)), interval.max.max(centroid.component(axis)));
+            }
+            bounds
+        };
+        let merge = |a: [Interval; 3], b: [Interval; 3]| [0, 1, 2].map(|i| Interval::from_interval(&a[i], &b[i]));
+
+        let bounds: [Interval; 3] = if objects.len() < PARALLEL_THRESHOLD {
+            objects.iter().fold([Interval::EMPTY; 3], add_centroid)
+        }
+        else {
+            objects.par_iter().fold(|| [Interval::EMPTY; 3], add_centroid).reduce(|| [Interval::EMPTY; 3], merge)
+        };
+        let axis: Axis = *Axis::iterator().max_by(
+            |&&a, &&b| bounds[a as usize].size().total_cmp(&bounds[b as usize].size())
+        ).unwrap();
+        (axis, bounds[axis as usize])
+    }
+
+    #[inline]
+    fn bin_index(object: &Arc<dyn Hittable>, axis: Axis, bounds: &Interval, bin_count: usize) -> usize {
+        let centroid: f64 = object.bounding_box().centroid().component(axis);
+        let index: f64 = (centroid - bounds.min) / bounds.size() * bin_count as f64;
+        (index.max(0.0) as usize).min(bin_count - 1)
+    }
+
+    fn sah_split(objects: &mut [Arc<dyn Hittable>], bounding_box: &AABB, config: &BVHConfig) -> Option<(usize, f64)> {
+        // Bins the objects by centroid along one axis, finds the bin boundary with the lowest
+        // expected cost and partitions the objects there. Returns the partition point and its cost.
+        let (axis, bounds) = Self::centroid_bounds(objects);
+        let bin_count: usize = config.bin_count.max(2);
+        if !(bounds.size() > 0.0 && bounds.size().is_finite()) {
+            return None;
+        }
+
+        let add_object = |mut bins: Vec<(AABB, usize)>, object: &Arc<dyn Hittable>| {
+            let bin: &mut (AABB, usize) = &mut bins[Self::bin_index(object, axis, &bounds, bin_count)];
+            bin.0 = AABB::from_bounding_box(&bin.0, object.bounding_box());
+            bin.1 += 1;
+            bins
+        };
+        let merge = |a: Vec<(AABB, usize)>, b: Vec<(AABB, usize)>| a.iter().zip(&b).map(
+            |((box_a, count_a), (box_b, count_b))| (AABB::from_bounding_box(box_a, box_b), count_a + count_b)
+        ).collect::<Vec<(AABB, usize)>>();
+
+        let empty_bins = || vec![(AABB::EMPTY, 0); bin_count];
+        let bins: Vec<(AABB, usize)> = if objects.len() < PARALLEL_THRESHOLD {
+            objects.iter().fold(empty_bins(), add_object)
+        }
+        else {
+            objects.par_iter().fold(empty_bins, add_object).reduce(empty_bins, merge)
+        };
+
+        // Sweep from the right to get the area and count of every suffix of bins, then from the
+        // left to evaluate each boundary.
+        let mut right_side: Vec<(f64, usize)> = vec![(0.0, 0); bin_count];
+        let (mut right_box, mut right_count) = (AABB::EMPTY, 0);
+        for boundary in (1..bin_count).rev() {
+            right_box = AABB::from_bounding_box(&right_box, &bins[boundary].0);
+            right_count += bins[boundary].1;
+            right_side[boundary] = (right_box.surface_area(), right_count);
+        }
+
+        let node_area: f64 = bounding_box.surface_area();
+        let (mut left_box, mut left_count) = (AABB::EMPTY, 0);
+        let mut best: Option<(usize, f64)> = None;
+        for boundary in 1..bin_count {
+            left_box = AABB::from_bounding_box(&left_box, &bins[boundary - 1].0);
+            left_count += bins[boundary - 1].1;
+            let (right_area, right_count) = right_side[boundary];
+            if left_count == 0 || right_count == 0 {
+                continue;
+            }
+
+            let cost: f64 = config.traversal_cost + config.intersection_cost
+                * (left_box.surface_area() * left_count as f64 + right_area * right_count as f64) / node_area;
+            if cost.is_finite() && best.is_none_or(|(_, best_cost)| cost < best_cost) {
+                best = Some((boundary, cost));
+            }
+        }
+        let (boundary, cost) = best?;
+
+        let mut mid: usize = 0;
+        for i in 0..objects.len() {
+            if Self::bin_index(&objects[i], axis, &bounds, bin_count) < boundary {
+                objects.swap(i, mid);
+                mid += 1;
+            }
+        }
+        Some((mid, cost))
     }
 
     fn box_compare(a: &Arc<dyn Hittable>, b: &Arc<dyn Hittable>, axis: Axis) -> Ordering {
         let a_axis_interval: Interval = a.bounding_box().axis_interval(axis);
         let b_axis_interval: Interval = b.bounding_box().axis_interval(axis);
-        
+
         if a_axis_interval.min < b_axis_interval.min {
             return Ordering::Less;
         }
@@ -82,23 +389,305 @@ impl BVHNode {
     }
 }
 
+struct Builder<'a> {
+    config: &'a BVHConfig,
+    nodes: Vec<LinearNode>,
+    stats: BVHStats
+}
+
+impl<'a> Builder<'a> {
+    fn detached(config: &'a BVHConfig, objects: &mut [Arc<dyn Hittable>], offset: usize, depth: usize) -> Self {
+        // Builds a subtree into its own node array, to be appended to its parent's afterwards.
+        let mut builder: Self = Self { config, nodes: Vec::with_capacity(2 * objects.len()), stats: BVHStats::default() };
+        builder.subtree(objects, offset, depth);
+        builder
+    }
+
+    fn append(&mut self, other: Builder) -> usize {
+        // Moves the nodes of a detached subtree to the end of this one and returns the index of its
+        // root. Interior nodes refer to their second child by index, which moves along with them.
+        let base: usize = self.nodes.len();
+        self.nodes.extend(other.nodes.into_iter().map(
+            |mut node| {
+                if node.object_count == 0 {
+                    node.offset += base as u32;
+                }
+                node
+            }
+        ));
+        self.stats.node_count += other.stats.node_count;
+        self.stats.leaf_count += other.stats.leaf_count;
+        self.stats.max_depth = self.stats.max_depth.max(other.stats.max_depth);
+        base
+    }
+
+    fn subtree(&mut self, objects: &mut [Arc<dyn Hittable>], offset: usize, depth: usize) -> usize {
+        // Appends the subtree over objects, which start at offset in the whole object array, and
+        // returns the index of its root node.
+        let bounding_box: AABB = BVHNode::enclosing_box(objects);
+        let index: usize = self.nodes.len();
+        self.nodes.push(LinearNode { bounding_box, offset: offset as u32, object_count: objects.len() as u32, axis: Axis::X, second_is_higher: true });
+
+        let split: Option<usize> = if objects.len() == 1 || depth + 1 >= MAX_DEPTH {
+            None
+        }
+        else {
+            BVHNode::split(objects, &bounding_box, self.config, true)
+        };
+
+        match split {
+            None => self.stats.add_leaf(depth),
+            Some(mid) => {
+                let parallel: bool = objects.len() >= PARALLEL_THRESHOLD;
+                let (left_objects, right_objects) = objects.split_at_mut(mid);
+                let (left, right) = if parallel {
+                    let config: &BVHConfig = self.config;
+                    let (left_builder, right_builder) = rayon::join(
+                        || Builder::detached(config, left_objects, offset, depth + 1),
+                        || Builder::detached(config, right_objects, offset + mid, depth + 1)
+                    );
+                    (self.append(left_builder), self.append(right_builder))
+                }
+                else {
+                    (self.subtree(left_objects, offset, depth + 1), self.subtree(right_objects, offset + mid, depth + 1))
+                };
+
+                let (axis, second_is_higher) = BVHNode::child_order(&self.nodes[left].bounding_box, &self.nodes[right].bounding_box);
+                self.nodes[index] = LinearNode { bounding_box, offset: right as u32, object_count: 0, axis, second_is_higher };
+                self.stats.add_node();
+            }
+        }
+        index
+    }
+}
+
 impl Hittable for BVHNode {
     fn hit(&self, ray: &Ray, ray_t: &Interval) -> Option<HitRecord> {
-        if !self.bounding_box.hit(ray, ray_t) {
-            return None
+        // The root of an empty tree has no objects, but isn't an interior node either.
+        if self.objects.is_empty() {
+            return None;
         }
+        let inverse_direction: Vec3f = Vec3f::new(
+            1.0 / ray.direction().component(Axis::X),
+            1.0 / ray.direction().component(Axis::Y),
+            1.0 / ray.direction().component(Axis::Z)
+        );
+
+        let mut closest: Option<HitRecord> = None;
+        let mut ray_max: f64 = ray_t.max;
+        let mut stack: [usize; MAX_DEPTH] = [0; MAX_DEPTH];
+        let mut stack_size: usize = 0;
+        let mut current: usize = 0;
+        loop {
+            let node: &LinearNode = &self.nodes[current];
+            if node.bounding_box.hit_inverse(ray.origin(), &inverse_direction, &Interval::new(ray_t.min, ray_max)) {
+                if node.object_count > 0 {
+                    let start: usize = node.offset as usize;
+                    for object in &self.objects[start..start + node.object_count as usize] {
+                        if let Some(rec) = object.hit(ray, &Interval::new(ray_t.min, ray_max)) {
+                            ray_max = rec.t;
+                            closest = Some(rec);
+                        }
+                    }
+                }
+                else {
+                    // Visit the child nearer to the ray origin first and come back for the other.
+                    let (near, far) = if (inverse_direction.component(node.axis) < 0.0) == node.second_is_higher {
+                        (node.offset as usize, current + 1)
+                    }
+                    else {
+                        (current + 1, node.offset as usize)
+                    };
+                    stack[stack_size] = far;
+                    stack_size += 1;
+                    current = near;
+                    continue;
+                }
+            }
 
-        let mut right_ray_max: f64 = ray_t.max;
-        let hit_left: Option<HitRecord> = self.left.hit(ray, ray_t);
-        if let Some(ref rec) = hit_left {
-            right_ray_max = rec.t
+            if stack_size == 0 {
+                break;
+            }
+            stack_size -= 1;
+            current = stack[stack_size];
         }
-        let hit_right: Option<HitRecord>  = self.right.hit(ray, &Interval::new(ray_t.min, right_ray_max));
-        
-        hit_right.or(hit_left)
+        closest
     }
 
     fn bounding_box(&self) -> &AABB {
         &self.bounding_box
     }
-}
\ No newline at end of file
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::bvh_node::*;
+    use crate::color::Color;
+    use crate::material::Lambertian;
+    use crate::plane::Plane;
+    use crate::sphere::Sphere;
+    use crate::vec3::{Point3f, Vec3f};
+
+    fn spheres() -> HittableList {
+        // A dense cluster of small spheres next to a few large ones, which a median split handles poorly.
+        let mat = Arc::new(Lambertian::from_color(&Color::ONE));
+        let mut list: HittableList = HittableList::new();
+        for i in 0..200 {
+            let center: Point3f = Point3f::new((i % 10) as f64 * 0.3, (i / 10 % 5) as f64 * 0.3, (i / 50) as f64 * 0.3);
+            list.add(Arc::new(Sphere::new_stationary(&center, 0.1, mat.clone())));
+        }
+        for i in 0..4 {
+            list.add(Arc::new(Sphere::new_stationary(&Point3f::new(20.0 + 10.0 * i as f64, 0.0, 0.0), 4.0, mat.clone())));
+        }
+        list
+    }
+
+    #[test]
+    fn sah_build() {
+        let reference: HittableList = spheres();
+        let (median, median_stats) = BVHNode::build(&mut spheres().objects, &BVHConfig::median());
+        let (sah, sah_stats) = BVHNode::build(&mut spheres().objects, &BVHConfig::sah());
+
+        assert_eq!(median_stats.leaf_count, 204);
+        assert_eq!(median_stats.node_count, 203);
+        assert_eq!(median.nodes.len(), 407);
+        assert!(sah_stats.sah_cost < median_stats.sah_cost, "{} >= {}", sah_stats, median_stats);
+
+        // Expensive traversal makes leaves with several objects worthwhile.
+        let config: BVHConfig = BVHConfig { traversal_cost: 4.0, max_leaf_size: 8, ..BVHConfig::sah() };
+        let (grouped, grouped_stats) = BVHNode::build(&mut spheres().objects, &config);
+        assert!(grouped_stats.leaf_count < 204 / 2, "{}", grouped_stats);
+        assert_eq!(grouped_stats.node_count + 1, grouped_stats.leaf_count);
+
+        let ray_t: Interval = Interval::new(0.001, f64::INFINITY);
+        for i in 0..200 {
+            // Half of the rays come from the other side, so the far child is visited first.
+            let (origin, direction) = if i % 2 == 0 {
+                (Point3f::new(-5.0, 0.01 * i as f64 - 0.5, 0.015 * i as f64 - 1.0), Vec3f::new(1.0, 0.01, 0.005))
+            }
+            else {
+                (Point3f::new(60.0, 0.01 * i as f64 - 0.5, 0.015 * i as f64 - 1.0), Vec3f::new(-1.0, -0.01, 0.005))
+            };
+            let ray: Ray = Ray::new(&origin, &direction);
+            let expected: Option<f64> = reference.hit(&ray, &ray_t).map(|rec| rec.t);
+            assert_eq!(median.hit(&ray, &ray_t).map(|rec| rec.t), expected);
+            assert_eq!(sah.hit(&ray, &ray_t).map(|rec| rec.t), expected);
+            assert_eq!(grouped.hit(&ray, &ray_t).map(|rec| rec.t), expected);
+        }
+    }
+
+    #[test]
+    fn parallel_build() {
+        // Enough spheres for the top of the tree to be built in parallel.
+        let mat = Arc::new(Lambertian::from_color(&Color::ONE));
+        let mut objects: Vec<Arc<dyn Hittable>> = (0..3 * PARALLEL_THRESHOLD).map(
+            |_| Arc::new(Sphere::new_stationary(&(50.0 * Vec3f::random()), 0.2, mat.clone())) as Arc<dyn Hittable>
+        ).collect();
+        let (bvh, stats) = BVHNode::build(&mut objects, &BVHConfig::sah());
+        assert_eq!(bvh.nodes.len(), stats.node_count + stats.leaf_count);
+
+        // Every object is in exactly one leaf, and every child lies inside its parent.
+        let mut covered: Vec<bool> = vec![false; bvh.objects.len()];
+        for (index, node) in bvh.nodes.iter().enumerate() {
+            if node.object_count > 0 {
+                for covered in &mut covered[node.offset as usize..(node.offset + node.object_count) as usize] {
+                    assert!(!*covered);
+                    *covered = true;
+                }
+                continue;
+            }
+            for child in [index + 1, node.offset as usize] {
+                let child_box: &AABB = &bvh.nodes[child].bounding_box;
+                assert_eq!(AABB::from_bounding_box(&node.bounding_box, child_box), node.bounding_box);
+            }
+        }
+        assert!(covered.iter().all(|&covered| covered));
+    }
+
+    #[test]
+    fn refit() {
+        let reference: HittableList = spheres();
+        let mut bvh: BVHNode = BVHNode::build(&mut reference.objects.clone(), &BVHConfig::sah()).0;
+        let mat = Arc::new(Lambertian::from_color(&Color::ONE));
+        let moved = |offset: Vec3f| {
+            let mat = mat.clone();
+            move |object: &Arc<dyn Hittable>| {
+                let center: Point3f = object.bounding_box().centroid();
+                let radius: f64 = 0.5 * object.bounding_box().x.size();
+                Some(Arc::new(Sphere::new_stationary(&(center + offset), radius, mat.clone())) as Arc<dyn Hittable>)
+            }
+        };
+
+        // A small shift of everything keeps the tree as good as it was.
+        assert_eq!(bvh.update_objects(moved(Vec3f::new(0.0, 1.0, 0.0))), 204);
+        assert!(!bvh.needs_rebuild());
+        let ray_t: Interval = Interval::new(0.001, f64::INFINITY);
+        let ray: Ray = Ray::new(&Point3f::new(0.3, 1.3, 5.0), &-Vec3f::E3);
+        assert_eq!(bvh.hit(&ray, &ray_t).map(|rec| rec.t), reference.hit(&Ray::new(&Point3f::new(0.3, 0.3, 5.0), &-Vec3f::E3), &ray_t).map(|rec| rec.t));
+
+        // Scattering the small spheres makes the old groups overlap, so the tree should be rebuilt.
+        let mut scattered: usize = 0;
+        bvh.update_objects(|object| {
+            scattered += 1;
+            moved(Vec3f::new(37.0 * (scattered % 7) as f64, 0.0, 23.0 * (scattered % 5) as f64))(object)
+        });
+        assert!(bvh.needs_rebuild(), "{} vs {}", bvh.sah_cost(), bvh.built_sah_cost);
+        let expected: Vec<Option<f64>> = (0..50).map(|i| {
+            let ray: Ray = Ray::new(&Point3f::new(0.7 * i as f64 + 0.05, 1.1, 200.0), &-Vec3f::E3);
+            bvh.objects().iter().filter_map(|object| object.hit(&ray, &ray_t).map(|rec| rec.t)).min_by(f64::total_cmp)
+        }).collect();
+        let stats: BVHStats = bvh.rebuild();
+        assert!(!bvh.needs_rebuild());
+        assert!((bvh.sah_cost() - stats.sah_cost).abs() < 1e-9);
+        for (i, expected) in expected.into_iter().enumerate() {
+            let ray: Ray = Ray::new(&Point3f::new(0.7 * i as f64 + 0.05, 1.1, 200.0), &-Vec3f::E3);
+            assert_eq!(bvh.hit(&ray, &ray_t).map(|rec| rec.t), expected);
+        }
+    }
+
+    #[test]
+    fn refit_with_plane() {
+        // An infinite ground plane leaves the cost of the bounded part measurable, so scattering
+        // the spheres still calls for a rebuild.
+        let mut objects: Vec<Arc<dyn Hittable>> = spheres().objects;
+        let mat = Arc::new(Lambertian::from_color(&Color::ONE));
+        objects.push(Arc::new(Plane::new(&Point3f::new(0.0, -5.0, 0.0), &Vec3f::E1, &Vec3f::E3, mat.clone())));
+        let (mut bvh, stats) = BVHNode::build(&mut objects, &BVHConfig::default());
+        assert!(stats.sah_cost.is_finite() && stats.sah_cost > 0.0, "{}", stats);
+        assert!(!bvh.needs_rebuild());
+
+        let mut scattered: usize = 0;
+        bvh.update_objects(|object| {
+            if !object.bounding_box().surface_area().is_finite() {
+                return None;
+            }
+            scattered += 1;
+            let center: Point3f = object.bounding_box().centroid() + Vec3f::new(37.0 * (scattered % 7) as f64, 0.0, 23.0 * (scattered % 5) as f64);
+            Some(Arc::new(Sphere::new_stationary(&center, 0.5 * object.bounding_box().x.size(), mat.clone())) as Arc<dyn Hittable>)
+        });
+        assert!(bvh.needs_rebuild(), "{} vs {}", bvh.sah_cost(), bvh.built_sah_cost);
+        bvh.rebuild();
+        assert!(!bvh.needs_rebuild());
+    }
+
+    #[test]
+    fn single_object() {
+        let mut objects: Vec<Arc<dyn Hittable>> = vec![
+            Arc::new(Sphere::new_stationary(&Point3f::ZERO, 1.0, Arc::new(Lambertian::from_color(&Color::ONE))))
+        ];
+        let (bvh, stats) = BVHNode::build(&mut objects, &BVHConfig::sah());
+        assert_eq!((bvh.nodes.len(), stats.node_count, stats.leaf_count, stats.max_depth), (1, 0, 1, 0));
+
+        let ray_t: Interval = Interval::new(0.001, f64::INFINITY);
+        assert_eq!(bvh.hit(&Ray::new(&Point3f::new(0.0, 0.0, 5.0), &-Vec3f::E3), &ray_t).map(|rec| rec.t), Some(4.0));
+        assert!(bvh.hit(&Ray::new(&Point3f::new(0.0, 2.0, 5.0), &-Vec3f::E3), &ray_t).is_none());
+    }
+
+    #[test]
+    fn no_objects() {
+        let (mut bvh, _) = BVHNode::build(&mut [], &BVHConfig::sah());
+        bvh.refit();
+        assert!(bvh.hit(&Ray::new(&Point3f::ZERO, &-Vec3f::E3), &Interval::new(0.001, f64::INFINITY)).is_none());
+    }
+}
@@ -1,26 +1,26 @@
+use core::f64;
 use std::cmp::max;
-use std::io::{BufWriter, Write};
-use std::fs::File;
-use std::path::Path;
-use std::sync::Arc;
 
 use log::info;
 use rayon::prelude::*;
 
-use crate::color::{Color, write_color};
+use crate::color::Color;
+use crate::film::{Film, Splat};
 use crate::hittable::Hittable;
 use crate::hittable_list::HittableList;
-use crate::interval::Interval;
+use crate::integrator::{Integrator, Scene};
 use crate::utilities;
 use crate::vec3::{Axis, Point3f, Vec3f};
-use crate::pdf::{HittablePDF, MixturePDF, PDF};
 use crate::ray::Ray;
+use crate::sampler::{Sampler, SamplerMethod};
+use crate::shutter::Shutter;
 
 pub struct Camera {
     pub aspect_ratio: f64,          // Ratio of image width over height
     pub image_width: u32,           // Rendered image width in pixel count
     pub samples_per_pixel: u32,     // Count of random samples for each pixel
     pub max_depth: u32,             // Maximum number of ray bounces into scene
+    pub russian_roulette_depth: u32, // Bounces before dim paths may be ended at random
     pub background: Color,          // Scene background color
 
     pub vertical_fov: f64,          // Vertical view angle (field of view)
@@ -29,20 +29,25 @@ pub struct Camera {
     pub vup: Vec3f,                 // Camera-relative "up" direction
     pub defocus_angle: f64,         // Variation angle of rays through each pixel
     pub focus_dist: f64,            // Distance from camera lookfrom point to plane of perfect focus
+    pub shutter: Shutter,           // Times within the frame the shutter is open, and how far
+    pub seed: u64,                  // Seed of the random numbers drawn for each pixel sample
+    pub sampler: SamplerMethod,     // How those random numbers are spread over each pixel's samples
 
     image_height: u32,              // Rendered image height
     pixel_samples_scale: f64,       // Color scale factor for a sum of pixel samples
-    sqrt_spp: u32,                  // Square root of number of samples per pixel
-    recip_sqrt_spp: f64,            // 1 / sqrt_spp
     center: Point3f,                // Camera center
     pixel00_loc: Point3f,           // Location of pixel (0, 0)
     pixel_delta_u: Vec3f,           // Offset to pixel to the right
     pixel_delta_v: Vec3f,           // Offset to pixel below
     defocus_disk_u: Vec3f,          // Defocus disk horizontal radius
-    defocus_disk_v: Vec3f           // Defocus disk vertical radius
+    defocus_disk_v: Vec3f,          // Defocus disk vertical radius
+    w: Vec3f                        // Unit vector pointing back from the view direction
 }
 
 impl Camera {
+    // Scanlines rendered between adding up splats, which bounds the splats held at once.
+    const SPLAT_BATCH_SCANLINES: u32 = 16;
+
     #[allow(clippy::too_many_arguments)]
     pub fn new(
         aspect_ratio: f64, 
@@ -57,156 +62,244 @@ impl Camera {
         defocus_angle: f64,
         focus_dist: f64
     ) -> Self {
-        let image_height: u32 = max((image_width as f64 / aspect_ratio) as u32, 1);
-        let sqrt_spp: u32 = f64::sqrt(samples_per_pixel as f64) as u32;
-        let recip_sqrt_spp: f64 = 1.0 / (sqrt_spp as f64);
-        let pixel_samples_scale: f64 = 1.0 / ((sqrt_spp * sqrt_spp) as f64);
-        let center: Point3f = *lookfrom;
+        let mut cam: Self = Self { 
+            aspect_ratio, image_width, samples_per_pixel, max_depth, russian_roulette_depth: 3,
+            background: *background, vertical_fov, 
+            lookfrom: *lookfrom, lookat: *lookat, vup: *vup,
+            defocus_angle, focus_dist, shutter: Shutter::default(), seed: 0,
+            sampler: SamplerMethod::default(), image_height: 0, pixel_samples_scale: 0.0, 
+            center: Point3f::ZERO, pixel00_loc: Point3f::ZERO, 
+            pixel_delta_u: Vec3f::ZERO, pixel_delta_v: Vec3f::ZERO,
+            defocus_disk_u: Vec3f::ZERO, defocus_disk_v: Vec3f::ZERO, w: Vec3f::ZERO
+        };
+        cam.initialize();
+        cam
+    }
+
+    pub fn initialize(&mut self) {
+        // Recompute the derived viewport state from the public camera settings. Call this after
+        // changing any public field of an existing camera.
+        self.image_height = max((self.image_width as f64 / self.aspect_ratio) as u32, 1);
+        self.pixel_samples_scale = 1.0 / (self.samples_per_pixel.max(1) as f64);
+        self.center = self.lookfrom;
 
         // Determine viewport dimensions.
-        let theta: f64 = utilities::degrees_to_radians(vertical_fov);
+        let theta: f64 = utilities::degrees_to_radians(self.vertical_fov);
         let h: f64 = f64::tan(theta / 2.0);
-        let viewport_height: f64 = 2.0 * h * focus_dist;
-        let viewport_width: f64 = viewport_height * (image_width as f64 / image_height as f64);
+        let viewport_height: f64 = 2.0 * h * self.focus_dist;
+        let viewport_width: f64 = viewport_height * (self.image_width as f64 / self.image_height as f64);
 
         // Calculate the u,v,w unit basis vectors for the camera coordinate frame.
-        let w: Vec3f = Vec3f::unit_vector(&(lookfrom - lookat));
-        let u: Vec3f = Vec3f::unit_vector(&Vec3f::cross(vup, &w));
+        let w: Vec3f = Vec3f::unit_vector(&(self.lookfrom - self.lookat));
+        let u: Vec3f = Vec3f::unit_vector(&Vec3f::cross(&self.vup, &w));
         let v: Vec3f = Vec3f::cross(&w, &u);
+        self.w = w;
 
         // Calculate the vectors across the horizontal and down the vertical viewport edges.
         let viewport_u: Vec3f = viewport_width * u;      // Vector across viewport horizontal edge
         let viewport_v: Vec3f = viewport_height * -v;    // Vector down viewport vertical edge
 
         // Calculate the horizontal and vertical delta vectors from pixel to pixel.
-        let pixel_delta_u: Vec3f = viewport_u / image_width as f64;
-        let pixel_delta_v: Vec3f = viewport_v / image_height as f64;
+        self.pixel_delta_u = viewport_u / self.image_width as f64;
+        self.pixel_delta_v = viewport_v / self.image_height as f64;
 
         // Calculate the location of the upper left pixel.
-        let viewport_upper_left: Vec3f = center - (focus_dist * w) - viewport_u / 2.0 - viewport_v / 2.0;
-        let pixel00_loc: Point3f = viewport_upper_left + 0.5 * (pixel_delta_u + pixel_delta_v);
+        let viewport_upper_left: Vec3f = self.center - (self.focus_dist * w) - viewport_u / 2.0 - viewport_v / 2.0;
+        self.pixel00_loc = viewport_upper_left + 0.5 * (self.pixel_delta_u + self.pixel_delta_v);
 
-        let defocus_radius: f64 = focus_dist * f64::tan(utilities::degrees_to_radians(defocus_angle / 2.0));
-        let defocus_disk_u: Vec3f = u * defocus_radius;
-        let defocus_disk_v: Vec3f = v * defocus_radius;
+        let defocus_radius: f64 = self.focus_dist * f64::tan(utilities::degrees_to_radians(self.defocus_angle / 2.0));
+        self.defocus_disk_u = u * defocus_radius;
+        self.defocus_disk_v = v * defocus_radius;
+    }
 
-        Self { 
-            aspect_ratio, image_width, samples_per_pixel, max_depth, 
-            background: *background, vertical_fov, 
-            lookfrom: *lookfrom, lookat: *lookat, vup: *vup,
-            defocus_angle, focus_dist,
-            image_height, pixel_samples_scale, sqrt_spp, recip_sqrt_spp, 
-            center, pixel00_loc, pixel_delta_u, pixel_delta_v,
-            defocus_disk_u, defocus_disk_v
+    pub fn render_to_buffer(&self, world: &dyn Hittable, lights: &HittableList, integrator: &dyn Integrator) -> Film {
+        // Returns the linear radiance of every pixel; gamma and quantization are left to the
+        // image writer. Pixels render in parallel, but each sample reseeds the thread's random
+        // numbers from the camera seed, pixel and sample, and takes the rest of its numbers from
+        // the pixel's own sampler, so the image is the same whatever the thread count. Light that
+        // samples splat onto other pixels is added in pixel order after each batch of scanlines,
+        // for the same reason. Integrators that render the whole image themselves take over here.
+        info!("Generating image");
+        let scene: &Scene = &Scene { world, lights, background: self.background, camera: self };
+        if let Some(film) = integrator.render(scene) {
+            return film;
+        }
+        let mut pixels: Vec<Color> = Vec::with_capacity((self.image_width * self.image_height) as usize);
+        let mut splatted: Vec<Color> = vec![Color::ZERO; (self.image_width * self.image_height) as usize];
+        for batch_start in (0..self.image_height).step_by(Self::SPLAT_BATCH_SCANLINES as usize) {
+            let batch_end: u32 = (batch_start + Self::SPLAT_BATCH_SCANLINES).min(self.image_height);
+            let batch: Vec<(Color, Vec<Splat>)> = (batch_start..batch_end).into_par_iter().flat_map(
+                |j: u32| {
+                    info!("Scanline: {}", j);
+                    (0..self.image_width).into_par_iter().map(move |i: u32| self.render_pixel(i, j, scene, integrator))
+                }
+            ).collect();
+
+            for (pixel_color, splats) in batch {
+                pixels.push(pixel_color);
+                for splat in splats {
+                    splatted[(splat.pixel.1 * self.image_width + splat.pixel.0) as usize] += splat.color;
+                }
+            }
         }
+
+        let pixels: Vec<Color> = pixels.iter().zip(&splatted).map(
+            |(pixel_color, splat_color)| pixel_color + self.pixel_samples_scale * splat_color
+        ).collect();
+        Film::from_pixels(self.image_width, self.image_height, pixels).expect("one color per pixel")
     }
 
-    pub fn render(&self, world: &HittableList, lights: &HittableList, output_filepath: &Path) {
-        let file: File = File::create(output_filepath).unwrap(); 
-        let mut writer: BufWriter<File> = BufWriter::new(file);
+    fn render_pixel(&self, i: u32, j: u32, scene: &Scene, integrator: &dyn Integrator) -> (Color, Vec<Splat>) {
+        let mut sampler: Box<dyn Sampler> = self.pixel_sampler();
+        let mut pixel_color: Color = Color::ZERO;
+        let mut splats: Vec<Splat> = Vec::new();
+        for sample in 0..self.samples_per_pixel.max(1) {
+            let r: Ray = self.start_sample(i, j, sample, sampler.as_mut());
+            pixel_color += integrator.sample(&r, scene, sampler.as_mut(), &mut splats);
+        }
 
-        info!("Generating image");
-        writeln!(writer, "P3").unwrap();
-        writeln!(writer, "{} {}", self.image_width, self.image_height).unwrap();
-        writeln!(writer, "255").unwrap();
-    
-        let pixels = (0..self.image_height).into_par_iter().map(
-            |j: u32| {
-                info!("Scanline: {}", j);
-                (0..self.image_width).into_par_iter().map(
-                    |i: u32| {
-                        let mut pixel_color: Color = Color::ZERO;
-                        pixel_color += (0..self.sqrt_spp).into_par_iter().map(
-                            |s_j: u32| {
-                                (0..self.sqrt_spp).into_par_iter().map(
-                                    |s_i: u32| {
-                                        let r: Ray = self.get_ray(i, j, s_i, s_j);
-                                        self.ray_color(&r, self.max_depth, world, lights)
-                                    }
-                                ).sum::<Color>()
-                            }
-                        ).sum::<Color>();
-        
-                        write_color(self.pixel_samples_scale * pixel_color)
-                    }
-                ).collect::<Vec<String>>().join("")
-            }
-        ).collect::<Vec<String>>().join("");
+        (self.pixel_samples_scale * pixel_color, splats)
+    }
 
-        writeln!(writer, "{}", pixels).unwrap();
-        writer.flush().unwrap();
+    #[inline]
+    pub fn image_height(&self) -> u32 {
+        self.image_height
     }
 
-    fn get_ray(&self, i: u32, j: u32, s_i: u32, s_j: u32) -> Ray {
-        // Construct a camera ray originating from the defocus disk and directed at a randomly
-        // sampled point around the pixel location i, j for stratified sample square s_i, s_j.
-        let offset: Vec3f = self.sample_square_stratified(s_i, s_j);
-        let pixel_sample: Vec3f = self.pixel00_loc
-                            + (((i as f64) + offset.component(Axis::X)) * self.pixel_delta_u)
-                            + (((j as f64) + offset.component(Axis::Y)) * self.pixel_delta_v);
+    pub fn pixel_sampler(&self) -> Box<dyn Sampler> {
+        self.sampler.sampler(self.samples_per_pixel, self.seed, (self.image_width, self.image_height))
+    }
 
-        let ray_origin: Point3f = if self.defocus_angle <= 0.0 {
-            self.center
+    pub fn start_sample(&self, i: u32, j: u32, sample: u32, sampler: &mut dyn Sampler) -> Ray {
+        // Reseeds the thread's random numbers and the sampler for sample `sample` of pixel i, j,
+        // and returns its camera ray.
+        let pixel: u64 = j as u64 * self.image_width as u64 + i as u64;
+        utilities::seed(utilities::hash(&[self.seed, pixel, sample as u64]));
+        sampler.start_pixel_sample((i, j), sample);
+        self.get_ray(i, j, sampler)
+    }
+
+    pub fn sample_lens(&self, u: (f64, f64)) -> (Point3f, f64) {
+        // A point on the lens, and its density over the lens area. A pinhole has no area, and
+        // its density is taken as one.
+        if self.defocus_angle <= 0.0 {
+            return (self.center, 1.0);
         }
-        else {
-            self.defocus_disk_sample()
-        };
+        (self.defocus_disk_sample(u), 1.0 / self.lens_area())
+    }
 
-        let ray_direction: Vec3f = pixel_sample - ray_origin;
-        let ray_time = utilities::random();
+    pub fn importance(&self, ray: &Ray) -> Option<((u32, u32), f64)> {
+        // The pixel a ray from the lens passes through, and the ray's importance: the share of the
+        // image a unit of radiance arriving back along it makes up, over the lens and film areas.
+        // Camera rays are drawn in proportion to it, which is what lets light traced from the
+        // other end be added to the same image.
+        let cos_theta: f64 = Vec3f::dot(&Vec3f::unit_vector(ray.direction()), &self.view_direction());
+        if cos_theta <= 0.0 {
+            return None;
+        }
 
-        Ray::with_time(&ray_origin, &ray_direction, ray_time)
-    }
+        // Where the ray crosses the plane of focus, in pixels from the top left corner.
+        let focus_point: Point3f = ray.at(self.focus_dist / Vec3f::dot(ray.direction(), &self.view_direction()));
+        let corner_offset: Vec3f = focus_point - (self.pixel00_loc - 0.5 * (self.pixel_delta_u + self.pixel_delta_v));
+        let x: f64 = Vec3f::dot(&corner_offset, &self.pixel_delta_u) / self.pixel_delta_u.length_squared();
+        let y: f64 = Vec3f::dot(&corner_offset, &self.pixel_delta_v) / self.pixel_delta_v.length_squared();
+        if !(0.0..self.image_width as f64).contains(&x) || !(0.0..self.image_height as f64).contains(&y) {
+            return None;
+        }
 
-    fn sample_square_stratified(&self, s_i: u32, s_j: u32) -> Vec3f {
-        // Returns the vector to a random point in the square sub-pixel specified by grid
-        // indices s_i and s_j, for an idealized unit square pixel [-.5,-.5] to [+.5,+.5].
-        let px: f64 = ((s_i as f64 + utilities::random()) * self.recip_sqrt_spp) - 0.5;
-        let py: f64 = ((s_j as f64 + utilities::random()) * self.recip_sqrt_spp) - 0.5;
+        let importance: f64 = self.focus_dist * self.focus_dist / (self.lens_area() * self.film_area() * cos_theta.powi(4));
+        Some(((x as u32, y as u32), importance))
+    }
 
-        Vec3f::new(px, py, 0.0)  
+    pub fn pdf_direction(&self, ray: &Ray) -> f64 {
+        // Density over solid angle with which camera rays from the ray's lens point take its
+        // direction, for a uniformly chosen point on the image.
+        match self.importance(ray) {
+            Some(_) => {
+                let cos_theta: f64 = Vec3f::dot(&Vec3f::unit_vector(ray.direction()), &self.view_direction());
+                self.focus_dist * self.focus_dist / (self.film_area() * cos_theta.powi(3))
+            },
+            None => 0.0
+        }
     }
 
-    fn defocus_disk_sample(&self) -> Point3f {
-        // Returns a random point in the camera defocus disk.
-        let p: Vec3f = Vec3f::random_in_unit_disk();
-        self.center + (p.component(Axis::X) * self.defocus_disk_u) + (p.component(Axis::Y) * self.defocus_disk_v)
+    #[inline]
+    pub fn view_direction(&self) -> Vec3f {
+        -self.w
     }
 
-    fn ray_color(&self, ray: &Ray, depth: u32, world: &HittableList, lights: &HittableList) -> Color {        
-        if depth == 0 {
-            return Color::ZERO;
+    fn lens_area(&self) -> f64 {
+        if self.defocus_angle <= 0.0 {
+            return 1.0;
         }
+        f64::consts::PI * self.defocus_disk_u.length_squared()
+    }
 
-        if let Some(rec) = world.hit(ray, &Interval::new(0.001, f64::INFINITY)) {
-            let color_from_emission: Color = rec.mat.emitted(ray, &rec, rec.uv, &rec.point);
-            if let Some(scatter_rec) = rec.mat.scatter(ray, &rec) {
-                if scatter_rec.skip_pdf {
-                    return scatter_rec.attenuation * self.ray_color(&scatter_rec.skip_pdf_ray, depth-1, world, lights);
-                }
+    fn film_area(&self) -> f64 {
+        // The image's area on the plane of focus.
+        self.pixel_delta_u.length() * self.pixel_delta_v.length() * (self.image_width * self.image_height) as f64
+    }
 
-                let selected_pdf: Arc<dyn PDF>;
-                if lights.objects.len() > 0 {
-                    let light_pdf_ptr: Arc<HittablePDF>  = Arc::new(HittablePDF::new(Arc::new(lights.clone()), &rec.point));
-                    selected_pdf = Arc::new(MixturePDF::new(light_pdf_ptr, scatter_rec.pdf_ptr));
-                }
-                else {
-                    selected_pdf = scatter_rec.pdf_ptr;
-                }
+    fn get_ray(&self, i: u32, j: u32, sampler: &mut dyn Sampler) -> Ray {
+        // Construct a camera ray originating from the defocus disk and directed at a sampled
+        // point around the pixel location i, j. The lens sample is drawn even without defocus so
+        // the later dimensions line up.
+        let (offset_x, offset_y) = sampler.get_2d();
+        let pixel_sample: Vec3f = self.pixel00_loc
+                            + (((i as f64) + offset_x - 0.5) * self.pixel_delta_u)
+                            + (((j as f64) + offset_y - 0.5) * self.pixel_delta_v);
 
+        let u_lens: (f64, f64) = sampler.get_2d();
+        let ray_origin: Point3f = if self.defocus_angle <= 0.0 {
+            self.center
+        }
+        else {
+            self.defocus_disk_sample(u_lens)
+        };
+
+        let ray_direction: Vec3f = pixel_sample - ray_origin;
+        let ray_time = self.shutter.sample(sampler.get_1d());
+
+        Ray::with_time(&ray_origin, &ray_direction, ray_time)
+    }
 
-                let scattered: Ray = Ray::with_time(&rec.point, &selected_pdf.generate(), ray.time());
-                let pdf_value: f64 = selected_pdf.value(scattered.direction());
+    fn defocus_disk_sample(&self, u: (f64, f64)) -> Point3f {
+        // Returns a sampled point in the camera defocus disk.
+        let p: Vec3f = Vec3f::sample_unit_disk(u);
+        self.center + (p.component(Axis::X) * self.defocus_disk_u) + (p.component(Axis::Y) * self.defocus_disk_v)
+    }
+}
 
-                let scattering_pdf: f64 = rec.mat.scattering_pdf(ray, &rec, &scattered);
+#[cfg(test)]
+mod tests {
+    use crate::bvh_node::{BVHConfig, BVHNode};
+    use crate::camera::*;
+    use crate::integrator::{IntegratorConfig, IntegratorMethod};
+    use crate::scenes;
 
-                let sample_color: Color = self.ray_color(&scattered, depth-1, world, lights);
-                let color_from_scatter: Color = (scatter_rec.attenuation * scattering_pdf * sample_color) / pdf_value;
-                return color_from_emission + color_from_scatter;
+    fn render(seed: u64, threads: usize) -> Vec<Color> {
+        // Builds the scene in the pool too, so the seeded scene builder is tested as well.
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+        pool.install(
+            || {
+                utilities::seed(seed);
+                let (mut scene, lights, mut cam) = scenes::bouncing_spheres();
+                cam.image_width = 24;
+                cam.samples_per_pixel = 4;
+                cam.max_depth = 6;
+                cam.seed = seed;
+                cam.initialize();
+                let (bvh, _) = BVHNode::build(&mut scene.objects, &BVHConfig::sah());
+                let integrator: Box<dyn Integrator> = IntegratorMethod::Path.integrator(&IntegratorConfig::from_camera(&cam));
+                cam.render_to_buffer(&bvh, &lights, integrator.as_ref()).pixels().to_vec()
             }
-            return color_from_emission;
-        }
+        )
+    }
 
-        self.background
+    #[test]
+    fn seeded_render() {
+        let single_threaded: Vec<Color> = render(7, 1);
+        assert_eq!(single_threaded, render(7, 4));
+        assert_ne!(single_threaded, render(8, 4));
     }
-}
\ No newline at end of file
+}
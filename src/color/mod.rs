@@ -11,7 +11,16 @@ pub fn linear_to_gamma(linear_component: f64) -> f64 {
     0.0
 }
 
-pub fn write_color(pixel_color: Color) -> String {
+#[inline]
+pub fn srgb_to_linear(srgb_component: f64) -> f64 {
+    // Decodes an sRGB encoded component in [0,1], as stored in 8-bit color files.
+    if srgb_component <= 0.04045 {
+        return srgb_component / 12.92;
+    }
+    ((srgb_component + 0.055) / 1.055).powf(2.4)
+}
+
+pub fn color_to_bytes(pixel_color: Color) -> [u8; 3] {
     let mut r: f64 = linear_to_gamma(pixel_color.component(Axis::X));
     let mut g: f64 = linear_to_gamma(pixel_color.component(Axis::Y));
     let mut b: f64 = linear_to_gamma(pixel_color.component(Axis::Z));
@@ -32,5 +41,22 @@ pub fn write_color(pixel_color: Color) -> String {
     let gbyte: u8 = (256.0 * intensity.clamp(g)) as u8;
     let bbyte: u8 = (256.0 * intensity.clamp(b)) as u8;
 
+    [rbyte, gbyte, bbyte]
+}
+
+pub fn color_to_linear_f32(pixel_color: Color) -> [f32; 3] {
+    // Keeps the full linear range; only NaN components are replaced with zero.
+    let mut rgb: [f32; 3] = [0.0; 3];
+    for (i, &axis) in Axis::iterator().enumerate() {
+        let component: f64 = pixel_color.component(axis);
+        if !component.is_nan() {
+            rgb[i] = component as f32;
+        }
+    }
+    rgb
+}
+
+pub fn write_color(pixel_color: Color) -> String {
+    let [rbyte, gbyte, bbyte] = color_to_bytes(pixel_color);
     format!("{} {} {}\n", rbyte, gbyte, bbyte)
-}
\ No newline at end of file
+}
@@ -13,17 +13,30 @@ use crate::vec3::Vec3f;
 
 pub struct ConstantMedium {
     boundary: Arc<dyn Hittable>,
+    density: f64,
     neg_inv_density: f64,
     phase_function: Arc<dyn Material>
 }
 
 impl ConstantMedium {
     pub fn from_color(boundary: Arc<dyn Hittable>, density: f64, color: &Color) -> Self {
-        Self { boundary, neg_inv_density: -1.0 / density, phase_function: Arc::new(Isotropic::from_color(color)) }
+        Self { boundary, density, neg_inv_density: -1.0 / density, phase_function: Arc::new(Isotropic::from_color(color)) }
     }
 
     pub fn from_texture(boundary: Arc<dyn Hittable>, density: f64, texture: Arc<dyn Texture>) -> Self {
-        Self { boundary, neg_inv_density: -1.0 / density, phase_function: Arc::new(Isotropic::from_texture(texture)) }
+        Self { boundary, density, neg_inv_density: -1.0 / density, phase_function: Arc::new(Isotropic::from_texture(texture)) }
+    }
+
+    pub fn boundary(&self) -> &Arc<dyn Hittable> {
+        &self.boundary
+    }
+
+    pub fn density(&self) -> f64 {
+        self.density
+    }
+
+    pub fn phase_function(&self) -> &Arc<dyn Material> {
+        &self.phase_function
     }
 }
 
@@ -58,10 +71,12 @@ impl Hittable for ConstantMedium {
                 let rec: HitRecord = HitRecord { 
                     point: ray.at(t), 
                     normal: Vec3f::E1, // Arbitrary 
+                    geometric_normal: Vec3f::E1, // Arbitrary
                     mat: self.phase_function.clone(), 
                     t,
                     uv: (0.0, 0.0), // Arbitrary
-                    front_face: true // Arbitrary
+                    front_face: true, // Arbitrary
+                    face: None
                 };
 
                 return Some(rec);  
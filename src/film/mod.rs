@@ -0,0 +1,121 @@
+use std::path::Path;
+
+use crate::color::Color;
+use crate::image_writer::ImageWriter;
+
+// Light a sample adds to a pixel other than its own, such as a path traced from a light that
+// reaches the camera.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Splat {
+    pub pixel: (u32, u32),
+    pub color: Color
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Film {
+    width: u32,
+    height: u32,
+    pixels: Vec<Color>
+}
+
+impl Film {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self { width, height, pixels: vec![Color::ZERO; (width * height) as usize] }
+    }
+
+    pub fn from_pixels(width: u32, height: u32, pixels: Vec<Color>) -> Result<Self, String> {
+        // Pixels are linear radiance values in row-major order, starting from the top-left pixel.
+        if pixels.len() != (width * height) as usize {
+            return Err(format!("Expected {} pixels for a {}x{} film, got {}", width * height, width, height, pixels.len()));
+        }
+        Ok(Self { width, height, pixels })
+    }
+
+    #[inline]
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    #[inline]
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    #[inline]
+    pub fn pixels(&self) -> &[Color] {
+        &self.pixels
+    }
+
+    #[inline]
+    pub fn pixel(&self, i: u32, j: u32) -> Color {
+        self.pixels[self.index(i, j)]
+    }
+
+    pub fn set_pixel(&mut self, i: u32, j: u32, pixel_color: Color) {
+        let index: usize = self.index(i, j);
+        self.pixels[index] = pixel_color;
+    }
+
+    pub fn map<F>(&self, f: F) -> Film where F: Fn(Color) -> Color {
+        // Returns a new film with f applied to every pixel, e.g. for exposure or tone mapping.
+        Self { width: self.width, height: self.height, pixels: self.pixels.iter().map(|p| f(*p)).collect() }
+    }
+
+    pub fn mean_squared_error(&self, other: &Film) -> Result<f64, String> {
+        if self.width != other.width || self.height != other.height {
+            return Err(format!(
+                "Cannot compare a {}x{} film with a {}x{} film", self.width, self.height, other.width, other.height
+            ));
+        }
+        if self.pixels.is_empty() {
+            return Ok(0.0);
+        }
+
+        let sum_squared_error: f64 = self.pixels.iter().zip(&other.pixels).map(
+            |(a, b)| (a - b).length_squared()
+        ).sum();
+        Ok(sum_squared_error / (3 * self.pixels.len()) as f64)
+    }
+
+    pub fn save(&self, output_filepath: &Path, image_writer: &dyn ImageWriter) -> Result<(), String> {
+        image_writer.write(self, output_filepath)
+    }
+
+    #[inline]
+    fn index(&self, i: u32, j: u32) -> usize {
+        (j * self.width + i) as usize
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::film::*;
+
+    #[test]
+    fn from_pixels() {
+        assert!(Film::from_pixels(2, 2, vec![Color::ZERO; 3]).is_err());
+
+        let film: Film = Film::from_pixels(2, 1, vec![Color::ZERO, Color::ONE]).unwrap();
+        assert_eq!(film.width(), 2);
+        assert_eq!(film.height(), 1);
+        assert_eq!(film.pixel(1, 0), Color::ONE);
+    }
+
+    #[test]
+    fn set_pixel() {
+        let mut film: Film = Film::new(3, 2);
+        film.set_pixel(2, 1, Color::E2);
+        assert_eq!(film.pixel(2, 1), Color::E2);
+        assert_eq!(film.pixels()[5], Color::E2);
+    }
+
+    #[test]
+    fn mean_squared_error() {
+        let a: Film = Film::new(2, 2);
+        let b: Film = a.map(|p| p + Color::ONE);
+        assert_eq!(a.mean_squared_error(&a), Ok(0.0));
+        assert_eq!(a.mean_squared_error(&b), Ok(1.0));
+        assert!(a.mean_squared_error(&Film::new(1, 2)).is_err());
+    }
+}
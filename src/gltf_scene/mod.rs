@@ -0,0 +1,489 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use gltf::texture::WrappingMode;
+use image::{DynamicImage, GrayAlphaImage, GrayImage, ImageBuffer, Rgb, Rgba, RgbaImage, RgbImage};
+use log::warn;
+
+use crate::camera::Camera;
+use crate::color::{self, Color};
+use crate::hittable::Hittable;
+use crate::hittable_list::HittableList;
+use crate::material::{Dielectric, DiffuseLight, Lambertian, Material, Metal};
+use crate::matrix::Matrix4;
+use crate::mesh::TriangleMesh;
+use crate::texture::{Image, Scaled, Texture};
+use crate::transform::{Transform, Transformed};
+use crate::vec3::{Axis, Point3f, Vec3f};
+
+
+pub fn load(filepath: &Path) -> Result<(HittableList, HittableList, Camera), String> {
+    // Loads the default scene of a glTF 2.0 file (.gltf or .glb) with its buffers and images.
+    // Emissive meshes are also returned as lights, and the first perspective camera in the node
+    // hierarchy becomes the scene camera; without one, the camera frames the whole scene.
+    let (document, buffers, images) = gltf::import(filepath)
+        .map_err(|err| format!("{}: {}", filepath.display(), err))?;
+    let base_dir: &Path = filepath.parent().unwrap_or(Path::new(""));
+
+    let mut loader: GltfLoader = GltfLoader::new(&buffers, &images, base_dir);
+    loader.build(&document).map_err(|err| format!("{}: {}", filepath.display(), err))
+}
+
+
+// Object space primitives of one glTF mesh, and whether each of them emits light.
+type Primitives = Vec<(Arc<dyn Hittable>, bool)>;
+
+struct GltfLoader<'a> {
+    buffers: &'a [gltf::buffer::Data],
+    images: &'a [gltf::image::Data],
+    base_dir: &'a Path,
+    decoded_images: HashMap<usize, Arc<Image>>,
+    textures: HashMap<usize, Arc<dyn Texture>>,
+    materials: HashMap<Option<usize>, (Arc<dyn Material>, bool)>,     // Material, and whether it emits
+    meshes: HashMap<usize, Primitives>,
+    scene: HittableList,
+    lights: HittableList,
+    camera: Option<Camera>,
+}
+
+impl<'a> GltfLoader<'a> {
+    fn new(buffers: &'a [gltf::buffer::Data], images: &'a [gltf::image::Data], base_dir: &'a Path) -> Self {
+        Self {
+            buffers, images, base_dir,
+            decoded_images: HashMap::new(),
+            textures: HashMap::new(),
+            materials: HashMap::new(),
+            meshes: HashMap::new(),
+            scene: HittableList::new(),
+            lights: HittableList::new(),
+            camera: None
+        }
+    }
+
+    fn build(&mut self, document: &gltf::Document) -> Result<(HittableList, HittableList, Camera), String> {
+        let gltf_scene: gltf::Scene = document.default_scene().or_else(|| document.scenes().next())
+            .ok_or("no scene")?;
+        for node in gltf_scene.nodes() {
+            self.node(&node, &Matrix4::IDENTITY)?;
+        }
+        if self.scene.objects.is_empty() {
+            return Err("no triangle meshes in the scene".to_string());
+        }
+
+        let mut cam: Camera = match self.camera.take() {
+            Some(cam) => cam,
+            None => self.framing_camera()
+        };
+        // Scenes lit only by emissive meshes get a black background instead of the sky.
+        if !self.lights.objects.is_empty() {
+            cam.background = Color::ZERO;
+        }
+        Ok((std::mem::take(&mut self.scene), std::mem::take(&mut self.lights), cam))
+    }
+
+    fn node(&mut self, node: &gltf::Node, parent_world: &Matrix4) -> Result<(), String> {
+        let local: Matrix4 = Matrix4::from_columns(node.transform().matrix().map(|column| column.map(f64::from)));
+        let world: Matrix4 = parent_world * &local;
+
+        if let Some(mesh) = node.mesh() {
+            self.mesh_instance(&mesh, &world)?;
+        }
+        if let Some(camera) = node.camera() {
+            self.camera(&camera, &world);
+        }
+        for child in node.children() {
+            self.node(&child, &world)?;
+        }
+        Ok(())
+    }
+
+    fn mesh_instance(&mut self, mesh: &gltf::Mesh, world: &Matrix4) -> Result<(), String> {
+        // Meshes stay in object space and are shared by every node that instances them. The
+        // transform also takes care of mirroring: glTF flips the winding order of mirrored meshes,
+        // and the inverse transpose keeps their normals on the same side of the surface.
+        let transform: Transform = match Transform::new(world) {
+            Ok(transform) => transform,
+            Err(_) => {
+                // Nodes scaled down to nothing are a common way to hide them.
+                warn!("skipping mesh #{} with a singular node transform", mesh.index());
+                return Ok(());
+            }
+        };
+        if !self.meshes.contains_key(&mesh.index()) {
+            let primitives: Primitives = self.mesh(mesh)?;
+            self.meshes.insert(mesh.index(), primitives);
+        }
+
+        for (primitive, emissive) in &self.meshes[&mesh.index()] {
+            let instance: Arc<dyn Hittable> = if transform.is_identity() {
+                primitive.clone()
+            }
+            else {
+                Arc::new(Transformed::new(primitive.clone(), &transform))
+            };
+            if *emissive {
+                self.lights.add(instance.clone());
+            }
+            self.scene.add(instance);
+        }
+        Ok(())
+    }
+
+    fn mesh(&mut self, mesh: &gltf::Mesh) -> Result<Primitives, String> {
+        let name: String = mesh.name().map_or_else(|| format!("#{}", mesh.index()), str::to_string);
+        let mut primitives: Primitives = Vec::new();
+
+        for primitive in mesh.primitives() {
+            if primitive.mode() != gltf::mesh::Mode::Triangles {
+                warn!("mesh '{}': skipping primitive with {:?} mode", name, primitive.mode());
+                continue;
+            }
+
+            let reader = primitive.reader(|buffer| self.buffers.get(buffer.index()).map(|data| &data[..]));
+            let positions: Vec<Point3f> = reader.read_positions()
+                .ok_or_else(|| format!("mesh '{}': primitive has no positions", name))?
+                .map(|p| Point3f::new(f64::from(p[0]), f64::from(p[1]), f64::from(p[2])))
+                .collect();
+            let normals: Vec<Vec3f> = reader.read_normals().map_or_else(Vec::new,
+                |normals| normals.map(|n| Vec3f::new(f64::from(n[0]), f64::from(n[1]), f64::from(n[2]))).collect()
+            );
+            // glTF puts the texture origin at the top left, and ours is at the bottom left.
+            let uvs: Vec<(f64, f64)> = reader.read_tex_coords(0).map_or_else(Vec::new,
+                |uvs| uvs.into_f32().map(|uv| (f64::from(uv[0]), 1.0 - f64::from(uv[1]))).collect()
+            );
+            let flat_indices: Vec<u32> = match reader.read_indices() {
+                Some(indices) => indices.into_u32().collect(),
+                None => (0..positions.len() as u32).collect()
+            };
+            let indices: Vec<[u32; 3]> = flat_indices.chunks_exact(3).map(|i| [i[0], i[1], i[2]]).collect();
+
+            let (mat, emissive) = self.material(&primitive.material())?;
+            let triangle_mesh: Arc<dyn Hittable> = Arc::new(
+                TriangleMesh::new(positions, normals, uvs, indices, mat).map_err(|err| format!("mesh '{}': {}", name, err))?
+            );
+            primitives.push((triangle_mesh, emissive));
+        }
+        Ok(primitives)
+    }
+
+    fn material(&mut self, material: &gltf::Material) -> Result<(Arc<dyn Material>, bool), String> {
+        // Metallic-roughness materials are mapped onto the closest of ours:
+        //     emissive                    => DiffuseLight
+        //     transmission factor >= 0.5  => Dielectric with the material's index of refraction
+        //     metallic factor >= 0.5      => Metal with the base color, fuzz from the roughness
+        //     otherwise                   => Lambertian with the base color
+        // The base color is the base color texture times the base color factor.
+        if let Some(cached) = self.materials.get(&material.index()) {
+            return Ok(cached.clone());
+        }
+
+        let pbr: gltf::material::PbrMetallicRoughness = material.pbr_metallic_roughness();
+        let [r, g, b, _alpha] = pbr.base_color_factor().map(f64::from);
+        let base_color: Color = Color::new(r, g, b);
+        let [er, eg, eb] = material.emissive_factor().map(f64::from);
+        let emission: Color = material.emissive_strength().map_or(1.0, f64::from) * Color::new(er, eg, eb);
+
+        let converted: (Arc<dyn Material>, bool) = if !emission.near_zero() {
+            (Arc::new(DiffuseLight::from_color(&emission)), true)
+        }
+        else if material.transmission().is_some_and(|transmission| transmission.transmission_factor() >= 0.5) {
+            (Arc::new(Dielectric::new(material.ior().map_or(1.5, f64::from))), false)
+        }
+        else if pbr.metallic_factor() >= 0.5 {
+            let fuzz: f64 = f64::from(pbr.roughness_factor());
+            match self.base_color_texture(&pbr, &base_color)? {
+                Some(texture) => (Arc::new(Metal::from_texture(texture, fuzz)), false),
+                None => (Arc::new(Metal::new(&base_color, fuzz)), false)
+            }
+        }
+        else {
+            match self.base_color_texture(&pbr, &base_color)? {
+                Some(texture) => (Arc::new(Lambertian::from_texture(texture)), false),
+                None => (Arc::new(Lambertian::from_color(&base_color)), false)
+            }
+        };
+
+        self.materials.insert(material.index(), converted.clone());
+        Ok(converted)
+    }
+
+    fn base_color_texture(&mut self, pbr: &gltf::material::PbrMetallicRoughness, base_color: &Color) -> Result<Option<Arc<dyn Texture>>, String> {
+        // The base color texture, scaled by the base color factor unless that is white.
+        let Some(info) = pbr.base_color_texture() else {
+            return Ok(None);
+        };
+        let texture: Arc<dyn Texture> = self.texture(&info.texture())?;
+        if *base_color == Color::ONE {
+            return Ok(Some(texture));
+        }
+        Ok(Some(Arc::new(Scaled::new(texture, base_color))))
+    }
+
+    fn texture(&mut self, texture: &gltf::Texture) -> Result<Arc<dyn Texture>, String> {
+        // Textures sharing an image differ only in their sampler, so the image is decoded once.
+        if let Some(cached) = self.textures.get(&texture.index()) {
+            return Ok(cached.clone());
+        }
+        let sampler: gltf::texture::Sampler = texture.sampler();
+        let gltf_texture: Arc<dyn Texture> = Arc::new(
+            GltfTexture::new(self.image(&texture.source())?, sampler.wrap_s(), sampler.wrap_t())
+        );
+        self.textures.insert(texture.index(), gltf_texture.clone());
+        Ok(gltf_texture)
+    }
+
+    fn image(&mut self, source: &gltf::Image) -> Result<Arc<Image>, String> {
+        if let Some(cached) = self.decoded_images.get(&source.index()) {
+            return Ok(cached.clone());
+        }
+
+        let data: &gltf::image::Data = self.images.get(source.index())
+            .ok_or_else(|| format!("missing image #{}", source.index()))?;
+        let img: DynamicImage = Self::decoded_image(data)
+            .ok_or_else(|| format!("image #{} does not match its {}x{} size", source.index(), data.width, data.height))?;
+
+        // Remember external image files so the texture can be exported to a scene file.
+        let filepath: Option<PathBuf> = match source.source() {
+            gltf::image::Source::Uri { uri, .. } if !uri.starts_with("data:") => Some(self.base_dir.join(uri)),
+            _ => None
+        };
+
+        let image: Arc<Image> = Arc::new(Image::from_image(img, filepath));
+        self.decoded_images.insert(source.index(), image.clone());
+        Ok(image)
+    }
+
+    fn decoded_image(data: &gltf::image::Data) -> Option<DynamicImage> {
+        use gltf::image::Format;
+        let (width, height, pixels) = (data.width, data.height, data.pixels.clone());
+        let u16_pixels = || pixels.chunks_exact(2).map(|b| u16::from_ne_bytes([b[0], b[1]])).collect::<Vec<u16>>();
+        let f32_pixels = || pixels.chunks_exact(4).map(|b| f32::from_ne_bytes([b[0], b[1], b[2], b[3]])).collect::<Vec<f32>>();
+
+        let img: DynamicImage = match data.format {
+            Format::R8 => DynamicImage::ImageLuma8(GrayImage::from_raw(width, height, pixels)?),
+            Format::R8G8 => DynamicImage::ImageLumaA8(GrayAlphaImage::from_raw(width, height, pixels)?),
+            Format::R8G8B8 => DynamicImage::ImageRgb8(RgbImage::from_raw(width, height, pixels)?),
+            Format::R8G8B8A8 => DynamicImage::ImageRgba8(RgbaImage::from_raw(width, height, pixels)?),
+            Format::R16 => DynamicImage::ImageLuma16(ImageBuffer::from_raw(width, height, u16_pixels())?),
+            Format::R16G16 => DynamicImage::ImageLumaA16(ImageBuffer::from_raw(width, height, u16_pixels())?),
+            Format::R16G16B16 => DynamicImage::ImageRgb16(ImageBuffer::<Rgb<u16>, _>::from_raw(width, height, u16_pixels())?),
+            Format::R16G16B16A16 => DynamicImage::ImageRgba16(ImageBuffer::<Rgba<u16>, _>::from_raw(width, height, u16_pixels())?),
+            Format::R32G32B32FLOAT => DynamicImage::ImageRgb32F(ImageBuffer::from_raw(width, height, f32_pixels())?),
+            Format::R32G32B32A32FLOAT => DynamicImage::ImageRgba32F(ImageBuffer::from_raw(width, height, f32_pixels())?),
+        };
+        Some(img)
+    }
+
+    fn camera(&mut self, camera: &gltf::Camera, world: &Matrix4) {
+        // glTF cameras look down their local -Z axis, with +Y up.
+        if self.camera.is_some() {
+            return;
+        }
+        let gltf::camera::Projection::Perspective(perspective) = camera.projection() else {
+            warn!("skipping orthographic camera #{}", camera.index());
+            return;
+        };
+
+        let lookfrom: Point3f = world.transform_point(&Point3f::ZERO);
+        let lookat: Point3f = lookfrom + Vec3f::unit_vector(&world.transform_vector(&-Vec3f::E3));
+        let vup: Vec3f = world.transform_vector(&Vec3f::E2);
+        let aspect_ratio: f64 = perspective.aspect_ratio().map_or(16.0 / 9.0, f64::from);
+        self.camera = Some(Self::default_camera(aspect_ratio, f64::from(perspective.yfov()).to_degrees(), &lookfrom, &lookat, &vup));
+    }
+
+    fn framing_camera(&self) -> Camera {
+        // Looks down -Z at the center of the scene from far enough away to see all of it.
+        let bounding_box = self.scene.bounding_box();
+        let center: Point3f = Point3f::new(
+            0.5 * (bounding_box.x.min + bounding_box.x.max),
+            0.5 * (bounding_box.y.min + bounding_box.y.max),
+            0.5 * (bounding_box.z.min + bounding_box.z.max)
+        );
+        let radius: f64 = 0.5 * Vec3f::new(bounding_box.x.size(), bounding_box.y.size(), bounding_box.z.size()).length();
+        let vertical_fov: f64 = 40.0;
+        let distance: f64 = radius / f64::sin(0.5 * vertical_fov.to_radians());
+        let lookfrom: Point3f = center + distance * Vec3f::E3;
+        Self::default_camera(16.0 / 9.0, vertical_fov, &lookfrom, &center, &Vec3f::E2)
+    }
+
+    fn default_camera(aspect_ratio: f64, vertical_fov: f64, lookfrom: &Point3f, lookat: &Point3f, vup: &Vec3f) -> Camera {
+        // glTF has no render settings, so these follow the built-in scenes.
+        Camera::new(
+            aspect_ratio, 600, 100, 50, &Color::new(0.70, 0.80, 1.00), vertical_fov,
+            lookfrom, lookat, vup, 0.0, 10.0
+        )
+    }
+}
+
+
+// A glTF base color texture: an sRGB encoded image, repeated or mirrored beyond [0,1] as its
+// sampler asks.
+pub struct GltfTexture {
+    image: Arc<Image>,
+    wrap_s: WrappingMode,
+    wrap_t: WrappingMode
+}
+
+impl fmt::Display for GltfTexture {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "glTF Texture Wrap: {:?}, {:?}; Image: {}", self.wrap_s, self.wrap_t, self.image)
+    }
+}
+
+impl GltfTexture {
+    pub fn new(image: Arc<Image>, wrap_s: WrappingMode, wrap_t: WrappingMode) -> Self {
+        Self { image, wrap_s, wrap_t }
+    }
+
+    pub fn image(&self) -> &Arc<Image> {
+        &self.image
+    }
+
+    fn wrap(coordinate: f64, mode: WrappingMode) -> f64 {
+        // Flipping V at load time keeps this the same, as every mode is symmetric about 1/2.
+        match mode {
+            WrappingMode::ClampToEdge => coordinate,
+            WrappingMode::Repeat => coordinate - coordinate.floor(),
+            WrappingMode::MirroredRepeat => 1.0 - ((coordinate - 2.0 * (0.5 * coordinate).floor()) - 1.0).abs()
+        }
+    }
+}
+
+impl Texture for GltfTexture {
+    fn value(&self, uv: (f64, f64), point: &Point3f) -> Color {
+        let uv: (f64, f64) = (Self::wrap(uv.0, self.wrap_s), Self::wrap(uv.1, self.wrap_t));
+        let srgb: Color = self.image.value(uv, point);
+        Color::new(
+            color::srgb_to_linear(srgb.component(Axis::X)),
+            color::srgb_to_linear(srgb.component(Axis::Y)),
+            color::srgb_to_linear(srgb.component(Axis::Z))
+        )
+    }
+}
+
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::fs;
+
+    use crate::gltf_scene::*;
+    use crate::hittable::HitRecord;
+    use crate::interval::Interval;
+    use crate::ray::Ray;
+
+    // One emissive triangle, instanced by a translated node and by a translated child of a
+    // scaled node, plus a camera one unit up the Z axis.
+    const TRIANGLE_GLTF: &str = r#"{
+        "asset": { "version": "2.0" },
+        "scene": 0,
+        "scenes": [{ "nodes": [0, 1, 3] }],
+        "nodes": [
+            { "mesh": 0, "translation": [0, 0, -5] },
+            { "scale": [2, 2, 2], "children": [2] },
+            { "mesh": 0, "translation": [3, 0, -10] },
+            { "camera": 0, "translation": [0, 0, 1] }
+        ],
+        "cameras": [{ "type": "perspective", "perspective": { "yfov": 0.5, "aspectRatio": 1.5, "znear": 0.1 } }],
+        "meshes": [{ "primitives": [{ "attributes": { "POSITION": 0 }, "indices": 1, "material": 0 }] }],
+        "materials": [{ "emissiveFactor": [1, 1, 1] }],
+        "accessors": [
+            { "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3", "min": [0, 0, 0], "max": [1, 1, 0] },
+            { "bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR" }
+        ],
+        "bufferViews": [
+            { "buffer": 0, "byteOffset": 0, "byteLength": 36 },
+            { "buffer": 0, "byteOffset": 36, "byteLength": 6 }
+        ],
+        "buffers": [{ "uri": "triangle.bin", "byteLength": 44 }]
+    }"#;
+
+    pub(crate) fn write_triangle_gltf(dir: &Path) {
+        // TRIANGLE_GLTF and its buffer, as triangle.gltf in dir.
+        fs::create_dir_all(dir).unwrap();
+        let mut bin: Vec<u8> = [0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0].iter().flat_map(|x| x.to_le_bytes()).collect();
+        bin.extend([0u16, 1, 2].iter().flat_map(|i| i.to_le_bytes()));
+        bin.extend([0, 0]);
+        fs::write(dir.join("triangle.bin"), bin).unwrap();
+        fs::write(dir.join("triangle.gltf"), TRIANGLE_GLTF).unwrap();
+    }
+
+    #[test]
+    fn node_hierarchy() {
+        let dir: PathBuf = std::env::temp_dir().join("raytracing_rs_gltf_node_hierarchy");
+        write_triangle_gltf(&dir);
+
+        let (scene, lights, cam) = load(&dir.join("triangle.gltf")).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(scene.objects.len(), 2);
+        assert_eq!(lights.objects.len(), 2);
+
+        // The first instance is moved 5 units away; the second is scaled along with its offset.
+        let ray_t: Interval = Interval::new(0.001, f64::INFINITY);
+        let rec: HitRecord = scene.hit(&Ray::new(&Point3f::ZERO, &Vec3f::new(0.25, 0.25, -5.0)), &ray_t).unwrap();
+        assert!((rec.t - 1.0).abs() < 1e-12);
+        assert!(rec.front_face);
+        let rec: HitRecord = scene.hit(&Ray::new(&Point3f::ZERO, &Vec3f::new(6.5, 0.5, -20.0)), &ray_t).unwrap();
+        assert!((rec.t - 1.0).abs() < 1e-12);
+        assert!(scene.hit(&Ray::new(&Point3f::ZERO, &Vec3f::new(-0.5, 0.5, -5.0)), &ray_t).is_none());
+
+        assert_eq!(cam.lookfrom, Point3f::new(0.0, 0.0, 1.0));
+        assert_eq!(cam.lookat, Point3f::ZERO);
+        assert_eq!(cam.vup, Vec3f::E2);
+        assert_eq!(cam.aspect_ratio, 1.5);
+        assert!((cam.vertical_fov - 0.5f64.to_degrees()).abs() < 1e-12);
+        assert_eq!(cam.background, Color::ZERO);
+    }
+
+    #[test]
+    fn base_color_texture() {
+        // A triangle textured by a 2x2 image held in its buffer, whose top left pixel alone is
+        // lit. The sampler mirrors U and repeats V, so texture coordinates above 1 land on it.
+        let dir: PathBuf = std::env::temp_dir().join("raytracing_rs_gltf_base_color_texture");
+        fs::create_dir_all(&dir).unwrap();
+        let mut png: Vec<u8> = Vec::new();
+        RgbImage::from_raw(2, 2, vec![188, 188, 188, 0, 0, 0, 0, 0, 0, 0, 0, 0]).unwrap()
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png).unwrap();
+        let mut bin: Vec<u8> = [0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0].iter().flat_map(|x| x.to_le_bytes()).collect();
+        bin.extend([0.0f32, 0.0, 4.0, 0.0, 0.0, 4.0].iter().flat_map(|x| x.to_le_bytes()));
+        bin.extend([0u16, 1, 2].iter().flat_map(|i| i.to_le_bytes()));
+        bin.extend([0, 0]);
+        bin.extend(&png);
+        fs::write(dir.join("textured.bin"), &bin).unwrap();
+        let gltf: String = format!(r#"{{
+            "asset": {{ "version": "2.0" }},
+            "scenes": [{{ "nodes": [0] }}],
+            "nodes": [{{ "mesh": 0 }}],
+            "meshes": [{{ "primitives": [{{ "attributes": {{ "POSITION": 0, "TEXCOORD_0": 1 }}, "indices": 2, "material": 0 }}] }}],
+            "materials": [{{ "pbrMetallicRoughness": {{ "baseColorTexture": {{ "index": 0 }}, "metallicFactor": 0 }} }}],
+            "textures": [{{ "source": 0, "sampler": 0 }}],
+            "samplers": [{{ "wrapS": 33648, "wrapT": 10497 }}],
+            "images": [{{ "bufferView": 3, "mimeType": "image/png" }}],
+            "accessors": [
+                {{ "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3", "min": [0, 0, 0], "max": [1, 1, 0] }},
+                {{ "bufferView": 1, "componentType": 5126, "count": 3, "type": "VEC2" }},
+                {{ "bufferView": 2, "componentType": 5123, "count": 3, "type": "SCALAR" }}
+            ],
+            "bufferViews": [
+                {{ "buffer": 0, "byteOffset": 0, "byteLength": 36 }},
+                {{ "buffer": 0, "byteOffset": 36, "byteLength": 24 }},
+                {{ "buffer": 0, "byteOffset": 60, "byteLength": 6 }},
+                {{ "buffer": 0, "byteOffset": 68, "byteLength": {} }}
+            ],
+            "buffers": [{{ "uri": "textured.bin", "byteLength": {} }}]
+        }}"#, png.len(), bin.len());
+        fs::write(dir.join("textured.gltf"), gltf).unwrap();
+
+        let (scene, _, _) = load(&dir.join("textured.gltf")).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        // The texture coordinates there are (1.8, 1.2): U mirrors to 0.2 and V repeats to 0.2.
+        let ray_t: Interval = Interval::new(0.001, f64::INFINITY);
+        let rec: HitRecord = scene.hit(&Ray::new(&Point3f::new(0.45, 0.3, 1.0), &Vec3f::new(0.0, 0.0, -1.0)), &ray_t).unwrap();
+        let mat: &dyn std::any::Any = rec.mat.as_ref();
+        let albedo: Color = mat.downcast_ref::<Lambertian>().unwrap().texture().value_at(&rec);
+        let linear: f64 = color::srgb_to_linear(188.0 / 255.0);
+        assert!((albedo - Color::new(linear, linear, linear)).length() < 1e-12, "{}", albedo);
+    }
+}
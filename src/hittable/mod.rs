@@ -1,3 +1,4 @@
+use std::any::Any;
 use std::sync::Arc;
 
 use crate::aabb::AABB;
@@ -6,14 +7,24 @@ use crate::material::Material;
 use crate::ray::Ray;
 use crate::vec3::{Point3f, Vec3f};
 
+// The vertices of the mesh face that was hit, with the barycentric weight of each at the hit point,
+// for interpolating per-vertex attributes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FaceHit {
+    pub vertices: [usize; 3],
+    pub barycentric: [f64; 3],
+}
+
 #[derive(Clone)]
 pub struct HitRecord {
     pub point: Point3f,
-    pub normal: Vec3f,
+    pub normal: Vec3f,              // Shading normal, facing against the ray
+    pub geometric_normal: Vec3f,    // True surface normal, on the same side as the shading normal
     pub mat: Arc<dyn Material>,
     pub t: f64,
     pub uv: (f64, f64),
     pub front_face: bool,
+    pub face: Option<FaceHit>,
 }
 
 impl HitRecord {
@@ -26,20 +37,56 @@ impl HitRecord {
             -*outward_normal
         };
 
-        Self { point, normal, mat, t, uv, front_face }
+        Self { point, normal, geometric_normal: normal, mat, t, uv, front_face, face: None }
     }
+
+    pub fn set_shading_normal(&mut self, shading_normal: &Vec3f) {
+        // Replace the shading normal, e.g. with one interpolated from vertex normals, keeping it in
+        // the hemisphere of the geometric normal. Degenerate normals are ignored.
+        if shading_normal.near_zero() {
+            return;
+        }
+        let shading_normal: Vec3f = Vec3f::unit_vector(shading_normal);
+        self.normal = if Vec3f::dot(&shading_normal, &self.geometric_normal) < 0.0 {
+            -shading_normal
+        }
+        else {
+            shading_normal
+        };
+    }
+}
+
+// A point drawn on the surface of an object, e.g. to emit light from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SurfaceSample {
+    pub point: Point3f,
+    pub normal: Vec3f,              // Outward unit normal
+    pub pdf: f64                    // Density over surface area
 }
 
-pub trait Hittable : Send + Sync {
+pub trait Hittable : Any + Send + Sync {
     fn hit(&self, ray: &Ray, ray_t: &Interval) -> Option<HitRecord>;
 
     fn bounding_box(&self) -> &AABB;
 
-    fn pdf_value(&self, _origin: &Point3f, _direction: &Vec3f) -> f64 {
+    fn pdf_value(&self, _origin: &Point3f, _direction: &Vec3f, _time: f64) -> f64 {
         0.0
     }
 
-    fn random(&self, _origin: &Point3f) -> Vec3f {
+    // Direction from origin to a point on the object, drawn from a 2D sample in [0, 1)^2.
+    fn random(&self, _origin: &Point3f, _u: (f64, f64), _time: f64) -> Vec3f {
         Vec3f::E1
     }
+
+    // A point on the object drawn uniformly by area from a 2D sample in [0, 1)^2, or None for
+    // objects without a bounded surface.
+    fn sample_surface(&self, _u: (f64, f64), _time: f64) -> Option<SurfaceSample> {
+        None
+    }
+
+    // Density over surface area with which sample_surface draws the point where a ray from origin
+    // along direction first hits the object.
+    fn surface_pdf(&self, _origin: &Point3f, _direction: &Vec3f, _time: f64) -> f64 {
+        0.0
+    }
 }
\ No newline at end of file
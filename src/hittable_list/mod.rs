@@ -1,11 +1,8 @@
 use std::sync::Arc;
 
-use rand::seq::SliceRandom;
-use rayon::prelude::*;
-
 use crate::aabb::AABB;
 use crate::color::Color;
-use crate::hittable::{HitRecord, Hittable};
+use crate::hittable::{HitRecord, Hittable, SurfaceSample};
 use crate::interval::Interval;
 use crate::material::Lambertian;
 use crate::ray::Ray;
@@ -52,10 +49,12 @@ impl Hittable for HittableList {
         let mut hit_rec: HitRecord = HitRecord {
             point: Point3f::ZERO,
             normal: Vec3f::ZERO,
+            geometric_normal: Vec3f::ZERO,
             mat: Arc::new(Lambertian::from_color(&Color::ZERO)),
             t: 0.0,
             uv: (0.0, 0.0),
-            front_face: false
+            front_face: false,
+            face: None
         };
 
         let mut hit_anything: bool = false;
@@ -79,20 +78,50 @@ impl Hittable for HittableList {
         &self.bounding_box
     }
 
-    fn pdf_value(&self, origin: &Point3f, direction: &Vec3f) -> f64 {
+    fn pdf_value(&self, origin: &Point3f, direction: &Vec3f, time: f64) -> f64 {
         let weight: f64 = 1.0 / self.objects.len() as f64;
 
-        self.objects.clone().into_par_iter().map(
-            |object: Arc<dyn Hittable>| {
-                weight * object.pdf_value(origin, direction)
+        // Summed in order on this thread: a parallel sum would round differently depending on how
+        // the work was split, and could run another pixel's samples while waiting.
+        self.objects.iter().map(
+            |object: &Arc<dyn Hittable>| {
+                weight * object.pdf_value(origin, direction, time)
             }
         ).sum::<f64>()
     }
 
-    fn random(&self, origin: &Point3f) -> Vec3f {
-        if let Some(hit) = self.objects.choose(&mut rand::thread_rng()) {
-            return hit.random(origin);
+    fn random(&self, origin: &Point3f, u: (f64, f64), time: f64) -> Vec3f {
+        // The first number picks an object and is stretched back over [0, 1) for it.
+        if self.objects.is_empty() {
+            return Vec3f::ZERO;
+        }
+        let scaled: f64 = u.0 * self.objects.len() as f64;
+        let index: usize = (scaled as usize).min(self.objects.len() - 1);
+        self.objects[index].random(origin, ((scaled - index as f64).min(1.0 - f64::EPSILON / 2.0), u.1), time)
+    }
+
+    fn sample_surface(&self, u: (f64, f64), time: f64) -> Option<SurfaceSample> {
+        // Picks an object like random, so each object's density is shared out between them.
+        if self.objects.is_empty() {
+            return None;
+        }
+        let scaled: f64 = u.0 * self.objects.len() as f64;
+        let index: usize = (scaled as usize).min(self.objects.len() - 1);
+        let sample: SurfaceSample = self.objects[index].sample_surface(((scaled - index as f64).min(1.0 - f64::EPSILON / 2.0), u.1), time)?;
+        Some(SurfaceSample { pdf: sample.pdf / self.objects.len() as f64, ..sample })
+    }
+
+    fn surface_pdf(&self, origin: &Point3f, direction: &Vec3f, time: f64) -> f64 {
+        // Unlike pdf_value, only the object the ray hits first could have drawn the point.
+        let ray: Ray = Ray::with_time(origin, direction, time);
+        let mut closest_so_far: f64 = f64::INFINITY;
+        let mut closest: Option<&Arc<dyn Hittable>> = None;
+        for object in &self.objects {
+            if let Some(rec) = object.hit(&ray, &Interval::new(0.001, closest_so_far)) {
+                closest_so_far = rec.t;
+                closest = Some(object);
+            }
         }
-        Vec3f::ZERO
+        closest.map_or(0.0, |object| object.surface_pdf(origin, direction, time) / self.objects.len() as f64)
     }
 }
\ No newline at end of file
@@ -0,0 +1,171 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use clap::ValueEnum;
+use image::{ImageFormat, Rgb32FImage, RgbImage};
+
+use crate::color;
+use crate::film::Film;
+
+pub trait ImageWriter {
+    fn write(&self, film: &Film, output_filepath: &Path) -> Result<(), String>;
+}
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    Ppm,
+    Png,
+    Jpeg,
+    Exr,
+    Hdr,
+    Pfm,
+}
+
+impl OutputFormat {
+    pub fn from_extension(filepath: &Path) -> Option<Self> {
+        let extension: String = filepath.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "ppm" => Some(Self::Ppm),
+            "png" => Some(Self::Png),
+            "jpg" | "jpeg" => Some(Self::Jpeg),
+            "exr" => Some(Self::Exr),
+            "hdr" => Some(Self::Hdr),
+            "pfm" => Some(Self::Pfm),
+            _ => None
+        }
+    }
+
+    pub fn writer(&self) -> Box<dyn ImageWriter> {
+        match self {
+            Self::Ppm => Box::new(PPMWriter),
+            Self::Png => Box::new(LDRWriter::new(ImageFormat::Png)),
+            Self::Jpeg => Box::new(LDRWriter::new(ImageFormat::Jpeg)),
+            Self::Exr => Box::new(HDRWriter::new(ImageFormat::OpenExr)),
+            Self::Hdr => Box::new(HDRWriter::new(ImageFormat::Hdr)),
+            Self::Pfm => Box::new(PFMWriter),
+        }
+    }
+}
+
+
+pub struct PPMWriter;
+
+impl ImageWriter for PPMWriter {
+    fn write(&self, film: &Film, output_filepath: &Path) -> Result<(), String> {
+        let file: File = File::create(output_filepath).map_err(|err| err.to_string())?;
+        let mut writer: BufWriter<File> = BufWriter::new(file);
+
+        writeln!(writer, "P3").map_err(|err| err.to_string())?;
+        writeln!(writer, "{} {}", film.width(), film.height()).map_err(|err| err.to_string())?;
+        writeln!(writer, "255").map_err(|err| err.to_string())?;
+        for pixel_color in film.pixels() {
+            write!(writer, "{}", color::write_color(*pixel_color)).map_err(|err| err.to_string())?;
+        }
+        writer.flush().map_err(|err| err.to_string())
+    }
+}
+
+
+pub struct LDRWriter {
+    format: ImageFormat
+}
+
+impl LDRWriter {
+    pub fn new(format: ImageFormat) -> Self {
+        Self { format }
+    }
+}
+
+impl ImageWriter for LDRWriter {
+    fn write(&self, film: &Film, output_filepath: &Path) -> Result<(), String> {
+        // Gamma corrected and quantized to 8 bits per channel.
+        let bytes: Vec<u8> = film.pixels().iter().flat_map(|pixel_color| color::color_to_bytes(*pixel_color)).collect();
+        let img: RgbImage = RgbImage::from_raw(film.width(), film.height(), bytes)
+            .ok_or_else(|| format!("Pixel buffer does not match a {}x{} image", film.width(), film.height()))?;
+        img.save_with_format(output_filepath, self.format).map_err(|err| err.to_string())
+    }
+}
+
+
+pub struct HDRWriter {
+    format: ImageFormat
+}
+
+impl HDRWriter {
+    pub fn new(format: ImageFormat) -> Self {
+        Self { format }
+    }
+}
+
+impl ImageWriter for HDRWriter {
+    fn write(&self, film: &Film, output_filepath: &Path) -> Result<(), String> {
+        // Linear radiance, without gamma or clamping.
+        let floats: Vec<f32> = film.pixels().iter().flat_map(|pixel_color| color::color_to_linear_f32(*pixel_color)).collect();
+        let img: Rgb32FImage = Rgb32FImage::from_raw(film.width(), film.height(), floats)
+            .ok_or_else(|| format!("Pixel buffer does not match a {}x{} image", film.width(), film.height()))?;
+        img.save_with_format(output_filepath, self.format).map_err(|err| err.to_string())
+    }
+}
+
+
+pub struct PFMWriter;
+
+impl ImageWriter for PFMWriter {
+    fn write(&self, film: &Film, output_filepath: &Path) -> Result<(), String> {
+        let file: File = File::create(output_filepath).map_err(|err| err.to_string())?;
+        let mut writer: BufWriter<File> = BufWriter::new(file);
+
+        // A negative scale marks little-endian samples.
+        writeln!(writer, "PF").map_err(|err| err.to_string())?;
+        writeln!(writer, "{} {}", film.width(), film.height()).map_err(|err| err.to_string())?;
+        writeln!(writer, "-1.0").map_err(|err| err.to_string())?;
+
+        // PFM stores scanlines from the bottom of the image to the top.
+        for row in film.pixels().chunks(film.width() as usize).rev() {
+            for pixel_color in row {
+                for value in color::color_to_linear_f32(*pixel_color) {
+                    writer.write_all(&value.to_le_bytes()).map_err(|err| err.to_string())?;
+                }
+            }
+        }
+        writer.flush().map_err(|err| err.to_string())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::color::Color;
+    use crate::image_writer::*;
+
+    #[test]
+    fn from_extension() {
+        assert_eq!(OutputFormat::from_extension(Path::new("out.ppm")), Some(OutputFormat::Ppm));
+        assert_eq!(OutputFormat::from_extension(Path::new("out.PNG")), Some(OutputFormat::Png));
+        assert_eq!(OutputFormat::from_extension(Path::new("out.jpg")), Some(OutputFormat::Jpeg));
+        assert_eq!(OutputFormat::from_extension(Path::new("dir/out.exr")), Some(OutputFormat::Exr));
+        assert_eq!(OutputFormat::from_extension(Path::new("out.hdr")), Some(OutputFormat::Hdr));
+        assert_eq!(OutputFormat::from_extension(Path::new("out.pfm")), Some(OutputFormat::Pfm));
+        assert_eq!(OutputFormat::from_extension(Path::new("out.txt")), None);
+        assert_eq!(OutputFormat::from_extension(Path::new("out")), None);
+    }
+
+    #[test]
+    fn pfm_keeps_radiance() {
+        let filepath = std::env::temp_dir().join("raytracing_rs_pfm_keeps_radiance.pfm");
+        let film: Film = Film::from_pixels(2, 1, vec![Color::new(4.0, 0.5, 0.0), Color::new(f64::NAN, 1.0, 2.0)]).unwrap();
+        PFMWriter.write(&film, &filepath).unwrap();
+
+        let bytes: Vec<u8> = std::fs::read(&filepath).unwrap();
+        std::fs::remove_file(&filepath).unwrap();
+
+        let header: &[u8] = b"PF\n2 1\n-1.0\n";
+        assert_eq!(&bytes[..header.len()], header);
+        let values: Vec<f32> = bytes[header.len()..].chunks(4).map(
+            |b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])
+        ).collect();
+        assert_eq!(values, vec![4.0, 0.5, 0.0, 0.0, 1.0, 2.0]);
+    }
+}
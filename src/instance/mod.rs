@@ -0,0 +1,104 @@
+use std::sync::Arc;
+
+use crate::aabb::AABB;
+use crate::hittable::{Hittable, HitRecord, SurfaceSample};
+use crate::interval::Interval;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::transform::{Transform, Transformed};
+use crate::vec3::{Point3f, Vec3f};
+
+// One placement of a shared prototype, such as a mesh or a BVH of objects. Instances only store a
+// transform and an optional material, so a scene can hold many copies of the same geometry. Put
+// them in a BVHNode to get a two-level hierarchy: the top level over instance bounding boxes, and
+// the bottom level inside the prototype, in its own object space.
+pub struct Instance {
+    transformed: Transformed,
+    material: Option<Arc<dyn Material>>
+}
+
+impl Instance {
+    pub fn new(prototype: Arc<dyn Hittable>, transform: &Transform, material: Option<Arc<dyn Material>>) -> Self {
+        Self { transformed: Transformed::new(prototype, transform), material }
+    }
+
+    pub fn prototype(&self) -> &Arc<dyn Hittable> {
+        self.transformed.object()
+    }
+
+    pub fn transform(&self) -> &Transform {
+        self.transformed.transform()
+    }
+
+    pub fn material(&self) -> Option<&Arc<dyn Material>> {
+        self.material.as_ref()
+    }
+}
+
+impl Hittable for Instance {
+    fn hit(&self, ray: &Ray, ray_t: &Interval) -> Option<HitRecord> {
+        let mut rec: HitRecord = self.transformed.hit(ray, ray_t)?;
+        // The override replaces the material of every surface in the prototype.
+        if let Some(mat) = &self.material {
+            rec.mat = mat.clone();
+        }
+        Some(rec)
+    }
+
+    fn bounding_box(&self) -> &AABB {
+        self.transformed.bounding_box()
+    }
+
+    fn pdf_value(&self, origin: &Point3f, direction: &Vec3f, time: f64) -> f64 {
+        self.transformed.pdf_value(origin, direction, time)
+    }
+
+    fn random(&self, origin: &Point3f, u: (f64, f64), time: f64) -> Vec3f {
+        self.transformed.random(origin, u, time)
+    }
+
+    fn sample_surface(&self, u: (f64, f64), time: f64) -> Option<SurfaceSample> {
+        self.transformed.sample_surface(u, time)
+    }
+
+    fn surface_pdf(&self, origin: &Point3f, direction: &Vec3f, time: f64) -> f64 {
+        self.transformed.surface_pdf(origin, direction, time)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::bvh_node::BVHNode;
+    use crate::color::Color;
+    use crate::hittable_list::HittableList;
+    use crate::instance::*;
+    use crate::material::{Lambertian, Metal};
+    use crate::plane::Quad;
+
+    #[test]
+    fn two_level_bvh() {
+        let white: Arc<dyn Material> = Arc::new(Lambertian::from_color(&Color::ONE));
+        let red: Arc<dyn Material> = Arc::new(Metal::new(&Color::new(1.0, 0.0, 0.0), 0.0));
+        let prototype: Arc<dyn Hittable> = Quad::new_box(&Point3f::ZERO, &Point3f::ONE, white.clone());
+
+        // A 10x10 grid of unit boxes, every other one with its own material.
+        let mut instances: HittableList = HittableList::new();
+        for i in 0..100 {
+            let offset: Vec3f = Vec3f::new(2.0 * (i % 10) as f64, 0.0, -2.0 * (i / 10) as f64);
+            let material: Option<Arc<dyn Material>> = (i % 2 == 1).then(|| red.clone());
+            instances.add(Arc::new(Instance::new(prototype.clone(), &Transform::translate(&offset), material)));
+        }
+        let scene: BVHNode = BVHNode::from_hittable_list(&mut instances);
+        assert_eq!(Arc::strong_count(&prototype), 101);
+
+        let ray_t: Interval = Interval::new(0.001, f64::INFINITY);
+        let rec: HitRecord = scene.hit(&Ray::new(&Point3f::new(4.5, 0.5, 5.0), &-Vec3f::E3), &ray_t).unwrap();
+        assert!((rec.point - Point3f::new(4.5, 0.5, 1.0)).length() < 1e-9);
+        assert!(Arc::ptr_eq(&rec.mat, &white));
+        let rec: HitRecord = scene.hit(&Ray::new(&Point3f::new(6.5, 5.0, -3.5), &-Vec3f::E2), &ray_t).unwrap();
+        assert!((rec.point - Point3f::new(6.5, 1.0, -3.5)).length() < 1e-9);
+        assert!(Arc::ptr_eq(&rec.mat, &red));
+        assert!(scene.hit(&Ray::new(&Point3f::new(1.5, 5.0, 0.5), &-Vec3f::E2), &ray_t).is_none());
+    }
+}
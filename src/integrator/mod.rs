@@ -0,0 +1,1355 @@
+use core::f64;
+use std::sync::Arc;
+
+use clap::ValueEnum;
+use log::info;
+use rayon::prelude::*;
+
+use crate::camera::Camera;
+use crate::color::Color;
+use crate::film::{Film, Splat};
+use crate::hittable::{HitRecord, Hittable, SurfaceSample};
+use crate::hittable_list::HittableList;
+use crate::interval::Interval;
+use crate::material::ScatterRecord;
+use crate::onb::ONB;
+use crate::pdf::{CosinePDF, HittablePDF, MixturePDF, PDF};
+use crate::photon_map::{Photon, PhotonMap};
+use crate::ray::Ray;
+use crate::sampler::{IndependentSampler, Sampler};
+use crate::utilities;
+use crate::vec3::{Axis, Point3f, Vec3f};
+
+// Integrators compute the light arriving along each camera ray; the camera only chooses the rays
+// and averages the results into pixels.
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum IntegratorMethod {
+    /// Path tracing, with a shadow ray to the lights at every diffuse bounce
+    #[default]
+    Path,
+    /// Shadow rays to the lights, mirrors and glass followed, and the background as ambient light
+    Whitted,
+    /// Only the light reaching a surface straight from emitters or the background, including
+    /// through mirrors and glass
+    DirectLighting,
+    /// White where nothing is near a surface to occlude it, darker where something is
+    AmbientOcclusion,
+    /// Paths from the camera and from the lights, connected at every pair of their vertices
+    Bidirectional,
+    /// Progressive photon mapping: photons from the lights and the background gathered around the
+    /// camera's paths, over shrinking radii
+    PhotonMapping,
+}
+
+impl IntegratorMethod {
+    pub fn integrator(self, config: &IntegratorConfig) -> Box<dyn Integrator> {
+        let max_depth: u32 = config.max_depth;
+        match self {
+            IntegratorMethod::Path => Box::new(PathIntegrator {
+                max_depth, russian_roulette_depth: config.russian_roulette_depth, heuristic: config.heuristic
+            }),
+            IntegratorMethod::Whitted => Box::new(WhittedIntegrator { max_depth }),
+            IntegratorMethod::DirectLighting => Box::new(DirectLightingIntegrator { max_depth }),
+            IntegratorMethod::AmbientOcclusion => Box::new(AmbientOcclusionIntegrator { distance: config.occlusion_distance }),
+            IntegratorMethod::Bidirectional => Box::new(BidirectionalIntegrator { max_depth, heuristic: config.heuristic }),
+            IntegratorMethod::PhotonMapping => Box::new(PhotonMappingIntegrator {
+                max_depth, heuristic: config.heuristic,
+                photons_per_iteration: config.photons_per_iteration, radius: config.photon_radius
+            }),
+        }
+    }
+}
+
+// How light found by sampling a light and light found by sampling the material are weighted
+// against each other when both could have found it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum MisHeuristic {
+    /// In proportion to the PDF of each strategy
+    Balance,
+    /// In proportion to the squared PDFs, favouring whichever strategy is clearly better
+    #[default]
+    Power,
+}
+
+impl MisHeuristic {
+    fn weight(self, pdf: f64, other_pdf: f64) -> f64 {
+        // Weight of a sample drawn with pdf, when other_pdf would have drawn it too.
+        match self {
+            MisHeuristic::Balance => pdf / (pdf + other_pdf),
+            MisHeuristic::Power => pdf * pdf / (pdf * pdf + other_pdf * other_pdf)
+        }
+    }
+
+    fn scale(self, pdf_ratio: f64) -> f64 {
+        // A ratio of two strategies' PDFs, in the terms the heuristic compares them in.
+        match self {
+            MisHeuristic::Balance => pdf_ratio,
+            MisHeuristic::Power => pdf_ratio * pdf_ratio
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct IntegratorConfig {
+    pub max_depth: u32,
+    pub russian_roulette_depth: u32,
+    pub heuristic: MisHeuristic,
+    pub occlusion_distance: Option<f64>,    // Defaults to a tenth of the scene's size
+    pub photons_per_iteration: Option<u32>, // Defaults to the image's pixel count
+    pub photon_radius: Option<f64>,         // Defaults to a hundredth of the scene's size
+}
+
+impl IntegratorConfig {
+    pub fn from_camera(cam: &Camera) -> Self {
+        Self {
+            max_depth: cam.max_depth, russian_roulette_depth: cam.russian_roulette_depth,
+            heuristic: MisHeuristic::default(), occlusion_distance: None,
+            photons_per_iteration: None, photon_radius: None
+        }
+    }
+}
+
+
+pub struct Scene<'a> {
+    pub world: &'a dyn Hittable,
+    pub lights: &'a HittableList,   // Shapes worth sampling directions towards, usually the emitters
+    pub background: Color,          // Light arriving along rays that miss the world
+    pub camera: &'a Camera
+}
+
+impl Scene<'_> {
+    fn hit(&self, ray: &Ray) -> Option<HitRecord> {
+        self.world.hit(ray, &Interval::new(0.001, f64::INFINITY))
+    }
+
+    fn light_pdf(&self, point: &Point3f, time: f64) -> HittablePDF {
+        HittablePDF::new(Arc::new(self.lights.clone()), point, time)
+    }
+
+    fn size(&self) -> f64 {
+        // Length of the diagonal of the world's bounding box.
+        let bounding_box = self.world.bounding_box();
+        Axis::iterator().map(|axis| bounding_box.axis_interval(*axis).size().powi(2)).sum::<f64>().sqrt()
+    }
+}
+
+
+pub trait Integrator: Send + Sync {
+    // Light arriving at the camera along ray, drawing random numbers from the pixel's sampler.
+    fn radiance(&self, ray: &Ray, scene: &Scene, sampler: &mut dyn Sampler) -> Color;
+
+    // The radiance of a camera sample, for integrators whose samples may also add light to other
+    // pixels, which they push onto splats.
+    fn sample(&self, ray: &Ray, scene: &Scene, sampler: &mut dyn Sampler, _splats: &mut Vec<Splat>) -> Color {
+        self.radiance(ray, scene, sampler)
+    }
+
+    // The whole image, for integrators that can't work one camera sample at a time. The camera
+    // renders it from samples when this returns None.
+    fn render(&self, _scene: &Scene) -> Option<Film> {
+        None
+    }
+}
+
+
+fn scatter(ray: &Ray, rec: &HitRecord, scatter_rec: ScatterRecord, scene: &Scene, u: (f64, f64)) -> (Ray, Color) {
+    // Returns the next ray of a path and the factor it scales the light it brings by. Materials
+    // that don't choose the ray themselves have it drawn from a mixture of their PDF and the
+    // lights'.
+    if scatter_rec.skip_pdf {
+        return (scatter_rec.skip_pdf_ray, scatter_rec.attenuation);
+    }
+
+    let selected_pdf: Arc<dyn PDF> = if !scene.lights.objects.is_empty() {
+        Arc::new(MixturePDF::new(Arc::new(scene.light_pdf(&rec.point, ray.time())), scatter_rec.pdf_ptr))
+    }
+    else {
+        scatter_rec.pdf_ptr
+    };
+
+    let scattered: Ray = Ray::with_time(&rec.point, &selected_pdf.generate(u), ray.time());
+    let pdf_value: f64 = selected_pdf.value(scattered.direction());
+    let scattering_pdf: f64 = rec.mat.scattering_pdf(ray, rec, &scattered);
+    (scattered, scatter_rec.attenuation * scattering_pdf / pdf_value)
+}
+
+
+fn sample_light(ray: &Ray, rec: &HitRecord, scatter_rec: &ScatterRecord, scene: &Scene, heuristic: MisHeuristic, u: (f64, f64)) -> Color {
+    // Next-event estimation: light arriving along a shadow ray towards a sampled point on a light,
+    // weighted against finding the same light by sampling the material. Whatever the shadow ray
+    // hits first is what it sees, so occluders need no separate test.
+    let light_pdf: HittablePDF = scene.light_pdf(&rec.point, ray.time());
+    let to_light: Ray = Ray::with_time(&rec.point, &light_pdf.generate(u), ray.time());
+    let light_pdf_value: f64 = light_pdf.value(to_light.direction());
+    let scattering_pdf: f64 = rec.mat.scattering_pdf(ray, rec, &to_light);
+    if light_pdf_value <= 0.0 || scattering_pdf <= 0.0 {
+        return Color::ZERO;
+    }
+
+    // A shadow ray that misses sees the background, which only material sampling accounts for.
+    let Some(light_rec) = scene.hit(&to_light) else {
+        return Color::ZERO;
+    };
+    let emitted: Color = light_rec.mat.emitted(&to_light, &light_rec, light_rec.uv, &light_rec.point);
+    let weight: f64 = heuristic.weight(light_pdf_value, scatter_rec.pdf_ptr.value(to_light.direction()));
+    scatter_rec.attenuation * scattering_pdf * emitted * weight / light_pdf_value
+}
+
+
+fn sample_emitter(scene: &Scene, time: f64, u: (f64, f64)) -> Option<(SurfaceSample, HitRecord)> {
+    // A point drawn on the lights, and the surface of the world there. The lights list may only
+    // hold the shapes of the lights, so the material comes from the world, found with a short ray
+    // back onto the point.
+    let sample: SurfaceSample = scene.lights.sample_surface(u, time).filter(|sample: &SurfaceSample| sample.pdf > 0.0)?;
+    let offset: f64 = 1e-4 * sample.point.length().max(1.0);
+    let probe: Ray = Ray::with_time(&(sample.point + offset * sample.normal), &-sample.normal, time);
+    let rec: HitRecord = scene.world.hit(&probe, &Interval::new(0.0, 2.0 * offset))?;
+    Some((sample, rec))
+}
+
+fn emitted_towards(rec: &HitRecord, point: &Point3f, towards: &Point3f, time: f64) -> Color {
+    // Light the surface of rec emits from point towards another point, whichever side of it the
+    // record was found from.
+    let ray: Ray = Ray::with_time(towards, &(point - towards), time);
+    let outward_normal: Vec3f = if rec.front_face { rec.geometric_normal } else { -rec.geometric_normal };
+    let facing: HitRecord = HitRecord { front_face: Vec3f::dot(ray.direction(), &outward_normal) < 0.0, ..rec.clone() };
+    rec.mat.emitted(&ray, &facing, rec.uv, point)
+}
+
+
+pub struct PathIntegrator {
+    max_depth: u32,
+    russian_roulette_depth: u32,
+    heuristic: MisHeuristic
+}
+
+impl Integrator for PathIntegrator {
+    fn radiance(&self, ray: &Ray, scene: &Scene, sampler: &mut dyn Sampler) -> Color {
+        // Follows the path one bounce at a time, carrying the product of the attenuations so far.
+        // Each diffuse bounce both sends a shadow ray to a light and samples the material for the
+        // next direction; light either finds is weighted by the multiple importance sampling
+        // heuristic. Mirrors and glass can't be sampled towards a light, so light seen through
+        // them counts in full.
+        //
+        // After russian_roulette_depth bounces, a path whose throughput has dropped below one
+        // survives with that probability and is weighted up to match, which keeps the estimate
+        // unbiased while dim paths end early.
+        let mut radiance: Color = Color::ZERO;
+        let mut throughput: Color = Color::ONE;
+        let mut ray: Ray = *ray;
+        let sample_lights: bool = !scene.lights.objects.is_empty();
+        // Where the ray was scattered from by sampling a material, and that sample's PDF.
+        let mut material_sample: Option<(Point3f, f64)> = None;
+
+        for depth in 0..self.max_depth {
+            let Some(rec) = scene.hit(&ray) else {
+                radiance += throughput * scene.background;
+                break;
+            };
+
+            // Every bounce draws the same dimensions, whichever are used: one sample for the
+            // material, one for the light, one for the direction drawn from the material's PDF,
+            // and one for Russian roulette.
+            let u_scatter: (f64, f64) = sampler.get_2d();
+            let u_light: (f64, f64) = sampler.get_2d();
+            let u_direction: (f64, f64) = sampler.get_2d();
+            let u_roulette: f64 = sampler.get_1d();
+
+            let emitted: Color = rec.mat.emitted(&ray, &rec, rec.uv, &rec.point);
+            let weight: f64 = match material_sample {
+                Some((origin, material_pdf)) if sample_lights => {
+                    self.heuristic.weight(material_pdf, scene.lights.pdf_value(&origin, ray.direction(), ray.time()))
+                },
+                _ => 1.0
+            };
+            radiance += throughput * emitted * weight;
+
+            let Some(scatter_rec) = rec.mat.scatter(&ray, &rec, u_scatter) else {
+                break;
+            };
+            if scatter_rec.skip_pdf {
+                throughput = throughput * scatter_rec.attenuation;
+                ray = scatter_rec.skip_pdf_ray;
+                material_sample = None;
+            }
+            else {
+                if sample_lights {
+                    radiance += throughput * sample_light(&ray, &rec, &scatter_rec, scene, self.heuristic, u_light);
+                }
+
+                let scattered: Ray = Ray::with_time(&rec.point, &scatter_rec.pdf_ptr.generate(u_direction), ray.time());
+                let material_pdf: f64 = scatter_rec.pdf_ptr.value(scattered.direction());
+                if material_pdf <= 0.0 {
+                    break;
+                }
+                let scattering_pdf: f64 = rec.mat.scattering_pdf(&ray, &rec, &scattered);
+                throughput = throughput * scatter_rec.attenuation * scattering_pdf / material_pdf;
+                material_sample = Some((rec.point, material_pdf));
+                ray = scattered;
+            }
+
+            let survival: f64 = throughput.max_component();
+            if survival <= 0.0 {
+                break;
+            }
+            if depth + 1 >= self.russian_roulette_depth && survival < 1.0 {
+                if u_roulette >= survival {
+                    break;
+                }
+                throughput /= survival;
+            }
+        }
+
+        radiance
+    }
+}
+
+
+// Direct light at surfaces that scatter diffusely, found with shadow rays towards the lights,
+// plus the background scaled by the surface color as a stand-in for all other light. Mirrors and
+// glass are followed up to max_depth.
+pub struct WhittedIntegrator {
+    max_depth: u32
+}
+
+impl Integrator for WhittedIntegrator {
+    fn radiance(&self, ray: &Ray, scene: &Scene, sampler: &mut dyn Sampler) -> Color {
+        let mut radiance: Color = Color::ZERO;
+        let mut throughput: Color = Color::ONE;
+        let mut ray: Ray = *ray;
+
+        for _ in 0..self.max_depth {
+            let Some(rec) = scene.hit(&ray) else {
+                radiance += throughput * scene.background;
+                break;
+            };
+            let u_scatter: (f64, f64) = sampler.get_2d();
+            let u_light: (f64, f64) = sampler.get_2d();
+
+            radiance += throughput * rec.mat.emitted(&ray, &rec, rec.uv, &rec.point);
+            let Some(scatter_rec) = rec.mat.scatter(&ray, &rec, u_scatter) else {
+                break;
+            };
+            if scatter_rec.skip_pdf {
+                throughput = throughput * scatter_rec.attenuation;
+                ray = scatter_rec.skip_pdf_ray;
+                continue;
+            }
+
+            radiance += throughput * scatter_rec.attenuation * scene.background;
+            if !scene.lights.objects.is_empty() {
+                let light_pdf: HittablePDF = scene.light_pdf(&rec.point, ray.time());
+                let to_light: Ray = Ray::with_time(&rec.point, &light_pdf.generate(u_light), ray.time());
+                let pdf_value: f64 = light_pdf.value(to_light.direction());
+                if let Some(light_rec) = scene.hit(&to_light).filter(|_| pdf_value > 0.0) {
+                    let scattering_pdf: f64 = rec.mat.scattering_pdf(&ray, &rec, &to_light);
+                    let emitted: Color = light_rec.mat.emitted(&to_light, &light_rec, light_rec.uv, &light_rec.point);
+                    radiance += throughput * scatter_rec.attenuation * scattering_pdf * emitted / pdf_value;
+                }
+            }
+            break;
+        }
+
+        radiance
+    }
+}
+
+
+// Light that reaches the first diffusely scattering surface straight from an emitter or the
+// background, sampled like the path tracer's first bounce, and seen from the camera through any
+// mirrors and glass.
+pub struct DirectLightingIntegrator {
+    max_depth: u32
+}
+
+impl Integrator for DirectLightingIntegrator {
+    fn radiance(&self, ray: &Ray, scene: &Scene, sampler: &mut dyn Sampler) -> Color {
+        let mut radiance: Color = Color::ZERO;
+        let mut throughput: Color = Color::ONE;
+        let mut ray: Ray = *ray;
+        let mut last_bounce: bool = false;
+
+        for _ in 0..self.max_depth {
+            let Some(rec) = scene.hit(&ray) else {
+                radiance += throughput * scene.background;
+                break;
+            };
+            let u_scatter: (f64, f64) = sampler.get_2d();
+            let u_direction: (f64, f64) = sampler.get_2d();
+
+            radiance += throughput * rec.mat.emitted(&ray, &rec, rec.uv, &rec.point);
+            if last_bounce {
+                break;
+            }
+            let Some(scatter_rec) = rec.mat.scatter(&ray, &rec, u_scatter) else {
+                break;
+            };
+            last_bounce = !scatter_rec.skip_pdf;
+            let (scattered, weight) = scatter(&ray, &rec, scatter_rec, scene, u_direction);
+            throughput = throughput * weight;
+            ray = scattered;
+        }
+
+        radiance
+    }
+}
+
+
+// The fraction of a cosine weighted hemisphere above each visible surface that is free of other
+// surfaces within distance, by default a tenth of the size of the scene.
+pub struct AmbientOcclusionIntegrator {
+    distance: Option<f64>
+}
+
+impl Integrator for AmbientOcclusionIntegrator {
+    fn radiance(&self, ray: &Ray, scene: &Scene, sampler: &mut dyn Sampler) -> Color {
+        let Some(rec) = scene.hit(ray) else {
+            return Color::ONE;
+        };
+        let u_direction: (f64, f64) = sampler.get_2d();
+
+        let distance: f64 = self.distance.unwrap_or_else(|| 0.1 * scene.size());
+        let direction: Vec3f = ONB::new(&rec.normal).transform(&Vec3f::sample_cosine_direction(u_direction));
+        if Vec3f::dot(&direction, &rec.geometric_normal) <= 0.0 {
+            return Color::ZERO;
+        }
+        let occluder: Ray = Ray::with_time(&rec.point, &direction, ray.time());
+        match scene.world.hit(&occluder, &Interval::new(0.001, distance)) {
+            Some(_) => Color::ZERO,
+            None => Color::ONE
+        }
+    }
+}
+
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum VertexKind {
+    Camera,
+    Light,
+    Surface
+}
+
+// A vertex of a camera or light subpath. Densities are over the area at the vertex, so the two
+// subpaths can be compared however they were built.
+#[derive(Clone)]
+struct Vertex {
+    kind: VertexKind,
+    point: Point3f,
+    normal: Vec3f,                      // Geometric normal; the view direction at the camera
+    beta: Color,                        // Throughput of the subpath up to here over its density
+    rec: Option<HitRecord>,             // The surface at surface and light vertices
+    ray_in: Ray,                        // Ray the subpath arrived along
+    attenuation: Color,
+    material_pdf: Option<Arc<dyn PDF>>, // Directions a diffuse surface scatters into
+    delta: bool,                        // Mirrors and glass, which can only be followed
+    pdf_fwd: f64,                       // Density of the vertex drawn from the previous one
+    pdf_rev: f64                        // Density of the vertex drawn from the next one
+}
+
+impl Vertex {
+    fn new(kind: VertexKind, point: &Point3f, normal: &Vec3f, beta: Color, rec: Option<HitRecord>, ray_in: &Ray) -> Self {
+        Self {
+            kind, point: *point, normal: *normal, beta, rec, ray_in: *ray_in, attenuation: Color::ZERO,
+            material_pdf: None, delta: false, pdf_fwd: 0.0, pdf_rev: 0.0
+        }
+    }
+
+    fn connectible(&self) -> bool {
+        self.kind != VertexKind::Surface || self.material_pdf.is_some()
+    }
+
+    fn emitted(&self, towards: &Point3f) -> Color {
+        // Light the vertex's surface emits towards a point.
+        match &self.rec {
+            Some(rec) => emitted_towards(rec, &self.point, towards, self.ray_in.time()),
+            None => Color::ZERO
+        }
+    }
+
+    fn scatter_factor(&self, towards: &Point3f) -> Color {
+        // The attenuation times the scattering PDF, the share of light arriving along ray_in that
+        // leaves towards a point, or the emission times the cosine there at a light. Zero at the
+        // camera, which connections handle themselves.
+        let direction: Vec3f = towards - self.point;
+        match (self.kind, &self.rec) {
+            (VertexKind::Surface, Some(rec)) if self.material_pdf.is_some() => {
+                let scattered: Ray = Ray::with_time(&self.point, &direction, self.ray_in.time());
+                self.attenuation * rec.mat.scattering_pdf(&self.ray_in, rec, &scattered)
+            },
+            (VertexKind::Light, _) => {
+                self.emitted(towards) * f64::abs(Vec3f::dot(&self.normal, &Vec3f::unit_vector(&direction)))
+            },
+            _ => Color::ZERO
+        }
+    }
+
+    fn convert_density(&self, pdf: f64, next: &Vertex) -> f64 {
+        // A density over directions leaving this vertex, as a density over the area at next.
+        let direction: Vec3f = next.point - self.point;
+        let distance_squared: f64 = direction.length_squared();
+        if distance_squared == 0.0 {
+            return 0.0;
+        }
+        let cos_theta: f64 = match next.kind {
+            VertexKind::Camera => 1.0,
+            _ => f64::abs(Vec3f::dot(&next.normal, &direction)) / distance_squared.sqrt()
+        };
+        pdf * cos_theta / distance_squared
+    }
+
+    fn pdf(&self, scene: &Scene, next: &Vertex) -> f64 {
+        // Density of drawing next by continuing the subpath from this vertex. The materials
+        // scatter the same whichever way light travels, so where the subpath came from doesn't
+        // matter.
+        let direction: Vec3f = next.point - self.point;
+        let pdf: f64 = match self.kind {
+            VertexKind::Camera => scene.camera.pdf_direction(&Ray::new(&self.point, &direction)),
+            VertexKind::Light => return self.pdf_emission(next),
+            VertexKind::Surface => self.material_pdf.as_ref().map_or(0.0, |pdf| pdf.value(&direction))
+        };
+        self.convert_density(pdf, next)
+    }
+
+    fn pdf_emission(&self, next: &Vertex) -> f64 {
+        // Density of a light at this vertex emitting towards next, cosine weighted.
+        let cos_theta: f64 = Vec3f::dot(&self.normal, &Vec3f::unit_vector(&(next.point - self.point)));
+        self.convert_density(f64::abs(cos_theta) / f64::consts::PI, next)
+    }
+
+    fn pdf_light_origin(&self, scene: &Scene, previous: &Vertex) -> f64 {
+        // Density of the lights drawing this vertex as the start of a light subpath, which is zero
+        // for emitters that aren't among them.
+        let direction: Vec3f = self.point - previous.point;
+        let time: f64 = self.ray_in.time();
+        match scene.lights.hit(&Ray::with_time(&previous.point, &direction, time), &Interval::new(0.001, f64::INFINITY)) {
+            Some(rec) if (rec.t - 1.0).abs() < 1e-6 => scene.lights.surface_pdf(&previous.point, &direction, time),
+            _ => 0.0
+        }
+    }
+}
+
+
+// Bidirectional path tracing: a subpath from the camera and one from a point on the lights are
+// joined at every pair of their vertices, and each joined path is weighted by multiple importance
+// sampling against the other ways the same path could have been built. Paths from the lights that
+// join the camera directly land on other pixels, and are splatted onto them. Small lights and
+// caustics, which paths from the camera rarely find, come from the light's side.
+//
+// Mirrors and glass can only be followed, never joined at. A path is at most max_depth segments
+// long, like in the path tracer, and the background is only found from the camera's side.
+pub struct BidirectionalIntegrator {
+    max_depth: u32,
+    heuristic: MisHeuristic
+}
+
+impl BidirectionalIntegrator {
+    fn random_walk(scene: &Scene, ray: &Ray, beta: Color, pdf: f64, segments: u32, sampler: &mut dyn Sampler, path: &mut Vec<Vertex>) -> Color {
+        // Extends path by up to segments rays, starting from ray drawn with the given density
+        // over directions. Returns the throughput times the background, if the path left the
+        // scene.
+        let (mut ray, mut beta, mut pdf_fwd) = (*ray, beta, pdf);
+        for _ in 0..segments {
+            let u_scatter: (f64, f64) = sampler.get_2d();
+            let u_direction: (f64, f64) = sampler.get_2d();
+            let Some(rec) = scene.hit(&ray) else {
+                return beta * scene.background;
+            };
+
+            let previous: usize = path.len() - 1;
+            let mut vertex: Vertex = Vertex::new(VertexKind::Surface, &rec.point, &rec.geometric_normal, beta, None, &ray);
+            vertex.pdf_fwd = path[previous].convert_density(pdf_fwd, &vertex);
+            let Some(scatter_rec) = rec.mat.scatter(&ray, &rec, u_scatter) else {
+                vertex.rec = Some(rec);
+                path.push(vertex);
+                break;
+            };
+
+            vertex.attenuation = scatter_rec.attenuation;
+            let pdf_rev: f64;
+            if scatter_rec.skip_pdf {
+                vertex.delta = true;
+                beta = beta * scatter_rec.attenuation;
+                ray = scatter_rec.skip_pdf_ray;
+                pdf_fwd = 0.0;
+                pdf_rev = 0.0;
+            }
+            else {
+                let scattered: Ray = Ray::with_time(&rec.point, &scatter_rec.pdf_ptr.generate(u_direction), ray.time());
+                pdf_fwd = scatter_rec.pdf_ptr.value(scattered.direction());
+                pdf_rev = scatter_rec.pdf_ptr.value(&-*ray.direction());
+                let scattering_pdf: f64 = rec.mat.scattering_pdf(&ray, &rec, &scattered);
+                beta = if pdf_fwd > 0.0 { beta * scatter_rec.attenuation * scattering_pdf / pdf_fwd } else { Color::ZERO };
+                vertex.material_pdf = Some(scatter_rec.pdf_ptr);
+                ray = scattered;
+            }
+
+            vertex.rec = Some(rec);
+            path[previous].pdf_rev = vertex.convert_density(pdf_rev, &path[previous]);
+            path.push(vertex);
+            if beta.max_component() <= 0.0 {
+                break;
+            }
+        }
+        Color::ZERO
+    }
+
+    fn light_subpath(&self, scene: &Scene, time: f64, sampler: &mut dyn Sampler) -> Vec<Vertex> {
+        let u_surface: (f64, f64) = sampler.get_2d();
+        let u_direction: (f64, f64) = sampler.get_2d();
+        if self.max_depth == 0 {
+            return Vec::new();
+        }
+        let Some((sample, rec)) = sample_emitter(scene, time, u_surface) else {
+            return Vec::new();
+        };
+
+        // Nothing arrives at a light vertex; its ray only carries the time.
+        let normal_ray: Ray = Ray::with_time(&sample.point, &sample.normal, time);
+        let mut light: Vertex = Vertex::new(VertexKind::Light, &sample.point, &sample.normal, Color::ONE / sample.pdf, Some(rec), &normal_ray);
+        light.pdf_fwd = sample.pdf;
+        let direction: Vec3f = CosinePDF::new(&sample.normal).generate(u_direction);
+        let emitted: Ray = Ray::with_time(&sample.point, &direction, time);
+        let pdf_direction: f64 = Vec3f::dot(&sample.normal, &Vec3f::unit_vector(&direction)) / f64::consts::PI;
+        let beta: Color = light.beta * light.scatter_factor(&(sample.point + direction)) / pdf_direction;
+
+        let mut path: Vec<Vertex> = vec![light];
+        if pdf_direction > 0.0 {
+            Self::random_walk(scene, &emitted, beta, pdf_direction, self.max_depth - 1, sampler, &mut path);
+        }
+        path
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn connect(&self, scene: &Scene, light_path: &[Vertex], camera_path: &[Vertex], s: usize, t: usize, light_tracing: bool, sampler: &mut dyn Sampler) -> Option<(Color, Option<(u32, u32)>)> {
+        // The light of the path made of the first s vertices of the light subpath and the first t
+        // of the camera subpath, and the pixel it lands on when that isn't the sample's own.
+        // Without light_tracing, paths joined straight to the lens (t == 1) aren't counted.
+        let time: f64 = camera_path[0].ray_in.time();
+        if s == 0 {
+            // The camera subpath found an emitter by itself.
+            let pt: &Vertex = &camera_path[t - 1];
+            let radiance: Color = pt.beta * pt.emitted(&camera_path[t - 2].point);
+            if radiance.max_component() <= 0.0 {
+                return None;
+            }
+            return Some((radiance * self.mis_weight(scene, light_path, camera_path, None, s, t, light_tracing), None));
+        }
+
+        let qs: &Vertex = &light_path[s - 1];
+        if !qs.connectible() {
+            return None;
+        }
+        if t == 1 {
+            // The light subpath joined to a point on the lens.
+            let (lens_point, lens_pdf) = scene.camera.sample_lens(sampler.get_2d());
+            let to_point: Ray = Ray::with_time(&lens_point, &(qs.point - lens_point), time);
+            let (pixel, importance) = scene.camera.importance(&to_point)?;
+            let camera: Vertex = Vertex::new(VertexKind::Camera, &lens_point, &scene.camera.view_direction(), Color::ONE / lens_pdf, None, &to_point);
+            let cos_theta: f64 = Vec3f::dot(&camera.normal, &Vec3f::unit_vector(to_point.direction()));
+            let radiance: Color = qs.beta * qs.scatter_factor(&lens_point) * camera.beta * importance * cos_theta / to_point.direction().length_squared();
+            if radiance.max_component() <= 0.0 || !Self::visible(scene, &qs.point, &lens_point, time) {
+                return None;
+            }
+            return Some((radiance * self.mis_weight(scene, light_path, camera_path, Some(&camera), s, t, light_tracing), Some(pixel)));
+        }
+
+        let pt: &Vertex = &camera_path[t - 1];
+        if !pt.connectible() {
+            return None;
+        }
+        let radiance: Color = qs.beta * qs.scatter_factor(&pt.point) * pt.scatter_factor(&qs.point) * pt.beta
+            / (pt.point - qs.point).length_squared();
+        if radiance.max_component() <= 0.0 || !Self::visible(scene, &qs.point, &pt.point, time) {
+            return None;
+        }
+        Some((radiance * self.mis_weight(scene, light_path, camera_path, None, s, t, light_tracing), None))
+    }
+
+    fn visible(scene: &Scene, from: &Point3f, to: &Point3f, time: f64) -> bool {
+        let direction: Vec3f = to - from;
+        let distance: f64 = direction.length();
+        let ray: Ray = Ray::with_time(from, &(direction / distance), time);
+        scene.world.hit(&ray, &Interval::new(0.001, distance - 0.001)).is_none()
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn mis_weight(&self, scene: &Scene, light_path: &[Vertex], camera_path: &[Vertex], sampled: Option<&Vertex>, s: usize, t: usize, light_tracing: bool) -> f64 {
+        // Compares the density of the path under this strategy with each other strategy that could
+        // have built it, by walking the vertex densities outwards from the connection, as in Veach's
+        // thesis and pbrt. Only the densities of the vertices around the connection change.
+        if s + t == 2 {
+            return 1.0;
+        }
+        let pt: &Vertex = sampled.unwrap_or(&camera_path[t - 1]);
+        let pt_minus: Option<&Vertex> = if t >= 2 { Some(&camera_path[t - 2]) } else { None };
+        let qs: Option<&Vertex> = if s >= 1 { Some(&light_path[s - 1]) } else { None };
+        let qs_minus: Option<&Vertex> = if s >= 2 { Some(&light_path[s - 2]) } else { None };
+
+        // (pdf_rev, pdf_fwd, delta) of each vertex used by this strategy.
+        let mut camera: Vec<(f64, f64, bool)> = camera_path[..t].iter().map(|v| (v.pdf_rev, v.pdf_fwd, v.delta)).collect();
+        let mut light: Vec<(f64, f64, bool)> = light_path[..s].iter().map(|v| (v.pdf_rev, v.pdf_fwd, v.delta)).collect();
+        camera[t - 1].2 = false;
+        match qs {
+            Some(qs) => {
+                light[s - 1].2 = false;
+                camera[t - 1].0 = qs.pdf(scene, pt);
+                light[s - 1].0 = pt.pdf(scene, qs);
+            },
+            None => {
+                // Emitters the lights can't draw are only ever found from the camera.
+                let pdf_origin: f64 = pt.pdf_light_origin(scene, pt_minus.expect("a camera subpath hitting an emitter has two vertices"));
+                if pdf_origin == 0.0 {
+                    return 1.0;
+                }
+                camera[t - 1].0 = pdf_origin;
+            }
+        }
+        if let Some(pt_minus) = pt_minus {
+            camera[t - 2].0 = if qs.is_some() { pt.pdf(scene, pt_minus) } else { pt.pdf_emission(pt_minus) };
+        }
+        if let (Some(qs), Some(qs_minus)) = (qs, qs_minus) {
+            light[s - 2].0 = qs.pdf(scene, qs_minus);
+        }
+
+        // A delta vertex has no density to compare, so its ratio is left as one.
+        let remap = |pdf: f64| if pdf != 0.0 { pdf } else { 1.0 };
+        let mut sum: f64 = 0.0;
+        let mut ratio: f64 = 1.0;
+        for i in (1..t).rev() {
+            // The strategy with i camera vertices; i == 1 joins the light subpath to the lens.
+            ratio *= remap(camera[i].0) / remap(camera[i].1);
+            if !camera[i].2 && !camera[i - 1].2 && (i > 1 || light_tracing) {
+                sum += self.heuristic.scale(ratio);
+            }
+        }
+        ratio = 1.0;
+        for i in (0..s).rev() {
+            ratio *= remap(light[i].0) / remap(light[i].1);
+            let follows_delta: bool = i > 0 && light[i - 1].2;
+            if !light[i].2 && !follows_delta {
+                sum += self.heuristic.scale(ratio);
+            }
+        }
+        1.0 / (1.0 + sum)
+    }
+
+    fn trace(&self, ray: &Ray, scene: &Scene, sampler: &mut dyn Sampler, mut splats: Option<&mut Vec<Splat>>) -> Color {
+        // Light subpaths are only joined straight to the lens when there are splats to add them to.
+        let mut camera_path: Vec<Vertex> = vec![
+            Vertex::new(VertexKind::Camera, ray.origin(), &scene.camera.view_direction(), Color::ONE, None, ray)
+        ];
+        let mut radiance: Color = Self::random_walk(
+            scene, ray, Color::ONE, scene.camera.pdf_direction(ray), self.max_depth, sampler, &mut camera_path
+        );
+        let light_path: Vec<Vertex> = if scene.lights.objects.is_empty() {
+            Vec::new()
+        }
+        else {
+            self.light_subpath(scene, ray.time(), sampler)
+        };
+
+        let light_tracing: bool = splats.is_some();
+        for t in 1..=camera_path.len() {
+            for s in 0..=light_path.len() {
+                // The joined path has s + t - 1 segments.
+                if (s == 1 && t == 1) || s + t < 2 || s + t - 1 > self.max_depth as usize || (t == 1 && !light_tracing) {
+                    continue;
+                }
+                match self.connect(scene, &light_path, &camera_path, s, t, light_tracing, sampler) {
+                    Some((color, Some(pixel))) => {
+                        if let Some(splats) = splats.as_deref_mut() {
+                            splats.push(Splat { pixel, color });
+                        }
+                    },
+                    Some((color, None)) => radiance += color,
+                    None => ()
+                }
+            }
+        }
+        radiance
+    }
+}
+
+impl Integrator for BidirectionalIntegrator {
+    fn radiance(&self, ray: &Ray, scene: &Scene, sampler: &mut dyn Sampler) -> Color {
+        // Without a film to splat onto, paths joined straight to the lens are left out, and the
+        // other strategies are weighted as if they were the only ones.
+        self.trace(ray, scene, sampler, None)
+    }
+
+    fn sample(&self, ray: &Ray, scene: &Scene, sampler: &mut dyn Sampler, splats: &mut Vec<Splat>) -> Color {
+        self.trace(ray, scene, sampler, Some(splats))
+    }
+}
+
+// Stochastic progressive photon mapping, after Hachisuka and Jensen. Each iteration follows one
+// path per pixel from the camera, through mirrors, glass and smoke, to the first diffuse surface
+// it meets, then sends photons out from the lights, and in from the background unless it is black,
+// and gathers those that land within a radius of each pixel's surface point. A pixel's radius
+// shrinks as it gathers photons, so the blur of the estimate fades while its noise averages out
+// over the iterations. Light focused through glass onto a diffuse surface, which paths from the
+// camera can't aim for, comes easily from the photons' side.
+//
+// Light reaching the camera's points straight from the lights or the background is sampled there,
+// as in the path tracer, and photons only carry light that has already bounced. Light from
+// emitters missing from the lights list isn't carried any further than that.
+pub struct PhotonMappingIntegrator {
+    max_depth: u32,
+    heuristic: MisHeuristic,
+    photons_per_iteration: Option<u32>,
+    radius: Option<f64>
+}
+
+// The first diffuse surface of a camera path, where its pixel gathers photons.
+struct VisiblePoint {
+    rec: HitRecord,
+    ray_in: Ray,
+    attenuation: Color,
+    beta: Color         // Throughput of the camera path up to here
+}
+
+impl VisiblePoint {
+    fn reflected(&self, photon: &Photon) -> Color {
+        // Power of a photon the surface sends back along the camera path. The scattering PDF
+        // includes the cosine towards the photon, which the density of photons already accounts
+        // for.
+        let towards_photon: Vec3f = -photon.direction;
+        let cos_theta: f64 = Vec3f::dot(&self.rec.normal, &towards_photon);
+        if cos_theta <= 0.0 {
+            return Color::ZERO;
+        }
+        let scattered: Ray = Ray::with_time(&self.rec.point, &towards_photon, self.ray_in.time());
+        let scattering_pdf: f64 = self.rec.mat.scattering_pdf(&self.ray_in, &self.rec, &scattered);
+        self.beta * self.attenuation * photon.power * scattering_pdf / cos_theta
+    }
+}
+
+// What a pixel has gathered over the iterations so far.
+#[derive(Clone, Copy)]
+struct PixelEstimate {
+    direct: Color,      // Sum of the light sampled along the camera paths
+    radius: f64,
+    photons: f64,       // Photons gathered, discounted as the radius shrank
+    flux: Color         // Power of the photons gathered, within the current radius
+}
+
+impl PixelEstimate {
+    // Share of each iteration's photons kept, rather than used to shrink the radius.
+    const ALPHA: f64 = 2.0 / 3.0;
+
+    fn gather(&mut self, found: usize, flux: Color) {
+        // Shrinks the radius so the disk holds the photons kept, and scales the power gathered so
+        // far down to the disk's new area.
+        if found == 0 {
+            return;
+        }
+        let photons: f64 = self.photons + Self::ALPHA * found as f64;
+        let radius: f64 = self.radius * f64::sqrt(photons / (self.photons + found as f64));
+        self.flux = (self.flux + flux) * (radius * radius) / (self.radius * self.radius);
+        self.photons = photons;
+        self.radius = radius;
+    }
+}
+
+impl PhotonMappingIntegrator {
+    fn direct_light(&self, ray: &Ray, rec: &HitRecord, scatter_rec: &ScatterRecord, scene: &Scene, u_light: (f64, f64), u_material: (f64, f64)) -> Color {
+        // Light arriving at rec straight from the lights or the background: a shadow ray to the
+        // lights and a ray drawn from the material, weighted against each other as in the path
+        // tracer.
+        let sample_lights: bool = !scene.lights.objects.is_empty();
+        let mut radiance: Color = if sample_lights {
+            sample_light(ray, rec, scatter_rec, scene, self.heuristic, u_light)
+        }
+        else {
+            Color::ZERO
+        };
+
+        let scattered: Ray = Ray::with_time(&rec.point, &scatter_rec.pdf_ptr.generate(u_material), ray.time());
+        let material_pdf: f64 = scatter_rec.pdf_ptr.value(scattered.direction());
+        if material_pdf <= 0.0 {
+            return radiance;
+        }
+        let factor: Color = scatter_rec.attenuation * rec.mat.scattering_pdf(ray, rec, &scattered) / material_pdf;
+        match scene.hit(&scattered) {
+            Some(light_rec) => {
+                let weight: f64 = if sample_lights {
+                    self.heuristic.weight(material_pdf, scene.lights.pdf_value(&rec.point, scattered.direction(), ray.time()))
+                }
+                else {
+                    1.0
+                };
+                radiance += factor * light_rec.mat.emitted(&scattered, &light_rec, light_rec.uv, &light_rec.point) * weight;
+            },
+            None => radiance += factor * scene.background
+        }
+        radiance
+    }
+
+    fn visible_point(&self, ray: &Ray, scene: &Scene, sampler: &mut dyn Sampler) -> (Color, Option<VisiblePoint>) {
+        // Follows a camera ray to the first diffuse surface, adding up the light sampled on the
+        // way. Emitters and the background only count when found through mirrors and glass;
+        // after smoke scatters the path they were already sampled.
+        let mut radiance: Color = Color::ZERO;
+        let mut beta: Color = Color::ONE;
+        let mut ray: Ray = *ray;
+        let mut specular: bool = true;
+
+        for _ in 0..self.max_depth {
+            let Some(rec) = scene.hit(&ray) else {
+                if specular {
+                    radiance += beta * scene.background;
+                }
+                break;
+            };
+            let u_scatter: (f64, f64) = sampler.get_2d();
+            let u_light: (f64, f64) = sampler.get_2d();
+            let u_material: (f64, f64) = sampler.get_2d();
+            let u_direction: (f64, f64) = sampler.get_2d();
+
+            if specular {
+                radiance += beta * rec.mat.emitted(&ray, &rec, rec.uv, &rec.point);
+            }
+            let Some(scatter_rec) = rec.mat.scatter(&ray, &rec, u_scatter) else {
+                break;
+            };
+            if scatter_rec.skip_pdf {
+                beta = beta * scatter_rec.attenuation;
+                ray = scatter_rec.skip_pdf_ray;
+                specular = true;
+                continue;
+            }
+
+            radiance += beta * self.direct_light(&ray, &rec, &scatter_rec, scene, u_light, u_material);
+            if !rec.mat.scatters_in_volume() {
+                let visible_point: VisiblePoint = VisiblePoint { rec, ray_in: ray, attenuation: scatter_rec.attenuation, beta };
+                return (radiance, Some(visible_point));
+            }
+
+            // Smoke doesn't gather photons, so the path goes on through it.
+            let scattered: Ray = Ray::with_time(&rec.point, &scatter_rec.pdf_ptr.generate(u_direction), ray.time());
+            let material_pdf: f64 = scatter_rec.pdf_ptr.value(scattered.direction());
+            if material_pdf <= 0.0 {
+                break;
+            }
+            beta = beta * scatter_rec.attenuation * rec.mat.scattering_pdf(&ray, &rec, &scattered) / material_pdf;
+            ray = scattered;
+            specular = false;
+        }
+        (radiance, None)
+    }
+
+    fn background_sphere(scene: &Scene) -> Option<(Point3f, f64)> {
+        // Center and radius of a sphere around the world, which photons from the background come
+        // in through; none if the background is black or the world is unbounded.
+        let radius: f64 = 0.5 * scene.size();
+        if scene.background.max_component() <= 0.0 || !(radius.is_finite() && radius > 0.0) {
+            return None;
+        }
+        Some((scene.world.bounding_box().centroid(), radius))
+    }
+
+    fn light_photon(scene: &Scene, time: f64, sampler: &mut dyn Sampler) -> Option<(Ray, Color)> {
+        // A photon leaving a point on the lights. Emission is drawn cosine weighted, so the cosine
+        // over the direction's density is pi.
+        let u_surface: (f64, f64) = sampler.get_2d();
+        let u_emission: (f64, f64) = sampler.get_2d();
+        let (sample, light_rec) = sample_emitter(scene, time, u_surface)?;
+        let direction: Vec3f = CosinePDF::new(&sample.normal).generate(u_emission);
+        let power: Color = emitted_towards(&light_rec, &sample.point, &(sample.point + direction), time) * f64::consts::PI / sample.pdf;
+        Some((Ray::with_time(&sample.point, &direction, time), power))
+    }
+
+    fn background_photon(scene: &Scene, (center, radius): (Point3f, f64), time: f64, sampler: &mut dyn Sampler) -> Option<(Ray, Color)> {
+        // A photon coming in from the background, which shines alike from every direction: the
+        // direction is drawn uniformly over the sphere, and the photon starts from a point on the
+        // disk that faces it and covers the bounding sphere, with densities 1 / 4 pi and 1 / pi r^2.
+        let u_surface: (f64, f64) = sampler.get_2d();
+        let u_emission: (f64, f64) = sampler.get_2d();
+        let direction: Vec3f = Vec3f::sample_unit_sphere(u_emission);
+        let offset: Vec3f = ONB::new(&direction).transform(&Vec3f::sample_unit_disk(u_surface));
+        let origin: Point3f = center + radius * (offset - direction);
+        let power: Color = scene.background * 4.0 * f64::consts::PI * f64::consts::PI * radius * radius;
+        Some((Ray::with_time(&origin, &direction, time), power))
+    }
+
+    fn trace_photon(&self, scene: &Scene, background_sphere: Option<(Point3f, f64)>, sampler: &mut dyn Sampler, photons: &mut Vec<Photon>) {
+        // Sends a photon out from a point on the lights or in from the background, and leaves a
+        // copy of it on every diffuse surface it reaches after its first bounce. With both, each
+        // photon picks one of them at random, and carries twice the power.
+        let time: f64 = scene.camera.shutter.sample(sampler.get_1d());
+        let both: bool = background_sphere.is_some() && !scene.lights.objects.is_empty();
+        let emitted: Option<(Ray, Color)> = match background_sphere {
+            Some(sphere) if !both || sampler.get_1d() < 0.5 => Self::background_photon(scene, sphere, time, sampler),
+            _ => Self::light_photon(scene, time, sampler)
+        };
+        let Some((mut ray, mut power)) = emitted else {
+            return;
+        };
+        if both {
+            power = 2.0 * power;
+        }
+
+        for depth in 0..self.max_depth {
+            if power.max_component() <= 0.0 {
+                break;
+            }
+            let Some(rec) = scene.hit(&ray) else {
+                break;
+            };
+            let u_scatter: (f64, f64) = sampler.get_2d();
+            let u_direction: (f64, f64) = sampler.get_2d();
+            let u_roulette: f64 = sampler.get_1d();
+
+            let Some(scatter_rec) = rec.mat.scatter(&ray, &rec, u_scatter) else {
+                break;
+            };
+            let next_power: Color;
+            if scatter_rec.skip_pdf {
+                next_power = power * scatter_rec.attenuation;
+                ray = scatter_rec.skip_pdf_ray;
+            }
+            else {
+                if depth > 0 && !rec.mat.scatters_in_volume() {
+                    photons.push(Photon { point: rec.point, direction: Vec3f::unit_vector(ray.direction()), power });
+                }
+                let scattered: Ray = Ray::with_time(&rec.point, &scatter_rec.pdf_ptr.generate(u_direction), ray.time());
+                let material_pdf: f64 = scatter_rec.pdf_ptr.value(scattered.direction());
+                if material_pdf <= 0.0 {
+                    break;
+                }
+                next_power = power * scatter_rec.attenuation * rec.mat.scattering_pdf(&ray, &rec, &scattered) / material_pdf;
+                ray = scattered;
+            }
+
+            // Russian roulette keeps photons near the power they left with: one whose power halves
+            // survives half the time, at its former power.
+            let survival: f64 = f64::min(next_power.max_component() / power.max_component(), 1.0);
+            if u_roulette >= survival {
+                break;
+            }
+            power = next_power / survival;
+        }
+    }
+}
+
+impl Integrator for PhotonMappingIntegrator {
+    fn radiance(&self, ray: &Ray, scene: &Scene, sampler: &mut dyn Sampler) -> Color {
+        // Without photons, only the light sampled along the camera path.
+        self.visible_point(ray, scene, sampler).0
+    }
+
+    fn render(&self, scene: &Scene) -> Option<Film> {
+        // Every sample per pixel is an iteration. Camera paths and photons are seeded by their
+        // pixel and sample, or their photon index, and gathered in pixel order, so the image is
+        // the same whatever the thread count.
+        let camera: &Camera = scene.camera;
+        let (width, height) = (camera.image_width, camera.image_height());
+        let iterations: u32 = camera.samples_per_pixel.max(1);
+        let photons_per_iteration: u32 = self.photons_per_iteration.unwrap_or(width * height).max(1);
+        let initial: PixelEstimate = PixelEstimate {
+            direct: Color::ZERO, radius: self.radius.unwrap_or_else(|| 0.01 * scene.size()), photons: 0.0, flux: Color::ZERO
+        };
+        let mut pixels: Vec<PixelEstimate> = vec![initial; (width * height) as usize];
+        let background_sphere: Option<(Point3f, f64)> = Self::background_sphere(scene);
+
+        for iteration in 0..iterations {
+            info!("Photon mapping iteration: {}", iteration);
+            let visible_points: Vec<(Color, Option<VisiblePoint>)> = (0..width * height).into_par_iter().map(
+                |pixel: u32| {
+                    let mut sampler: Box<dyn Sampler> = camera.pixel_sampler();
+                    let ray: Ray = camera.start_sample(pixel % width, pixel / width, iteration, sampler.as_mut());
+                    self.visible_point(&ray, scene, sampler.as_mut())
+                }
+            ).collect();
+
+            // No pixel has the index u64::MAX, so photons never share a pixel sample's random numbers.
+            let photons: Vec<Photon> = if scene.lights.objects.is_empty() && background_sphere.is_none() {
+                Vec::new()
+            }
+            else {
+                (0..photons_per_iteration).into_par_iter().flat_map_iter(
+                    |photon: u32| {
+                        let index: u64 = iteration as u64 * photons_per_iteration as u64 + photon as u64;
+                        utilities::seed(utilities::hash(&[camera.seed, u64::MAX, index]));
+                        let mut deposited: Vec<Photon> = Vec::new();
+                        self.trace_photon(scene, background_sphere, &mut IndependentSampler, &mut deposited);
+                        deposited
+                    }
+                ).collect()
+            };
+
+            let max_radius: f64 = pixels.iter().zip(&visible_points)
+                .filter(|(_, (_, visible_point))| visible_point.is_some())
+                .map(|(pixel, _)| pixel.radius)
+                .fold(0.0, f64::max);
+            let photon_map: Option<PhotonMap> = PhotonMap::new(photons, max_radius).ok();
+            pixels.par_iter_mut().zip(visible_points).for_each(
+                |(pixel, (direct, visible_point))| {
+                    pixel.direct += direct;
+                    if let (Some(visible_point), Some(photon_map)) = (visible_point, &photon_map) {
+                        let mut found: usize = 0;
+                        let mut flux: Color = Color::ZERO;
+                        for photon in photon_map.within(&visible_point.rec.point, pixel.radius) {
+                            flux += visible_point.reflected(photon);
+                            found += 1;
+                        }
+                        pixel.gather(found, flux);
+                    }
+                }
+            );
+        }
+
+        // The flux gathered is spread over each pixel's disk and over every photon sent out.
+        let emitted_photons: f64 = iterations as f64 * photons_per_iteration as f64;
+        let colors: Vec<Color> = pixels.iter().map(
+            |pixel: &PixelEstimate| {
+                pixel.direct / iterations as f64 + pixel.flux / (emitted_photons * f64::consts::PI * pixel.radius * pixel.radius)
+            }
+        ).collect();
+        Some(Film::from_pixels(width, height, colors).expect("one color per pixel"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::camera::Camera;
+    use crate::film::Film;
+    use crate::integrator::*;
+    use crate::material::{Dielectric, DiffuseLight, Empty, Lambertian, Metal};
+    use crate::plane::Quad;
+    use crate::sampler::SamplerMethod;
+    use crate::sphere::Sphere;
+
+    fn mean_red(film: &Film) -> f64 {
+        film.pixels().iter().map(|pixel| pixel.component(Axis::X)).sum::<f64>() / film.pixels().len() as f64
+    }
+
+    #[test]
+    fn russian_roulette() {
+        // Inside a closed grey room lit by a glowing ball, ending dim paths early must not change
+        // the average brightness, only its noise.
+        let mut world: HittableList = HittableList::new();
+        world.add(Arc::new(Sphere::new_stationary(&Point3f::ZERO, 3.0, Arc::new(Lambertian::from_color(&Color::new(0.8, 0.7, 0.6))))));
+        world.add(Arc::new(Sphere::new_stationary(&Point3f::new(0.0, 1.5, -1.0), 0.7, Arc::new(DiffuseLight::from_color(&Color::ONE)))));
+        let cam: Camera = Camera::new(
+            1.0, 8, 256, 40, &Color::ZERO, 90.0,
+            &Point3f::ZERO, &Point3f::new(0.0, 0.0, -1.0), &Vec3f::E2, 0.0, 1.0
+        );
+        let render = |russian_roulette_depth: u32| -> f64 {
+            let integrator: Box<dyn Integrator> = IntegratorMethod::Path.integrator(
+                &IntegratorConfig { russian_roulette_depth, ..IntegratorConfig::from_camera(&cam) }
+            );
+            mean_red(&cam.render_to_buffer(&world, &HittableList::new(), integrator.as_ref()))
+        };
+
+        let expected: f64 = render(cam.max_depth);
+        let roulette: f64 = render(0);
+        assert!((roulette - expected).abs() < 0.03 * expected, "{} != {}", roulette, expected);
+    }
+
+    #[test]
+    fn next_event_estimation() {
+        // Lighting the same room through shadow rays to the ball must give the same brightness as
+        // only finding it by sampling the walls, with either heuristic, and far less noise.
+        let light: Arc<dyn Hittable> = Arc::new(Sphere::new_stationary(&Point3f::new(0.0, 1.5, -1.0), 0.3, Arc::new(DiffuseLight::from_color(&Color::new(5.0, 5.0, 5.0)))));
+        let mut world: HittableList = HittableList::new();
+        world.add(Arc::new(Sphere::new_stationary(&Point3f::ZERO, 3.0, Arc::new(Lambertian::from_color(&Color::new(0.8, 0.7, 0.6))))));
+        world.add(light.clone());
+        let mut lights: HittableList = HittableList::new();
+        lights.add(light);
+        let mut cam: Camera = Camera::new(
+            1.0, 16, 64, 10, &Color::ZERO, 90.0,
+            &Point3f::ZERO, &Point3f::new(0.0, 0.0, -1.0), &Vec3f::E2, 0.0, 1.0
+        );
+        let mut render = |lights: &HittableList, heuristic: MisHeuristic, seed: u64| -> Vec<f64> {
+            cam.seed = seed;
+            let integrator: Box<dyn Integrator> = IntegratorMethod::Path.integrator(
+                &IntegratorConfig { heuristic, ..IntegratorConfig::from_camera(&cam) }
+            );
+            cam.render_to_buffer(&world, lights, integrator.as_ref()).pixels().iter().map(|pixel| pixel.component(Axis::X)).collect()
+        };
+        let mean = |image: &[f64]| -> f64 { image.iter().sum::<f64>() / image.len() as f64 };
+        let noise = |a: &[f64], b: &[f64]| -> f64 { a.iter().zip(b).map(|(x, y)| (x - y).powi(2)).sum::<f64>() / a.len() as f64 };
+
+        let unlit: (Vec<f64>, Vec<f64>) = (render(&HittableList::new(), MisHeuristic::Power, 1), render(&HittableList::new(), MisHeuristic::Power, 2));
+        let power: (Vec<f64>, Vec<f64>) = (render(&lights, MisHeuristic::Power, 1), render(&lights, MisHeuristic::Power, 2));
+        let balance: Vec<f64> = render(&lights, MisHeuristic::Balance, 1);
+
+        let expected: f64 = mean(&unlit.0);
+        for image in [&power.0, &balance] {
+            assert!((mean(image) - expected).abs() < 0.03 * expected, "{} != {}", mean(image), expected);
+        }
+        assert!(noise(&power.0, &power.1) < 0.2 * noise(&unlit.0, &unlit.1), "{} vs {}", noise(&power.0, &power.1), noise(&unlit.0, &unlit.1));
+    }
+
+    #[test]
+    fn bidirectional() {
+        // A closed room lit by a small ball, with a glass ball and a mirror in it: joining paths
+        // from both ends must give the same image as the path tracer, splats included, with or
+        // without a lens. The walls are dark enough that cutting paths off at max_depth, which
+        // the path tracer's shadow rays count one segment later, makes no difference. Like in the
+        // scenes, the lights list only holds the light's shape.
+        let light_center: Point3f = Point3f::new(0.0, 2.0, -1.0);
+        let light: Arc<dyn Hittable> = Arc::new(Sphere::new_stationary(&light_center, 0.3, Arc::new(DiffuseLight::from_color(&Color::new(5.0, 5.0, 5.0)))));
+        let mut world: HittableList = HittableList::new();
+        world.add(Arc::new(Sphere::new_stationary(&Point3f::ZERO, 3.0, Arc::new(Lambertian::from_color(&Color::new(0.4, 0.3, 0.2))))));
+        world.add(Arc::new(Sphere::new_stationary(&Point3f::new(0.5, -1.0, -1.5), 0.8, Arc::new(Dielectric::new(1.5)))));
+        world.add(Arc::new(Sphere::new_stationary(&Point3f::new(-1.2, -0.5, -1.5), 0.6, Arc::new(Metal::new(&Color::new(0.9, 0.9, 0.9), 0.0)))));
+        world.add(light);
+        let lights: HittableList = HittableList::from_object(Arc::new(Sphere::new_stationary(&light_center, 0.3, Arc::new(Empty))));
+        let mut cam: Camera = Camera::new(
+            1.0, 12, 128, 8, &Color::ZERO, 90.0,
+            &Point3f::new(0.0, 0.0, 1.5), &Point3f::new(0.0, 0.0, -1.0), &Vec3f::E2, 0.0, 1.0
+        );
+        let mut render = |method: IntegratorMethod, defocus_angle: f64| -> Film {
+            cam.defocus_angle = defocus_angle;
+            cam.initialize();
+            let integrator: Box<dyn Integrator> = method.integrator(&IntegratorConfig::from_camera(&cam));
+            cam.render_to_buffer(&world, &lights, integrator.as_ref())
+        };
+        for defocus_angle in [0.0, 10.0] {
+            let path: Film = render(IntegratorMethod::Path, defocus_angle);
+            let bidirectional: Film = render(IntegratorMethod::Bidirectional, defocus_angle);
+            assert!((mean_red(&bidirectional) - mean_red(&path)).abs() < 0.03 * mean_red(&path), "{} != {}", mean_red(&bidirectional), mean_red(&path));
+        }
+
+        // Rendering through radiance alone, without splats, must still find the same light.
+        struct RadianceOnly(Box<dyn Integrator>);
+        impl Integrator for RadianceOnly {
+            fn radiance(&self, ray: &Ray, scene: &Scene, sampler: &mut dyn Sampler) -> Color {
+                self.0.radiance(ray, scene, sampler)
+            }
+        }
+        cam.defocus_angle = 0.0;
+        cam.initialize();
+        let config: IntegratorConfig = IntegratorConfig::from_camera(&cam);
+        let path: Film = cam.render_to_buffer(&world, &lights, IntegratorMethod::Path.integrator(&config).as_ref());
+        let radiance: Film = cam.render_to_buffer(&world, &lights, &RadianceOnly(IntegratorMethod::Bidirectional.integrator(&config)));
+        assert!((mean_red(&radiance) - mean_red(&path)).abs() < 0.03 * mean_red(&path), "{} != {}", mean_red(&radiance), mean_red(&path));
+    }
+
+    #[test]
+    fn bidirectional_zero_depth() {
+        // With no segments allowed, no light reaches the camera, and neither subpath is extended.
+        let light: Arc<dyn Hittable> = Arc::new(Sphere::new_stationary(&Point3f::new(0.0, 0.0, -2.0), 0.5, Arc::new(DiffuseLight::from_color(&Color::ONE))));
+        let world: HittableList = HittableList::from_object(light.clone());
+        let lights: HittableList = HittableList::from_object(light);
+        let cam: Camera = Camera::new(
+            1.0, 4, 4, 0, &Color::ONE, 90.0, &Point3f::ZERO, &Point3f::new(0.0, 0.0, -1.0), &Vec3f::E2, 0.0, 1.0
+        );
+        let integrator: Box<dyn Integrator> = IntegratorMethod::Bidirectional.integrator(&IntegratorConfig::from_camera(&cam));
+        assert_eq!(mean_red(&cam.render_to_buffer(&world, &lights, integrator.as_ref())), 0.0);
+    }
+
+    #[test]
+    fn photon_mapping() {
+        // A closed room lit by a small ball through a glass ball: the photons that land around
+        // each pixel's point must add up to the light the path tracer finds, caustic included.
+        // The caustic is noisy for the path tracer, so it gets more samples.
+        let light_center: Point3f = Point3f::new(0.0, 2.0, -1.0);
+        let mut world: HittableList = HittableList::new();
+        world.add(Arc::new(Sphere::new_stationary(&Point3f::ZERO, 3.0, Arc::new(Lambertian::from_color(&Color::new(0.4, 0.3, 0.2))))));
+        world.add(Arc::new(Sphere::new_stationary(&Point3f::new(0.0, 0.5, -1.0), 0.8, Arc::new(Dielectric::new(1.5)))));
+        world.add(Arc::new(Sphere::new_stationary(&light_center, 0.3, Arc::new(DiffuseLight::from_color(&Color::new(5.0, 5.0, 5.0))))));
+        let lights: HittableList = HittableList::from_object(Arc::new(Sphere::new_stationary(&light_center, 0.3, Arc::new(Empty))));
+        let mut cam: Camera = Camera::new(
+            1.0, 12, 64, 8, &Color::ZERO, 90.0,
+            &Point3f::new(0.0, 0.0, 1.5), &Point3f::new(0.0, -1.0, -1.0), &Vec3f::E2, 0.0, 1.0
+        );
+        let config: IntegratorConfig = IntegratorConfig {
+            photons_per_iteration: Some(4000), photon_radius: Some(0.3), ..IntegratorConfig::from_camera(&cam)
+        };
+        let photons: Film = cam.render_to_buffer(&world, &lights, IntegratorMethod::PhotonMapping.integrator(&config).as_ref());
+        cam.samples_per_pixel = 1024;
+        cam.initialize();
+        let path: Film = cam.render_to_buffer(&world, &lights, IntegratorMethod::Path.integrator(&config).as_ref());
+        assert!((mean_red(&photons) - mean_red(&path)).abs() < 0.03 * mean_red(&path), "{} != {}", mean_red(&photons), mean_red(&path));
+    }
+
+    #[test]
+    fn photon_mapping_background() {
+        // A floor under a glass ball, lit only by the background: the caustic under the ball
+        // comes from photons sent in from the background.
+        let mut world: HittableList = HittableList::new();
+        world.add(Arc::new(Quad::new(
+            &Point3f::new(-2.0, -1.0, -2.0), &(4.0 * Vec3f::E3), &(4.0 * Vec3f::E1), Arc::new(Lambertian::from_color(&Color::new(0.6, 0.6, 0.6)))
+        )));
+        world.add(Arc::new(Sphere::new_stationary(&Point3f::ZERO, 0.7, Arc::new(Dielectric::new(1.5)))));
+        let lights: HittableList = HittableList::new();
+        let mut cam: Camera = Camera::new(
+            1.0, 12, 16, 8, &Color::new(0.8, 0.8, 0.8), 60.0,
+            &Point3f::new(0.0, 2.5, 1.5), &Point3f::new(0.0, -1.0, 0.0), &Vec3f::E2, 0.0, 1.0
+        );
+        let config: IntegratorConfig = IntegratorConfig {
+            photons_per_iteration: Some(4000), photon_radius: Some(0.2), ..IntegratorConfig::from_camera(&cam)
+        };
+        let photons: Film = cam.render_to_buffer(&world, &lights, IntegratorMethod::PhotonMapping.integrator(&config).as_ref());
+        cam.samples_per_pixel = 1024;
+        cam.initialize();
+        let path: Film = cam.render_to_buffer(&world, &lights, IntegratorMethod::Path.integrator(&config).as_ref());
+        assert!((mean_red(&photons) - mean_red(&path)).abs() < 0.03 * mean_red(&path), "{} != {}", mean_red(&photons), mean_red(&path));
+    }
+
+    #[test]
+    fn direct_light() {
+        // With one convex object under a light and a black background, every path that reaches
+        // the light does so right after the first bounce, so all three integrators agree.
+        let mut world: HittableList = HittableList::new();
+        world.add(Arc::new(Sphere::new_stationary(&Point3f::ZERO, 1.0, Arc::new(Lambertian::from_color(&Color::new(0.7, 0.5, 0.3))))));
+        let light: Arc<dyn Hittable> = Arc::new(Quad::new(
+            &Vec3f::new(-1.0, 3.0, -1.0), &(2.0 * Vec3f::E1), &(2.0 * Vec3f::E3), Arc::new(DiffuseLight::from_color(&Color::new(4.0, 4.0, 4.0)))
+        ));
+        world.add(light.clone());
+        let mut lights: HittableList = HittableList::new();
+        lights.add(light);
+
+        let cam: Camera = Camera::new(
+            1.0, 12, 64, 10, &Color::ZERO, 30.0,
+            &Vec3f::new(0.0, 1.0, 5.0), &Vec3f::ZERO, &Vec3f::E2, 0.0, 5.0
+        );
+        let render = |method: IntegratorMethod| -> f64 {
+            let integrator: Box<dyn Integrator> = method.integrator(&IntegratorConfig::from_camera(&cam));
+            mean_red(&cam.render_to_buffer(&world, &lights, integrator.as_ref()))
+        };
+        let path: f64 = render(IntegratorMethod::Path);
+        assert!(path > 0.0);
+        for method in [IntegratorMethod::Whitted, IntegratorMethod::DirectLighting] {
+            let mean: f64 = render(method);
+            assert!((mean - path).abs() < 0.03 * path, "{:?}: {} != {}", method, mean, path);
+        }
+    }
+
+    #[test]
+    fn ambient_occlusion() {
+        // A floor is unoccluded in the open, and half occluded in the corner under a wall.
+        let mut world: HittableList = HittableList::new();
+        let mat = Arc::new(Lambertian::from_color(&Color::ONE));
+        world.add(Arc::new(Quad::new(&Vec3f::new(-50.0, 0.0, -50.0), &(100.0 * Vec3f::E1), &(100.0 * Vec3f::E3), mat.clone())));
+        world.add(Arc::new(Quad::new(&Vec3f::new(-50.0, 0.0, -50.0), &(100.0 * Vec3f::E2), &(100.0 * Vec3f::E3), mat)));
+        let cam: Camera = Camera::new(
+            1.0, 1, 1, 1, &Color::ZERO, 90.0, &Point3f::ZERO, &Point3f::new(0.0, 0.0, -1.0), &Vec3f::E2, 0.0, 1.0
+        );
+        let scene: Scene = Scene { world: &world, lights: &HittableList::new(), background: Color::ZERO, camera: &cam };
+
+        let occlusion = |distance: Option<f64>, x: f64| -> f64 {
+            let integrator: Box<dyn Integrator> = IntegratorMethod::AmbientOcclusion.integrator(&IntegratorConfig {
+                occlusion_distance: distance, ..IntegratorConfig::from_camera(&cam)
+            });
+            let mut sampler: Box<dyn Sampler> = SamplerMethod::Sobol.sampler(256, 0, (1, 1));
+            let ray: Ray = Ray::new(&Vec3f::new(x, 1.0, 0.0), &-Vec3f::E2);
+            (0..256).map(
+                |index| {
+                    sampler.start_pixel_sample((0, 0), index);
+                    integrator.radiance(&ray, &scene, sampler.as_mut()).component(Axis::X)
+                }
+            ).sum::<f64>() / 256.0
+        };
+        assert_eq!(occlusion(Some(1.0), 0.0), 1.0);
+        assert!((occlusion(Some(1.0), -49.999) - 0.5).abs() < 0.03);
+        // By default, within a tenth of the scene's diagonal of about 170.
+        assert_eq!(occlusion(None, 30.0), 1.0);
+        assert!((occlusion(None, -49.999) - 0.5).abs() < 0.03);
+    }
+}
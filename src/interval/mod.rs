@@ -67,7 +67,7 @@ impl Interval {
 }
 
 
-impl<'ivl> Add<f64> for &'ivl Interval {
+impl Add<f64> for &Interval {
     type Output = Interval;
 
     fn add(self, other: f64) -> Interval {
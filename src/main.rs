@@ -1,65 +1,328 @@
-use std::path::Path;
-use std::sync::Arc;
+// Tests spell out boolean results with assert_eq! and exercise the operators taking references.
+#![cfg_attr(test, allow(clippy::bool_assert_comparison, clippy::op_ref, clippy::unnecessary_cast))]
+
+use std::path::PathBuf;
+use std::process;
 use std::time::{Duration, Instant};
 
+use clap::builder::PossibleValuesParser;
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser};
 use dotenv::dotenv;
-use log::info;
+use log::{debug, error, info, warn};
 
 pub mod aabb;
+pub mod animation;
 pub mod bvh_node;
 pub mod color;
 pub mod constant_medium;
+pub mod film;
+pub mod gltf_scene;
 pub mod camera;
 pub mod hittable;
 pub mod hittable_list;
+pub mod image_writer;
+pub mod instance;
+pub mod integrator;
 pub mod interval;
 pub mod material;
+pub mod matrix;
+pub mod mesh;
+pub mod obj;
 pub mod onb;
 pub mod pdf;
 pub mod perlin;
+pub mod photon_map;
 pub mod plane;
+pub mod ply;
+pub mod quaternion;
 pub mod ray;
+pub mod sampler;
+pub mod scene_file;
 pub mod scenes;
+pub mod shutter;
 pub mod sphere;
 pub mod texture;
 pub mod transform;
 pub mod utilities;
 pub mod vec3;
 
-use bvh_node::BVHNode;
-use hittable_list::HittableList;
+use animation::{Animation, Replacements};
+use bvh_node::{BVHConfig, BVHNode, BVHStats, SplitMethod};
+use camera::Camera;
+use film::Film;
+use image_writer::OutputFormat;
+use integrator::{Integrator, IntegratorConfig, IntegratorMethod, MisHeuristic};
+use sampler::SamplerMethod;
+use scenes::SceneBuilder;
 
 
-#[derive(Debug)]
+#[derive(Debug, Parser)]
+#[command(version, about = "Render one of the built-in scenes.")]
 pub struct Config {
+    /// Name of the built-in scene to render
+    #[arg(short, long, env = "SCENE", default_value = "cornell_smoke",
+          value_parser = PossibleValuesParser::new(scenes::names()))]
+    pub scene: String,
+
+    /// Render a scene description file (.toml) or a glTF 2.0 scene (.gltf, .glb) instead of a
+    /// built-in scene; takes precedence over --scene, including a SCENE set in the environment
+    #[arg(long, env = "SCENE_FILE")]
+    pub scene_file: Option<PathBuf>,
+
+    /// Path of the rendered image
+    #[arg(short, long, env = "OUTPUT_PATH", default_value = "test.ppm")]
+    pub output: PathBuf,
+
+    /// Render this many frames of the scene's animation as an image sequence, numbering each
+    /// output file, e.g. test_0000.ppm, test_0001.ppm, ...
+    #[arg(long, env = "FRAMES")]
+    pub frames: Option<u32>,
+
+    /// Frames per second of scene time in an image sequence
+    #[arg(long, env = "FRAME_RATE", default_value_t = 24.0)]
+    pub frame_rate: f64,
+
+    /// Image format; inferred from the output extension when omitted. PPM, PNG and JPEG are
+    /// gamma corrected; EXR, HDR and PFM keep the linear radiance
+    #[arg(short, long, env = "OUTPUT_FORMAT", value_enum)]
+    pub format: Option<OutputFormat>,
+
+    /// Override the scene's rendered image width in pixels
+    #[arg(long, env = "IMAGE_WIDTH")]
+    pub image_width: Option<u32>,
+
+    /// Override the scene's count of random samples for each pixel
+    #[arg(long, env = "SAMPLES_PER_PIXEL")]
+    pub samples_per_pixel: Option<u32>,
+
+    /// Override the scene's sampler, which spreads the random numbers of each pixel's samples
+    #[arg(long, env = "SAMPLER", value_enum)]
+    pub sampler: Option<SamplerMethod>,
+
+    /// Override the scene's maximum number of ray bounces
+    #[arg(long, env = "MAX_DEPTH")]
+    pub max_depth: Option<u32>,
+
+    /// Override the scene's number of bounces before paths that carry little light may be ended
+    /// at random (Russian roulette)
+    #[arg(long, env = "RUSSIAN_ROULETTE_DEPTH")]
+    pub russian_roulette_depth: Option<u32>,
+
+    /// Seed of the random numbers used to build procedural scenes and to sample the image; the
+    /// same seed renders the same image on any number of threads
+    #[arg(long, env = "SEED", default_value_t = 0)]
+    pub seed: u64,
+
+    /// Light transport algorithm
+    #[arg(long, env = "INTEGRATOR", value_enum, default_value = "path")]
+    pub integrator: IntegratorMethod,
+
+    /// How the path tracer weights light found by shadow rays against light found by sampling
+    /// materials
+    #[arg(long, env = "MIS_HEURISTIC", value_enum, default_value = "power")]
+    pub mis_heuristic: MisHeuristic,
+
+    /// Distance within which surfaces occlude each other for the ambient-occlusion integrator;
+    /// a tenth of the scene's size when omitted
+    #[arg(long, env = "OCCLUSION_DISTANCE")]
+    pub occlusion_distance: Option<f64>,
+
+    /// Photons sent out from the lights and the background in each iteration of photon mapping; the
+    /// image's pixel count when omitted
+    #[arg(long, env = "PHOTONS_PER_ITERATION", value_parser = clap::value_parser!(u32).range(1..))]
+    pub photons_per_iteration: Option<u32>,
+
+    /// Radius around each pixel's surface point that photon mapping first gathers photons from,
+    /// shrinking with every iteration; a hundredth of the scene's size when omitted
+    #[arg(long, env = "PHOTON_RADIUS")]
+    pub photon_radius: Option<f64>,
+
+    /// How the bounding volume hierarchy over the scene's objects is built
+    #[arg(long, env = "BVH", value_enum, default_value = "sah")]
+    pub bvh: SplitMethod,
+
+    /// Write the scene, after camera overrides, to a scene description file and exit without
+    /// rendering
+    #[arg(long)]
+    pub export_scene: Option<PathBuf>,
+
+    /// Print the names of the built-in scenes and exit
+    #[arg(long)]
+    pub list_scenes: bool,
+
+    /// Log filter, in env_logger syntax
+    #[arg(long, env = "RUST_LOG", default_value = "info")]
     pub rust_log: String,
 }
 
 impl Config {
     pub fn init() -> Config {
-        Config {
-            rust_log: std::env::var("RUST_LOG").expect("RUST_LOG must be specified"),
+        // Command-line arguments take precedence over the environment, which is populated from
+        // `.env` before parsing.
+        let config: Config = Config::parse();
+        let renders: bool = !config.list_scenes && config.export_scene.is_none();
+        if renders && config.output_format().is_none() {
+            Config::command().error(
+                ErrorKind::InvalidValue,
+                format!("cannot infer an image format from '{}'; pass --format", config.output.display())
+            ).exit();
+        }
+        if !(config.frame_rate > 0.0 && config.frame_rate.is_finite()) {
+            Config::command().error(ErrorKind::InvalidValue, "--frame-rate must be positive").exit();
+        }
+        if config.photon_radius.is_some_and(|radius| !(radius > 0.0 && radius.is_finite())) {
+            Config::command().error(ErrorKind::InvalidValue, "--photon-radius must be positive").exit();
+        }
+        config
+    }
+
+    pub fn output_format(&self) -> Option<OutputFormat> {
+        self.format.or_else(|| OutputFormat::from_extension(&self.output))
+    }
+
+    pub fn frame_path(&self, frame: u32) -> PathBuf {
+        // The frame number goes between the file stem and the extension.
+        let stem: String = self.output.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+        let mut filename: String = format!("{}_{:04}", stem, frame);
+        if let Some(extension) = self.output.extension() {
+            filename = format!("{}.{}", filename, extension.to_string_lossy());
         }
+        self.output.with_file_name(filename)
+    }
+
+    pub fn apply_camera_overrides(&self, cam: &mut Camera) {
+        if let Some(image_width) = self.image_width {
+            cam.image_width = image_width;
+        }
+        if let Some(samples_per_pixel) = self.samples_per_pixel {
+            cam.samples_per_pixel = samples_per_pixel;
+        }
+        if let Some(max_depth) = self.max_depth {
+            cam.max_depth = max_depth;
+        }
+        if let Some(russian_roulette_depth) = self.russian_roulette_depth {
+            cam.russian_roulette_depth = russian_roulette_depth;
+        }
+        if let Some(sampler) = self.sampler {
+            cam.sampler = sampler;
+        }
+        cam.seed = self.seed;
+        cam.initialize();
     }
 }
 
 
 fn main() {
     dotenv().ok();
-    env_logger::init();
+    let config: Config = Config::init();
+    env_logger::Builder::new().parse_filters(&config.rust_log).init();
 
-    let now: Instant = Instant::now();
+    if config.list_scenes {
+        for name in scenes::names() {
+            println!("{}", name);
+        }
+        return;
+    }
 
-    // Output
-    let output_filepath: &Path = Path::new("test.ppm");
+    let now: Instant = Instant::now();
+    utilities::seed(config.seed);
 
     // World + Camera
-    let (mut scene, lights, cam ) = scenes::cornell_smoke();
-    let bvh_scene: Arc<BVHNode> = Arc::new(BVHNode::from_hittable_list(&mut scene));
-    let world: HittableList = HittableList::from_object(bvh_scene);
+    let (mut scene, mut lights, mut cam, mut animation) = match &config.scene_file {
+        Some(scene_filepath) => {
+            info!("Loading scene file {}", scene_filepath.display());
+            let is_gltf: bool = scene_filepath.extension().and_then(|extension| extension.to_str())
+                .is_some_and(|extension| extension.eq_ignore_ascii_case("gltf") || extension.eq_ignore_ascii_case("glb"));
+            let loaded = if is_gltf {
+                gltf_scene::load(scene_filepath).map(|(scene, lights, cam)| (scene, lights, cam, Animation::default()))
+            }
+            else {
+                scene_file::load_animated(scene_filepath)
+            };
+            loaded.unwrap_or_else(
+                |err| {
+                    error!("Failed to load scene: {}", err);
+                    process::exit(1);
+                }
+            )
+        },
+        None => {
+            let scene_builder: SceneBuilder = scenes::from_name(&config.scene).expect("scene names are validated by the parser");
+            info!("Building scene {}", config.scene);
+            let (scene, lights, cam) = scene_builder();
+            (scene, lights, cam, Animation::default())
+        }
+    };
+    config.apply_camera_overrides(&mut cam);
+
+    if let Some(export_filepath) = &config.export_scene {
+        info!("Writing scene to {}", export_filepath.display());
+        if let Err(err) = scene_file::save(export_filepath, &scene, &lights, &cam) {
+            error!("Failed to export scene: {}", err);
+            process::exit(1);
+        }
+        return;
+    }
+
+    let bvh_start: Instant = Instant::now();
+    let (mut bvh_scene, bvh_stats): (BVHNode, BVHStats) = BVHNode::build(&mut scene.objects, &BVHConfig::from_method(config.bvh));
+    info!("Built {:?} BVH in {:.2?}: {}", config.bvh, bvh_start.elapsed(), bvh_stats);
+
+    // The integrator's settings come from the camera, which animation doesn't change.
+    let integrator_config: IntegratorConfig = IntegratorConfig {
+        heuristic: config.mis_heuristic,
+        occlusion_distance: config.occlusion_distance,
+        photons_per_iteration: config.photons_per_iteration,
+        photon_radius: config.photon_radius,
+        ..IntegratorConfig::from_camera(&cam)
+    };
+    let integrator: Box<dyn Integrator> = config.integrator.integrator(&integrator_config);
+    let output_format: OutputFormat = config.output_format().expect("output format is validated by Config::init");
+    let save = |film: &Film, filepath: &PathBuf| {
+        info!("Writing image to {}", filepath.display());
+        if let Err(err) = film.save(filepath, output_format.writer().as_ref()) {
+            error!("Failed to write {}: {}", filepath.display(), err);
+            process::exit(1);
+        }
+    };
+
+    match config.frames {
+        None => save(&cam.render_to_buffer(&bvh_scene, &lights, integrator.as_ref()), &config.output),
+        Some(frame_count) => {
+            if animation.is_empty() {
+                warn!("The scene is not animated, so every frame will be the same");
+            }
+            for frame in 0..frame_count {
+                let time: f64 = frame as f64 / config.frame_rate;
+                info!("Rendering frame {} at {:.3}s", frame, time);
+                let replacements: Replacements = animation.set_time(time, 1.0 / config.frame_rate, &cam.shutter).unwrap_or_else(
+                    |err| {
+                        error!("Failed to animate frame {}: {}", frame, err);
+                        process::exit(1);
+                    }
+                );
+
+                // Only the animated objects change, so refitting the BVH is usually enough.
+                if !replacements.is_empty() {
+                    bvh_scene.update_objects(|object| replacements.get(object));
+                    if bvh_scene.needs_rebuild() {
+                        let rebuild_start: Instant = Instant::now();
+                        let stats: BVHStats = bvh_scene.rebuild();
+                        debug!("Rebuilt BVH in {:.2?}: {}", rebuild_start.elapsed(), stats);
+                    }
+                    lights = replacements.apply(&lights);
+                }
+                animation.camera.apply(&mut cam, time);
+                config.apply_camera_overrides(&mut cam);
+                // Each frame gets its own noise, or it would stay fixed on screen as things move.
+                cam.seed = utilities::hash(&[config.seed, frame as u64]);
+
+                save(&cam.render_to_buffer(&bvh_scene, &lights, integrator.as_ref()), &config.frame_path(frame));
+            }
+        }
+    }
 
-    cam.render(&world, &lights, output_filepath);
-    
     let elapsed: Duration = now.elapsed();
     info!("Done. Time elapsed {:.2?}", elapsed);
-}
\ No newline at end of file
+}
@@ -1,4 +1,5 @@
 use core::f64;
+use std::any::Any;
 use std::fmt;
 use std::sync::Arc;
 
@@ -7,7 +8,6 @@ use crate::hittable::HitRecord;
 use crate::pdf::{CosinePDF, EmptyPDF, SpherePDF, PDF};
 use crate::ray::Ray;
 use crate::texture::{Texture, Solid};
-use crate::utilities;
 use crate::vec3::{Point3f, Vec3f};
 
 
@@ -18,8 +18,10 @@ pub struct ScatterRecord {
     pub skip_pdf_ray: Ray
 }
 
-pub trait Material: Send + Sync + fmt::Display {
-    fn scatter(&self, _ray_in: &Ray, _rec: &HitRecord) -> Option<ScatterRecord> {
+pub trait Material: Any + Send + Sync + fmt::Display {
+    // u is a 2D sample in [0, 1)^2 for materials that pick the scattered ray themselves; the others
+    // return a PDF to draw it from.
+    fn scatter(&self, _ray_in: &Ray, _rec: &HitRecord, _u: (f64, f64)) -> Option<ScatterRecord> {
         None
     }
 
@@ -30,6 +32,12 @@ pub trait Material: Send + Sync + fmt::Display {
     fn scattering_pdf(&self, _ray_in: &Ray, _rec: &HitRecord, _scattered: &Ray) -> f64 {
         0.0
     }
+
+    // Phase functions of participating media scatter from a point inside the volume, in every
+    // direction, rather than off a surface.
+    fn scatters_in_volume(&self) -> bool {
+        false
+    }
 }
 
 
@@ -63,12 +71,16 @@ impl Lambertian {
     pub fn from_texture(texture: Arc<dyn Texture>) -> Self {
         Self { texture }
     }
+
+    pub fn texture(&self) -> &Arc<dyn Texture> {
+        &self.texture
+    }
 }
 
 impl Material for Lambertian {
-    fn scatter(&self, _ray_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+    fn scatter(&self, _ray_in: &Ray, rec: &HitRecord, _u: (f64, f64)) -> Option<ScatterRecord> {
         let scatter_rec: ScatterRecord = ScatterRecord { 
-            attenuation: self.texture.value(rec.uv, &rec.point), 
+            attenuation: self.texture.value_at(rec), 
             pdf_ptr: Arc::new(CosinePDF::new(&rec.normal)), 
             skip_pdf: false, 
             skip_pdf_ray: Ray::ZERO
@@ -77,8 +89,10 @@ impl Material for Lambertian {
     }
 
     fn scattering_pdf(&self, _ray_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        // Directions below the geometric surface would leak light through it, even when they are
+        // above an interpolated shading normal.
         let cos_theta: f64 = Vec3f::dot(&rec.normal, &Vec3f::unit_vector(scattered.direction()));
-        if cos_theta < 0.0 {
+        if cos_theta < 0.0 || Vec3f::dot(&rec.geometric_normal, scattered.direction()) <= 0.0 {
             return 0.0;
         }
         cos_theta / f64::consts::PI
@@ -87,7 +101,7 @@ impl Material for Lambertian {
 
 
 pub struct Metal {
-    albedo: Color,
+    albedo: Arc<dyn Texture>,
     fuzz: f64
 }
 
@@ -99,20 +113,36 @@ impl fmt::Display for Metal {
 
 impl Metal {
     pub fn new(albedo: &Color, fuzz: f64) -> Self {
+        Self::from_texture(Arc::new(Solid::new(albedo)), fuzz)
+    }
+
+    pub fn from_texture(albedo: Arc<dyn Texture>, fuzz: f64) -> Self {
         Self {
-            albedo: *albedo,
+            albedo,
             fuzz: fuzz.min(1.0)
         }
     }
+
+    pub fn albedo(&self) -> &Arc<dyn Texture> {
+        &self.albedo
+    }
+
+    pub fn fuzz(&self) -> f64 {
+        self.fuzz
+    }
 }
 
 impl Material for Metal {
-    fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord, u: (f64, f64)) -> Option<ScatterRecord> {
         let mut reflected: Vec3f = Vec3f::reflect(ray_in.direction(), &rec.normal);
-        reflected = Vec3f::unit_vector(&reflected) + self.fuzz * Vec3f::random_unit_vector();
+        reflected = Vec3f::unit_vector(&reflected) + self.fuzz * Vec3f::sample_unit_sphere(u);
+        if Vec3f::dot(&reflected, &rec.geometric_normal) <= 0.0 {
+            // Absorb reflections that would pass through the surface.
+            return None;
+        }
         
         let scatter_rec: ScatterRecord = ScatterRecord { 
-            attenuation: self.albedo, 
+            attenuation: self.albedo.value_at(rec), 
             pdf_ptr: Arc::new(EmptyPDF), 
             skip_pdf: true, 
             skip_pdf_ray: Ray::with_time(&rec.point, &reflected, ray_in.time())
@@ -141,10 +171,14 @@ impl Dielectric {
         let r0: f64 = ((1.0 - refractive_index) / (1.0 + refractive_index)).powi(2);
         r0 + (1.0 - r0) * (1.0 - cosine).powi(5) 
     }
+
+    pub fn refractive_index(&self) -> f64 {
+        self.refractive_index
+    }
 }
 
 impl Material for Dielectric {
-    fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord, u: (f64, f64)) -> Option<ScatterRecord> {
         let ri: f64 = if rec.front_face {
             1.0 / self.refractive_index
         } 
@@ -157,7 +191,7 @@ impl Material for Dielectric {
         let sin_theta: f64 = (1.0 - cos_theta*cos_theta).sqrt();
 
         let cannot_refract: bool = ri * sin_theta > 1.0;
-        let direction: Vec3f = if cannot_refract || Dielectric::reflectance(cos_theta, ri) > utilities::random() {
+        let direction: Vec3f = if cannot_refract || Dielectric::reflectance(cos_theta, ri) > u.0 {
             Vec3f::reflect(&unit_direction, &rec.normal)
         } 
         else {            
@@ -193,14 +227,18 @@ impl DiffuseLight {
     pub fn from_texture(texture: Arc<dyn Texture>) -> Self {
         Self { texture }
     }
+
+    pub fn texture(&self) -> &Arc<dyn Texture> {
+        &self.texture
+    }
 }
 
 impl Material for DiffuseLight {
-    fn emitted(&self, _ray_in: &Ray, rec: &HitRecord, uv: (f64, f64), point: &Point3f) -> Color {
+    fn emitted(&self, _ray_in: &Ray, rec: &HitRecord, _uv: (f64, f64), _point: &Point3f) -> Color {
         if !rec.front_face {
             return Color::ZERO;
         }
-        self.texture.value(uv, point)
+        self.texture.value_at(rec)
     }
 }
 
@@ -223,12 +261,16 @@ impl Isotropic {
     pub fn from_texture(texture: Arc<dyn Texture>) -> Self {
         Self { texture }
     }
+
+    pub fn texture(&self) -> &Arc<dyn Texture> {
+        &self.texture
+    }
 }
 
 impl Material for Isotropic {
-    fn scatter(&self, _ray_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+    fn scatter(&self, _ray_in: &Ray, rec: &HitRecord, _u: (f64, f64)) -> Option<ScatterRecord> {
         let scatter_rec: ScatterRecord = ScatterRecord { 
-            attenuation: self.texture.value(rec.uv, &rec.point), 
+            attenuation: self.texture.value_at(rec), 
             pdf_ptr: Arc::new(SpherePDF), 
             skip_pdf: false, 
             skip_pdf_ray: Ray::ZERO
@@ -239,4 +281,8 @@ impl Material for Isotropic {
     fn scattering_pdf(&self, _ray_in: &Ray, _rec: &HitRecord, _scattered: &Ray) -> f64 {
         1.0 / (4.0 * f64::consts::PI)
     }
+
+    fn scatters_in_volume(&self) -> bool {
+        true
+    }
 }
\ No newline at end of file
@@ -0,0 +1,290 @@
+use std::fmt;
+use std::ops::Mul;
+
+use serde::{Deserialize, Serialize};
+
+use crate::utilities;
+use crate::vec3::{Axis, Point3f, Vec3f};
+
+// A 4x4 matrix acting on column vectors, stored by rows: m[row][column]. Points are transformed
+// with an implicit w of 1 and vectors with a w of 0.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct Matrix4 {
+    m: [[f64; 4]; 4]
+}
+
+impl fmt::Display for Matrix4 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.m)
+    }
+}
+
+impl Default for Matrix4 {
+    fn default() -> Self {
+        Matrix4::IDENTITY
+    }
+}
+
+impl Matrix4 {
+    pub const IDENTITY: Matrix4 = Matrix4 {
+        m: [
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0]
+        ]
+    };
+
+    pub fn new(rows: [[f64; 4]; 4]) -> Self {
+        Self { m: rows }
+    }
+
+    pub fn from_columns(columns: [[f64; 4]; 4]) -> Self {
+        Self::new(columns).transpose()
+    }
+
+    pub fn translation(offset: &Vec3f) -> Self {
+        let mut matrix: Self = Self::IDENTITY;
+        for &axis in Axis::iterator() {
+            matrix.m[axis as usize][3] = offset.component(axis);
+        }
+        matrix
+    }
+
+    pub fn scaling(factors: &Vec3f) -> Self {
+        let mut matrix: Self = Self::IDENTITY;
+        for &axis in Axis::iterator() {
+            matrix.m[axis as usize][axis as usize] = factors.component(axis);
+        }
+        matrix
+    }
+
+    pub fn rotation(axis: Axis, degrees: f64) -> Self {
+        // Counter-clockwise rotation when looking down the axis towards the origin.
+        let radians: f64 = utilities::degrees_to_radians(degrees);
+        let (sin, cos) = radians.sin_cos();
+        let (a, b) = match axis {
+            Axis::X => (1, 2),
+            Axis::Y => (2, 0),
+            Axis::Z => (0, 1),
+        };
+        let mut matrix: Self = Self::IDENTITY;
+        matrix.m[a][a] = cos;
+        matrix.m[a][b] = -sin;
+        matrix.m[b][a] = sin;
+        matrix.m[b][b] = cos;
+        matrix
+    }
+
+    pub fn euler_rotation(degrees: &Vec3f) -> Self {
+        // Rotates about X first, then Y, then Z.
+        Self::rotation(Axis::Z, degrees.component(Axis::Z))
+            * Self::rotation(Axis::Y, degrees.component(Axis::Y))
+            * Self::rotation(Axis::X, degrees.component(Axis::X))
+    }
+
+    pub fn shearing(xy: f64, xz: f64, yx: f64, yz: f64, zx: f64, zy: f64) -> Self {
+        // Each coordinate moves in proportion to the other two, e.g. x' = x + xy * y + xz * z.
+        Self::new([
+            [1.0, xy, xz, 0.0],
+            [yx, 1.0, yz, 0.0],
+            [zx, zy, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0]
+        ])
+    }
+
+    #[inline]
+    pub fn rows(&self) -> [[f64; 4]; 4] {
+        self.m
+    }
+
+    #[inline]
+    pub fn get(&self, row: usize, column: usize) -> f64 {
+        self.m[row][column]
+    }
+
+    pub fn transpose(&self) -> Self {
+        let mut transposed: Self = Self::IDENTITY;
+        for row in 0..4 {
+            for column in 0..4 {
+                transposed.m[column][row] = self.m[row][column];
+            }
+        }
+        transposed
+    }
+
+    pub fn determinant3(&self) -> f64 {
+        // Determinant of the upper-left 3x3 block, i.e. of the linear part of an affine transform.
+        let m = &self.m;
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+
+    pub fn inverse3(&self) -> Option<Self> {
+        // Inverse of a linear transform, from the adjugate of its upper-left 3x3 block; None for a
+        // singular one. The translation is ignored.
+        let det: f64 = self.determinant3();
+        if det == 0.0 || !det.is_finite() {
+            return None;
+        }
+        let m = &self.m;
+        let cofactor = |row: usize, column: usize| -> f64 {
+            let (r0, r1) = ((row + 1) % 3, (row + 2) % 3);
+            let (c0, c1) = ((column + 1) % 3, (column + 2) % 3);
+            m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0]
+        };
+        let mut inverse: Self = Self::IDENTITY;
+        for row in 0..3 {
+            for column in 0..3 {
+                inverse.m[row][column] = cofactor(column, row) / det;
+            }
+        }
+        Some(inverse)
+    }
+
+    pub fn inverse(&self) -> Option<Self> {
+        // Gauss-Jordan elimination with partial pivoting; None for a singular matrix.
+        let mut a: [[f64; 4]; 4] = self.m;
+        let mut inverse: [[f64; 4]; 4] = Self::IDENTITY.m;
+
+        for column in 0..4 {
+            let pivot_row: usize = (column..4).max_by(
+                |&i, &j| a[i][column].abs().total_cmp(&a[j][column].abs())
+            )?;
+            if a[pivot_row][column] == 0.0 || !a[pivot_row][column].is_finite() {
+                return None;
+            }
+            a.swap(column, pivot_row);
+            inverse.swap(column, pivot_row);
+
+            let recip_pivot: f64 = 1.0 / a[column][column];
+            for k in 0..4 {
+                a[column][k] *= recip_pivot;
+                inverse[column][k] *= recip_pivot;
+            }
+
+            for row in 0..4 {
+                if row == column {
+                    continue;
+                }
+                let factor: f64 = a[row][column];
+                if factor == 0.0 {
+                    continue;
+                }
+                for k in 0..4 {
+                    a[row][k] -= factor * a[column][k];
+                    inverse[row][k] -= factor * inverse[column][k];
+                }
+            }
+        }
+        Some(Self { m: inverse })
+    }
+
+    #[inline]
+    pub fn transform_point(&self, point: &Point3f) -> Point3f {
+        self.transform_vector(point) + Vec3f::new(self.m[0][3], self.m[1][3], self.m[2][3])
+    }
+
+    #[inline]
+    pub fn transform_vector(&self, v: &Vec3f) -> Vec3f {
+        let m = &self.m;
+        let (x, y, z) = (v.component(Axis::X), v.component(Axis::Y), v.component(Axis::Z));
+        Vec3f::new(
+            m[0][0] * x + m[0][1] * y + m[0][2] * z,
+            m[1][0] * x + m[1][1] * y + m[1][2] * z,
+            m[2][0] * x + m[2][1] * y + m[2][2] * z
+        )
+    }
+
+    #[inline]
+    pub fn transform_vector_transposed(&self, v: &Vec3f) -> Vec3f {
+        // Multiplies by the transpose of the linear part, without building it.
+        let m = &self.m;
+        let (x, y, z) = (v.component(Axis::X), v.component(Axis::Y), v.component(Axis::Z));
+        Vec3f::new(
+            m[0][0] * x + m[1][0] * y + m[2][0] * z,
+            m[0][1] * x + m[1][1] * y + m[2][1] * z,
+            m[0][2] * x + m[1][2] * y + m[2][2] * z
+        )
+    }
+}
+
+impl Mul for Matrix4 {
+    type Output = Matrix4;
+
+    fn mul(self, other: Matrix4) -> Matrix4 {
+        let mut product: Matrix4 = Matrix4 { m: [[0.0; 4]; 4] };
+        for row in 0..4 {
+            for column in 0..4 {
+                product.m[row][column] = (0..4).map(|k| self.m[row][k] * other.m[k][column]).sum();
+            }
+        }
+        product
+    }
+}
+
+impl Mul for &Matrix4 {
+    type Output = Matrix4;
+
+    #[inline]
+    fn mul(self, other: &Matrix4) -> Matrix4 {
+        *self * *other
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::matrix::*;
+
+    fn assert_near(a: &Matrix4, b: &Matrix4) {
+        for row in 0..4 {
+            for column in 0..4 {
+                assert!((a.get(row, column) - b.get(row, column)).abs() < 1e-12, "{} != {}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn rotation() {
+        let rotated: Vec3f = Matrix4::rotation(Axis::Y, 90.0).transform_vector(&Vec3f::E1);
+        assert!((rotated - -Vec3f::E3).length() < 1e-12);
+        let rotated: Vec3f = Matrix4::rotation(Axis::Z, 90.0).transform_vector(&Vec3f::E1);
+        assert!((rotated - Vec3f::E2).length() < 1e-12);
+        let rotated: Vec3f = Matrix4::rotation(Axis::X, 90.0).transform_vector(&Vec3f::E2);
+        assert!((rotated - Vec3f::E3).length() < 1e-12);
+
+        // X first: E2 goes to E3, which a Z rotation leaves alone.
+        let rotated: Vec3f = Matrix4::euler_rotation(&Vec3f::new(90.0, 0.0, 90.0)).transform_vector(&Vec3f::E2);
+        assert!((rotated - Vec3f::E3).length() < 1e-12);
+    }
+
+    #[test]
+    fn composition() {
+        let translate: Matrix4 = Matrix4::translation(&Vec3f::new(1.0, 2.0, 3.0));
+        let scale: Matrix4 = Matrix4::scaling(&Vec3f::new(2.0, 2.0, 2.0));
+        let p: Point3f = Point3f::ONE;
+        assert_eq!((translate * scale).transform_point(&p), Point3f::new(3.0, 4.0, 5.0));
+        assert_eq!((scale * translate).transform_point(&p), Point3f::new(4.0, 6.0, 8.0));
+        assert_eq!((translate * scale).transform_vector(&p), Vec3f::new(2.0, 2.0, 2.0));
+    }
+
+    #[test]
+    fn inverse() {
+        let m: Matrix4 = Matrix4::translation(&Vec3f::new(1.0, -2.0, 3.0))
+            * Matrix4::euler_rotation(&Vec3f::new(10.0, 20.0, 30.0))
+            * Matrix4::shearing(0.5, 0.0, 0.0, 0.25, 0.0, 0.0)
+            * Matrix4::scaling(&Vec3f::new(2.0, 0.5, 4.0));
+        assert_near(&(m * m.inverse().unwrap()), &Matrix4::IDENTITY);
+        assert_near(&(m.inverse().unwrap() * m), &Matrix4::IDENTITY);
+        assert!((m.determinant3() - 4.0).abs() < 1e-12);
+
+        let linear: Matrix4 = Matrix4::euler_rotation(&Vec3f::new(10.0, 20.0, 30.0)) * Matrix4::shearing(0.5, 0.0, 0.0, 0.25, 0.0, 0.0);
+        assert_near(&linear.inverse3().unwrap(), &linear.inverse().unwrap());
+
+        assert_eq!(Matrix4::scaling(&Vec3f::new(1.0, 0.0, 1.0)).inverse(), None);
+        assert_eq!(Matrix4::scaling(&Vec3f::new(1.0, 0.0, 1.0)).inverse3(), None);
+    }
+}
@@ -0,0 +1,318 @@
+use std::fmt;
+use std::sync::Arc;
+
+use crate::aabb::AABB;
+use crate::bvh_node::{BVHConfig, BVHNode};
+use crate::hittable::{FaceHit, HitRecord, Hittable, SurfaceSample};
+use crate::interval::Interval;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::vec3::{Point3f, Vec3f};
+
+// Vertex and index buffers shared by every triangle of a mesh.
+struct MeshData {
+    positions: Vec<Point3f>,
+    normals: Vec<Vec3f>,        // Per-vertex normals, or empty for flat shading
+    uvs: Vec<(f64, f64)>,       // Per-vertex texture coordinates, or empty
+    indices: Vec<[u32; 3]>,
+    mat: Arc<dyn Material>
+}
+
+impl MeshData {
+    #[inline]
+    fn vertices(&self, face: usize) -> [usize; 3] {
+        let [i0, i1, i2] = self.indices[face];
+        [i0 as usize, i1 as usize, i2 as usize]
+    }
+
+    fn area(&self, face: usize) -> f64 {
+        let [i0, i1, i2] = self.vertices(face);
+        let edge1: Vec3f = self.positions[i1] - self.positions[i0];
+        let edge2: Vec3f = self.positions[i2] - self.positions[i0];
+        0.5 * Vec3f::cross(&edge1, &edge2).length()
+    }
+
+    fn normal(&self, face: usize) -> Vec3f {
+        let [i0, i1, i2] = self.vertices(face);
+        Vec3f::unit_vector(&Vec3f::cross(&(self.positions[i1] - self.positions[i0]), &(self.positions[i2] - self.positions[i0])))
+    }
+
+    fn sample_point(&self, face: usize, u: (f64, f64)) -> Point3f {
+        // Uniformly distributed point on the triangle.
+        let su: f64 = f64::sqrt(u.0);
+        let b1: f64 = 1.0 - su;
+        let b2: f64 = u.1 * su;
+        let [i0, i1, i2] = self.vertices(face);
+        (1.0 - b1 - b2) * self.positions[i0] + b1 * self.positions[i1] + b2 * self.positions[i2]
+    }
+}
+
+
+// A single face of a mesh; it only refers to the shared buffers so the mesh BVH stays small.
+struct MeshTriangle {
+    mesh: Arc<MeshData>,
+    face: usize,
+    bounding_box: AABB
+}
+
+impl MeshTriangle {
+    fn new(mesh: Arc<MeshData>, face: usize) -> Self {
+        let [i0, i1, i2] = mesh.vertices(face);
+        let bounding_box: AABB = AABB::from_bounding_box(
+            &AABB::from_point(&mesh.positions[i0], &mesh.positions[i1]),
+            &AABB::from_point(&mesh.positions[i0], &mesh.positions[i2])
+        );
+        Self { mesh, face, bounding_box }
+    }
+}
+
+impl Hittable for MeshTriangle {
+    fn hit(&self, ray: &Ray, ray_t: &Interval) -> Option<HitRecord> {
+        // Möller-Trumbore intersection, which yields the barycentric coordinates (b1, b2) of the
+        // hit point along with t.
+        let mesh: &MeshData = &self.mesh;
+        let [i0, i1, i2] = mesh.vertices(self.face);
+        let edge1: Vec3f = mesh.positions[i1] - mesh.positions[i0];
+        let edge2: Vec3f = mesh.positions[i2] - mesh.positions[i0];
+
+        let pvec: Vec3f = Vec3f::cross(ray.direction(), &edge2);
+        let det: f64 = Vec3f::dot(&edge1, &pvec);
+
+        // No hit if the ray is parallel to the triangle.
+        if det.abs() < 1e-12 {
+            return None;
+        }
+        let inv_det: f64 = 1.0 / det;
+
+        let tvec: Vec3f = ray.origin() - mesh.positions[i0];
+        let b1: f64 = Vec3f::dot(&tvec, &pvec) * inv_det;
+        if !(0.0..=1.0).contains(&b1) {
+            return None;
+        }
+
+        let qvec: Vec3f = Vec3f::cross(&tvec, &edge1);
+        let b2: f64 = Vec3f::dot(ray.direction(), &qvec) * inv_det;
+        if b2 < 0.0 || b1 + b2 > 1.0 {
+            return None;
+        }
+
+        let t: f64 = Vec3f::dot(&edge2, &qvec) * inv_det;
+        if !ray_t.contains(t) {
+            return None;
+        }
+        let b0: f64 = 1.0 - b1 - b2;
+
+        let uv: (f64, f64) = if mesh.uvs.is_empty() {
+            (b1, b2)
+        }
+        else {
+            let (uv0, uv1, uv2) = (mesh.uvs[i0], mesh.uvs[i1], mesh.uvs[i2]);
+            (b0 * uv0.0 + b1 * uv1.0 + b2 * uv2.0, b0 * uv0.1 + b1 * uv1.1 + b2 * uv2.1)
+        };
+
+        let geometric_normal: Vec3f = Vec3f::unit_vector(&Vec3f::cross(&edge1, &edge2));
+        let mut rec: HitRecord = HitRecord::new(ray.at(t), mesh.mat.clone(), t, uv, ray, &geometric_normal);
+        rec.face = Some(FaceHit { vertices: [i0, i1, i2], barycentric: [b0, b1, b2] });
+
+        if !mesh.normals.is_empty() {
+            rec.set_shading_normal(&(b0 * mesh.normals[i0] + b1 * mesh.normals[i1] + b2 * mesh.normals[i2]));
+        }
+        Some(rec)
+    }
+
+    fn bounding_box(&self) -> &AABB {
+        &self.bounding_box
+    }
+}
+
+
+pub struct TriangleMesh {
+    data: Arc<MeshData>,
+    bvh: BVHNode,
+    area_cdf: Vec<f64>      // Running sum of face areas, for sampling the mesh as a light
+}
+
+impl fmt::Display for TriangleMesh {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f, "Vertices: {}; Triangles: {}; Material: {}",
+            self.data.positions.len(), self.data.indices.len(), self.data.mat
+        )
+    }
+}
+
+impl TriangleMesh {
+    pub fn new(
+        positions: Vec<Point3f>,
+        normals: Vec<Vec3f>,
+        uvs: Vec<(f64, f64)>,
+        indices: Vec<[u32; 3]>,
+        mat: Arc<dyn Material>
+    ) -> Result<Self, String> {
+        // Normals and texture coordinates are optional, but when given there must be one per
+        // vertex position.
+        if indices.is_empty() {
+            return Err("a mesh needs at least one triangle".to_string());
+        }
+        if !normals.is_empty() && normals.len() != positions.len() {
+            return Err(format!("expected {} vertex normals, got {}", positions.len(), normals.len()));
+        }
+        if !uvs.is_empty() && uvs.len() != positions.len() {
+            return Err(format!("expected {} texture coordinates, got {}", positions.len(), uvs.len()));
+        }
+        if let Some(index) = indices.iter().flatten().find(|index| **index as usize >= positions.len()) {
+            return Err(format!("vertex index {} is out of range for {} vertices", index, positions.len()));
+        }
+
+        let data: Arc<MeshData> = Arc::new(MeshData { positions, normals, uvs, indices, mat });
+
+        let mut triangles: Vec<Arc<dyn Hittable>> = (0..data.indices.len()).map(
+            |face| Arc::new(MeshTriangle::new(data.clone(), face)) as Arc<dyn Hittable>
+        ).collect();
+        let (bvh, _) = BVHNode::build(&mut triangles, &BVHConfig::sah());
+
+        let area_cdf: Vec<f64> = (0..data.indices.len()).scan(0.0,
+            |total_area, face| {
+                *total_area += data.area(face);
+                Some(*total_area)
+            }
+        ).collect();
+
+        Ok(Self { data, bvh, area_cdf })
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.data.indices.len()
+    }
+
+    pub fn area(&self) -> f64 {
+        *self.area_cdf.last().expect("a mesh has at least one triangle")
+    }
+
+    fn sample_face(&self, u: f64) -> (usize, f64) {
+        // Picks a face by area, and stretches u back over [0, 1) for it.
+        let target: f64 = u * self.area();
+        let face: usize = self.area_cdf.partition_point(|area| *area < target).min(self.area_cdf.len() - 1);
+        let face_start: f64 = if face == 0 { 0.0 } else { self.area_cdf[face - 1] };
+        let face_area: f64 = self.area_cdf[face] - face_start;
+        let u_face: f64 = if face_area > 0.0 { ((target - face_start) / face_area).clamp(0.0, 1.0) } else { 0.0 };
+        (face, u_face)
+    }
+
+    pub fn positions(&self) -> &[Point3f] {
+        &self.data.positions
+    }
+
+    pub fn normals(&self) -> &[Vec3f] {
+        &self.data.normals
+    }
+
+    pub fn uvs(&self) -> &[(f64, f64)] {
+        &self.data.uvs
+    }
+
+    pub fn indices(&self) -> &[[u32; 3]] {
+        &self.data.indices
+    }
+
+    pub fn material(&self) -> &Arc<dyn Material> {
+        &self.data.mat
+    }
+}
+
+impl Hittable for TriangleMesh {
+    fn hit(&self, ray: &Ray, ray_t: &Interval) -> Option<HitRecord> {
+        self.bvh.hit(ray, ray_t)
+    }
+
+    fn bounding_box(&self) -> &AABB {
+        self.bvh.bounding_box()
+    }
+
+    fn pdf_value(&self, origin: &Point3f, direction: &Vec3f, time: f64) -> f64 {
+        // Faces are sampled in proportion to their area, so the mesh is sampled uniformly by area.
+        if let Some(rec) = self.hit(&Ray::with_time(origin, direction, time), &Interval::new(0.001, f64::INFINITY)) {
+            let distance_squared: f64 = rec.t * rec.t * direction.length_squared();
+            let cos_theta: f64 = f64::abs(Vec3f::dot(direction, &rec.geometric_normal) / direction.length());
+            return distance_squared / (cos_theta * self.area());
+        }
+        0.0
+    }
+
+    fn random(&self, origin: &Point3f, u: (f64, f64), _time: f64) -> Vec3f {
+        let (face, u_face) = self.sample_face(u.0);
+        self.data.sample_point(face, (u_face, u.1)) - *origin
+    }
+
+    fn sample_surface(&self, u: (f64, f64), _time: f64) -> Option<SurfaceSample> {
+        let (face, u_face) = self.sample_face(u.0);
+        Some(SurfaceSample { point: self.data.sample_point(face, (u_face, u.1)), normal: self.data.normal(face), pdf: 1.0 / self.area() })
+    }
+
+    fn surface_pdf(&self, origin: &Point3f, direction: &Vec3f, time: f64) -> f64 {
+        match self.hit(&Ray::with_time(origin, direction, time), &Interval::new(0.001, f64::INFINITY)) {
+            Some(_) => 1.0 / self.area(),
+            None => 0.0
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::color::Color;
+    use crate::material::Lambertian;
+    use crate::mesh::*;
+
+    fn unit_square(normals: Vec<Vec3f>) -> TriangleMesh {
+        // Two triangles covering [0,1] x [0,1] in the z = 0 plane.
+        TriangleMesh::new(
+            vec![Point3f::new(0.0, 0.0, 0.0), Point3f::new(1.0, 0.0, 0.0), Point3f::new(1.0, 1.0, 0.0), Point3f::new(0.0, 1.0, 0.0)],
+            normals,
+            vec![(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)],
+            vec![[0, 1, 2], [0, 2, 3]],
+            Arc::new(Lambertian::from_color(&Color::ONE))
+        ).unwrap()
+    }
+
+    #[test]
+    fn hit() {
+        let mesh: TriangleMesh = unit_square(Vec::new());
+        assert_eq!(mesh.triangle_count(), 2);
+        assert_eq!(mesh.area(), 1.0);
+
+        let ray: Ray = Ray::new(&Point3f::new(0.25, 0.75, 1.0), &-Vec3f::E3);
+        let rec: HitRecord = mesh.hit(&ray, &Interval::new(0.001, f64::INFINITY)).unwrap();
+        assert_eq!(rec.t, 1.0);
+        assert_eq!(rec.normal, Vec3f::E3);
+        assert!(rec.front_face);
+        assert!((rec.uv.0 - 0.25).abs() < 1e-12 && (rec.uv.1 - 0.75).abs() < 1e-12);
+
+        let miss: Ray = Ray::new(&Point3f::new(1.5, 0.5, 1.0), &-Vec3f::E3);
+        assert!(mesh.hit(&miss, &Interval::new(0.001, f64::INFINITY)).is_none());
+    }
+
+    #[test]
+    fn interpolated_normal() {
+        let tilted: Vec3f = Vec3f::unit_vector(&Vec3f::new(1.0, 0.0, 1.0));
+        let mesh: TriangleMesh = unit_square(vec![Vec3f::E3, tilted, tilted, Vec3f::E3]);
+
+        // Halfway across the square the shading normal is halfway between the vertex normals,
+        // and it faces the ray even when hit from behind.
+        let ray: Ray = Ray::new(&Point3f::new(0.5, 0.25, -1.0), &Vec3f::E3);
+        let rec: HitRecord = mesh.hit(&ray, &Interval::new(0.001, f64::INFINITY)).unwrap();
+        let expected: Vec3f = -Vec3f::unit_vector(&(0.5 * Vec3f::E3 + 0.5 * tilted));
+        assert!(!rec.front_face);
+        assert!((rec.normal - expected).length() < 1e-12);
+    }
+
+    #[test]
+    fn invalid_buffers() {
+        let mat: Arc<dyn Material> = Arc::new(Lambertian::from_color(&Color::ONE));
+        let positions: Vec<Point3f> = vec![Point3f::ZERO, Point3f::E1, Point3f::E2];
+        assert!(TriangleMesh::new(positions.clone(), Vec::new(), Vec::new(), Vec::new(), mat.clone()).is_err());
+        assert!(TriangleMesh::new(positions.clone(), Vec::new(), Vec::new(), vec![[0, 1, 3]], mat.clone()).is_err());
+        assert!(TriangleMesh::new(positions.clone(), vec![Vec3f::E3], Vec::new(), vec![[0, 1, 2]], mat.clone()).is_err());
+        assert!(TriangleMesh::new(positions, Vec::new(), Vec::new(), vec![[0, 1, 2]], mat).is_ok());
+    }
+}
@@ -0,0 +1,177 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use log::warn;
+
+use crate::color::Color;
+use crate::hittable_list::HittableList;
+use crate::material::{Dielectric, DiffuseLight, Lambertian, Material, Metal};
+use crate::mesh::TriangleMesh;
+use crate::texture::{Image, Texture};
+use crate::vec3::{Point3f, Vec3f};
+
+
+pub fn load(filepath: &Path, override_mat: Option<Arc<dyn Material>>) -> Result<HittableList, String> {
+    // Loads every object of a Wavefront OBJ file as a triangle mesh. Each mesh uses its MTL material
+    // unless override_mat is given. Polygons are triangulated and vertices are re-indexed so
+    // positions, normals and texture coordinates share one index buffer.
+    let (models, mtl_materials) = tobj::load_obj(filepath, &tobj::GPU_LOAD_OPTIONS)
+        .map_err(|err| format!("{}: {}", filepath.display(), err))?;
+
+    let base_dir: &Path = filepath.parent().unwrap_or(Path::new(""));
+    let materials: Vec<Arc<dyn Material>> = match (&override_mat, mtl_materials) {
+        (Some(_), _) => Vec::new(),
+        (None, Ok(mtl_materials)) => mtl_materials.iter().map(
+            |mtl| material_from_mtl(mtl, base_dir)
+        ).collect::<Result<Vec<Arc<dyn Material>>, String>>()?,
+        (None, Err(err)) => {
+            warn!("{}: no materials loaded: {}", filepath.display(), err);
+            Vec::new()
+        }
+    };
+    let default_mat: Arc<dyn Material> = Arc::new(Lambertian::from_color(&Color::new(0.73, 0.73, 0.73)));
+
+    let mut meshes: HittableList = HittableList::new();
+    for model in models {
+        let mesh: tobj::Mesh = model.mesh;
+        if mesh.indices.is_empty() {
+            continue;
+        }
+
+        let positions: Vec<Point3f> = mesh.positions.chunks_exact(3).map(
+            |p| Point3f::new(f64::from(p[0]), f64::from(p[1]), f64::from(p[2]))
+        ).collect();
+        let normals: Vec<Vec3f> = mesh.normals.chunks_exact(3).map(
+            |n| Vec3f::new(f64::from(n[0]), f64::from(n[1]), f64::from(n[2]))
+        ).collect();
+        let uvs: Vec<(f64, f64)> = mesh.texcoords.chunks_exact(2).map(
+            |uv| (f64::from(uv[0]), f64::from(uv[1]))
+        ).collect();
+        let indices: Vec<[u32; 3]> = mesh.indices.chunks_exact(3).map(|i| [i[0], i[1], i[2]]).collect();
+
+        let mat: Arc<dyn Material> = match (&override_mat, mesh.material_id) {
+            (Some(override_mat), _) => override_mat.clone(),
+            (None, Some(material_id)) => materials.get(material_id).cloned().unwrap_or_else(|| default_mat.clone()),
+            (None, None) => default_mat.clone()
+        };
+
+        let triangle_mesh: TriangleMesh = TriangleMesh::new(positions, normals, uvs, indices, mat)
+            .map_err(|err| format!("{}: object '{}': {}", filepath.display(), model.name, err))?;
+        meshes.add(Arc::new(triangle_mesh));
+    }
+
+    if meshes.objects.is_empty() {
+        return Err(format!("{}: no triangles found", filepath.display()));
+    }
+    Ok(meshes)
+}
+
+fn material_from_mtl(mtl: &tobj::Material, base_dir: &Path) -> Result<Arc<dyn Material>, String> {
+    // MTL describes Phong-style materials, which map onto ours as follows:
+    //     Ke (emission) set                     => DiffuseLight
+    //     illum 4, 6, 7, 9 or d (dissolve) < 1  => Dielectric with Ni (optical density)
+    //     illum 3 or 5 (reflection)             => Metal with Ks, fuzz derived from Ns (shininess)
+    //     otherwise                             => Lambertian with map_Kd or Kd
+    let to_color = |c: [f32; 3]| Color::new(f64::from(c[0]), f64::from(c[1]), f64::from(c[2]));
+
+    let emission: Option<Color> = mtl.unknown_param.get("Ke").and_then(|ke| parse_color(ke));
+    if let Some(emit) = emission.filter(|emit| !emit.near_zero()) {
+        return Ok(Arc::new(DiffuseLight::from_color(&emit)));
+    }
+
+    let illum: u8 = mtl.illumination_model.unwrap_or(2);
+    if matches!(illum, 4 | 6 | 7 | 9) || mtl.dissolve.is_some_and(|d| d < 1.0) {
+        let refractive_index: f64 = mtl.optical_density.map_or(1.5, f64::from);
+        return Ok(Arc::new(Dielectric::new(refractive_index)));
+    }
+
+    if matches!(illum, 3 | 5) {
+        let albedo: Color = mtl.specular.or(mtl.diffuse).map_or(Color::ONE, to_color);
+        let shininess: f64 = mtl.shininess.map_or(0.0, f64::from).max(0.0);
+        let fuzz: f64 = f64::sqrt(2.0 / (shininess + 2.0));
+        return Ok(Arc::new(Metal::new(&albedo, fuzz)));
+    }
+
+    if let Some(diffuse_texture) = &mtl.diffuse_texture {
+        let texture: Arc<dyn Texture> = Arc::new(
+            Image::read_image(&base_dir.join(diffuse_texture))
+                .map_err(|err| format!("material '{}': {}: {}", mtl.name, diffuse_texture, err))?
+        );
+        return Ok(Arc::new(Lambertian::from_texture(texture)));
+    }
+    let albedo: Color = mtl.diffuse.map_or(Color::new(0.73, 0.73, 0.73), to_color);
+    Ok(Arc::new(Lambertian::from_color(&albedo)))
+}
+
+fn parse_color(value: &str) -> Option<Color> {
+    let components: Vec<f64> = value.split_whitespace().map(|c| c.parse::<f64>()).collect::<Result<Vec<f64>, _>>().ok()?;
+    match components[..] {
+        [r, g, b] => Some(Color::new(r, g, b)),
+        [v] => Some(Color::new(v, v, v)),
+        _ => None
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+
+    use crate::hittable::{HitRecord, Hittable};
+    use crate::interval::Interval;
+    use crate::obj::*;
+    use crate::ray::Ray;
+
+    #[test]
+    fn load_with_materials() {
+        let dir: PathBuf = std::env::temp_dir().join("raytracing_rs_obj_load_with_materials");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("scene.mtl"), "\
+newmtl glass
+Ni 1.5
+illum 7
+
+newmtl lamp
+Kd 0 0 0
+Ke 4 4 4
+").unwrap();
+        fs::write(dir.join("scene.obj"), "\
+mtllib scene.mtl
+o window
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+usemtl glass
+f 1 2 3 4
+o lamp
+v 0 0 2
+v 1 0 2
+v 0 1 2
+usemtl lamp
+f 5 6 7
+").unwrap();
+
+        let meshes: HittableList = load(&dir.join("scene.obj"), None).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(meshes.objects.len(), 2);
+
+        // The quad is triangulated and keeps its material.
+        let ray: Ray = Ray::new(&Point3f::new(0.9, 0.9, -1.0), &Vec3f::E3);
+        let rec: HitRecord = meshes.hit(&ray, &Interval::new(0.001, f64::INFINITY)).unwrap();
+        assert_eq!(rec.t, 1.0);
+        assert_eq!(format!("{}", rec.mat), "Material Dielectric. Refractive Index: 1.5");
+
+        let ray: Ray = Ray::new(&Point3f::new(0.1, 0.1, 3.0), &-Vec3f::E3);
+        let rec: HitRecord = meshes.hit(&ray, &Interval::new(0.001, f64::INFINITY)).unwrap();
+        assert!(format!("{}", rec.mat).starts_with("Material DiffuseLight"));
+    }
+
+    #[test]
+    fn parse_mtl_color() {
+        assert_eq!(parse_color("1 0.5 0"), Some(Color::new(1.0, 0.5, 0.0)));
+        assert_eq!(parse_color("2"), Some(Color::new(2.0, 2.0, 2.0)));
+        assert_eq!(parse_color("1 x 0"), None);
+    }
+}
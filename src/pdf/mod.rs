@@ -3,7 +3,6 @@ use std::sync::Arc;
 
 use crate::hittable::Hittable;
 use crate::onb::{BasisAxis, ONB};
-use crate::utilities;
 use crate::vec3::{Point3f, Vec3f};
 
 pub trait PDF: Send + Sync {
@@ -11,7 +10,8 @@ pub trait PDF: Send + Sync {
         0.0
     }
 
-    fn generate(&self) -> Vec3f {
+    // Maps a 2D sample in [0, 1)^2 to a direction drawn from the distribution.
+    fn generate(&self, _u: (f64, f64)) -> Vec3f {
         Vec3f::ZERO
     }
 }
@@ -29,8 +29,8 @@ impl PDF for SpherePDF {
         1.0 / (4.0 * f64::consts::PI)
     }
 
-    fn generate(&self) -> Vec3f {
-        Vec3f::random_unit_vector()
+    fn generate(&self, u: (f64, f64)) -> Vec3f {
+        Vec3f::sample_unit_sphere(u)
     }
 }
 
@@ -51,30 +51,31 @@ impl PDF for CosinePDF {
         f64::max(0.0, cosine_theta / f64::consts::PI)
     }
 
-    fn generate(&self) -> Vec3f {
-        self.uvw.transform(&Vec3f::random_cosine_direction())
+    fn generate(&self, u: (f64, f64)) -> Vec3f {
+        self.uvw.transform(&Vec3f::sample_cosine_direction(u))
     }
 }
 
 
 pub struct HittablePDF {
     objects: Arc<dyn Hittable>,
-    origin: Point3f
+    origin: Point3f,
+    time: f64
 }
 
 impl HittablePDF {
-    pub fn new(objects: Arc<dyn Hittable>, origin: &Point3f) -> Self {
-        Self { objects, origin: *origin }
+    pub fn new(objects: Arc<dyn Hittable>, origin: &Point3f, time: f64) -> Self {
+        Self { objects, origin: *origin, time }
     }
 }
 
 impl PDF for HittablePDF {
     fn value(&self, direction: &Vec3f) -> f64 {
-        self.objects.pdf_value(&self.origin, direction)
+        self.objects.pdf_value(&self.origin, direction, self.time)
     }
 
-    fn generate(&self) -> Vec3f {
-        self.objects.random(&self.origin)
+    fn generate(&self, u: (f64, f64)) -> Vec3f {
+        self.objects.random(&self.origin, u, self.time)
     }
 }
 
@@ -95,12 +96,13 @@ impl PDF for MixturePDF {
         0.5 * self.light_pdf.value(direction) + 0.5 * self.surface_pdf.value(direction)
     }
 
-    fn generate(&self) -> Vec3f {
-        if utilities::random() < 0.5 {
-            self.light_pdf.generate()
+    fn generate(&self, u: (f64, f64)) -> Vec3f {
+        // The first number picks a distribution and is stretched back over [0, 1) for it.
+        if u.0 < 0.5 {
+            self.light_pdf.generate((2.0 * u.0, u.1))
         }
         else {
-            self.surface_pdf.generate()
+            self.surface_pdf.generate((2.0 * u.0 - 1.0, u.1))
         }
     }
 }
\ No newline at end of file
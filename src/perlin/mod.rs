@@ -1,7 +1,7 @@
-use rand::thread_rng;
 use rand::prelude::SliceRandom;
-use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
 
+use crate::utilities;
 use crate::vec3::{Axis, Point3f, Vec3f};
 
 #[derive(Debug, Clone, Eq, PartialEq)]
@@ -11,7 +11,7 @@ pub enum PerlinTexture {
     Marble(u32),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Perlin {
     point_count: usize,
     random_vecs: Vec<Vec3f>,
@@ -22,22 +22,45 @@ pub struct Perlin {
 
 impl Perlin {
     pub fn new(point_count: usize) -> Self {
-        let random_vecs: Vec<Vec3f> = (0..point_count).into_par_iter().map(
+        // Drawn in order on the calling thread, so a seeded scene gets the same noise every time.
+        let random_vecs: Vec<Vec3f> = (0..point_count).map(
             |_| {
                 Vec3f::random_unit_vector()
             }
         ).collect::<Vec<Vec3f>>();
 
         let mut perm_x: Vec<usize> = (0..point_count).collect();
-        perm_x.shuffle(&mut thread_rng());
+        utilities::with_rng(|rng| perm_x.shuffle(rng));
         let mut perm_y: Vec<usize> = (0..point_count).collect();
-        perm_y.shuffle(&mut  thread_rng());
+        utilities::with_rng(|rng| perm_y.shuffle(rng));
         let mut perm_z: Vec<usize> = (0..point_count).collect();
-        perm_z.shuffle(&mut  thread_rng());
+        utilities::with_rng(|rng| perm_z.shuffle(rng));
 
         Self { point_count, random_vecs, perm_x, perm_y, perm_z }
     }
 
+    pub fn point_count(&self) -> usize {
+        self.point_count
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        // Checks tables that were not produced by Perlin::new, e.g. when read from a scene file.
+        if !self.point_count.is_power_of_two() {
+            return Err(format!("Perlin point count {} is not a power of two", self.point_count));
+        }
+        if self.random_vecs.len() != self.point_count {
+            return Err(format!("Expected {} Perlin vectors, got {}", self.point_count, self.random_vecs.len()));
+        }
+        for perm in [&self.perm_x, &self.perm_y, &self.perm_z] {
+            let mut sorted: Vec<usize> = perm.clone();
+            sorted.sort_unstable();
+            if sorted.iter().copied().ne(0..self.point_count) {
+                return Err(format!("Perlin permutation is not a permutation of 0..{}", self.point_count));
+            }
+        }
+        Ok(())
+    }
+
     pub fn noise(&self, point: &Point3f) -> f64 {
         let u: f64 = point.component(Axis::X) - point.component(Axis::X).floor();
         let v: f64 = point.component(Axis::Y) - point.component(Axis::Y).floor();
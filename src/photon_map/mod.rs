@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+
+use crate::color::Color;
+use crate::vec3::{Axis, Point3f, Vec3f};
+
+// Light traced from the lights and left on a surface, to be gathered by the camera's paths.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Photon {
+    pub point: Point3f,
+    pub direction: Vec3f,   // Unit direction the photon arrived along
+    pub power: Color
+}
+
+
+// Photons hashed into a grid of cubic cells, so those near a point are found by only looking in
+// the cells around it. Cells are as wide as the largest radius searched, which keeps a search to
+// at most three cells along each axis.
+pub struct PhotonMap {
+    cell_size: f64,
+    cells: HashMap<(i64, i64, i64), Vec<Photon>>,
+    len: usize
+}
+
+impl PhotonMap {
+    pub fn new(photons: Vec<Photon>, cell_size: f64) -> Result<Self, String> {
+        if !(cell_size > 0.0 && cell_size.is_finite()) {
+            return Err(format!("photon map cells must have a positive size, got {}", cell_size));
+        }
+        let len: usize = photons.len();
+        let mut map: Self = Self { cell_size, cells: HashMap::new(), len };
+        for photon in photons {
+            map.cells.entry(map.cell(&photon.point)).or_default().push(photon);
+        }
+        Ok(map)
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn cell(&self, point: &Point3f) -> (i64, i64, i64) {
+        let index = |axis: Axis| (point.component(axis) / self.cell_size).floor() as i64;
+        (index(Axis::X), index(Axis::Y), index(Axis::Z))
+    }
+
+    pub fn within<'a>(&'a self, point: &'a Point3f, radius: f64) -> impl Iterator<Item = &'a Photon> + 'a {
+        // The photons no further than radius from point, in no particular order.
+        let extent: Vec3f = Vec3f::new(radius, radius, radius);
+        let (min, max) = (self.cell(&(point - extent)), self.cell(&(point + extent)));
+        (min.0..=max.0).flat_map(
+            move |x: i64| (min.1..=max.1).flat_map(move |y: i64| (min.2..=max.2).map(move |z: i64| (x, y, z)))
+        )
+            .filter_map(|key: (i64, i64, i64)| self.cells.get(&key))
+            .flatten()
+            .filter(move |photon: &&Photon| (photon.point - point).length_squared() <= radius * radius)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::photon_map::*;
+
+    #[test]
+    fn within() {
+        // A lattice of photons straddling the origin, where the cell indices change sign.
+        let mut photons: Vec<Photon> = Vec::new();
+        for i in -10..10 {
+            for j in -10..10 {
+                for k in -10..10 {
+                    let point: Point3f = Point3f::new(i as f64 * 0.3, j as f64 * 0.3, k as f64 * 0.3 + 0.05);
+                    photons.push(Photon { point, direction: -Vec3f::E2, power: Color::ONE });
+                }
+            }
+        }
+        let map: PhotonMap = PhotonMap::new(photons.clone(), 0.7).unwrap();
+        assert_eq!(map.len(), photons.len());
+
+        for (center, radius) in [(Point3f::ZERO, 0.7), (Point3f::new(0.4, -1.1, 0.2), 0.5), (Point3f::new(-2.0, 2.0, 0.0), 0.35)] {
+            let mut found: Vec<Point3f> = map.within(&center, radius).map(|photon: &Photon| photon.point).collect();
+            let expected: Vec<Point3f> = photons.iter().map(|photon: &Photon| photon.point)
+                .filter(|point: &Point3f| (point - center).length() <= radius).collect();
+            assert!(!expected.is_empty());
+            assert_eq!(found.len(), expected.len());
+            found.retain(|point: &Point3f| !expected.contains(point));
+            assert!(found.is_empty());
+        }
+
+        assert!(PhotonMap::new(Vec::new(), 0.0).is_err());
+    }
+}
@@ -2,12 +2,11 @@ use std::fmt;
 use std::sync::Arc;
 
 use crate::aabb::AABB;
-use crate::hittable::{HitRecord, Hittable};
+use crate::hittable::{HitRecord, Hittable, SurfaceSample};
 use crate::hittable_list::HittableList;
 use crate::interval::Interval;
 use crate::material::Material;
 use crate::ray::Ray;
-use crate::utilities::random;
 use crate::vec3::{Axis, Point3f, Vec3f};
 
 
@@ -63,6 +62,22 @@ impl Plane {
         let beta: f64 = Vec3f::dot(&self.w, &Vec3f::cross(&self.dir_a, &planar_hitpt_vector));
         (alpha, beta)
     }
+
+    pub fn origin(&self) -> &Point3f {
+        &self.orig
+    }
+
+    pub fn u(&self) -> &Vec3f {
+        &self.dir_a
+    }
+
+    pub fn v(&self) -> &Vec3f {
+        &self.dir_b
+    }
+
+    pub fn material(&self) -> &Arc<dyn Material> {
+        &self.mat
+    }
 }
 
 impl Hittable for Plane {
@@ -93,24 +108,24 @@ impl Hittable for Plane {
             &self.normal
         );
 
-        return Some(rec);
+        Some(rec)
     }
 
     fn bounding_box(&self) -> &AABB {
         &self.bounding_box
     }
 
-    fn pdf_value(&self, origin: &Point3f, direction: &Vec3f) -> f64 {
-        if let Some(rec) =  self.hit(&Ray::new(origin, direction), &Interval::new(0.001, f64::INFINITY)) {
+    fn pdf_value(&self, origin: &Point3f, direction: &Vec3f, time: f64) -> f64 {
+        if let Some(rec) =  self.hit(&Ray::with_time(origin, direction, time), &Interval::new(0.001, f64::INFINITY)) {
             let distance_squared: f64 = rec.t * rec.t * direction.length_squared();
-            let cos_theta: f64 = f64::abs(Vec3f::dot(direction, &rec.normal) / direction.length());
+            let cos_theta: f64 = f64::abs(Vec3f::dot(direction, &rec.geometric_normal) / direction.length());
             return distance_squared / (cos_theta * self.area);
         }
         0.0
     }
 
-    fn random(&self, origin: &Point3f) -> Vec3f {
-        let p: Vec3f = self.orig + (random() * self.dir_a) + (random() * self.dir_b);
+    fn random(&self, origin: &Point3f, u: (f64, f64), _time: f64) -> Vec3f {
+        let p: Vec3f = self.orig + (u.0 * self.dir_a) + (u.1 * self.dir_b);
         p - *origin
     }
 }
@@ -204,6 +219,10 @@ impl Quad {
 
         Arc::new(sides)
     }
+
+    pub fn plane(&self) -> &Plane {
+        &self.plane
+    }
 }
 
 impl Interior for Quad {
@@ -232,12 +251,24 @@ impl Hittable for Quad {
         &self.plane.bounding_box
     }
 
-    fn pdf_value(&self, origin: &Point3f, direction: &Vec3f) -> f64 {
-        self.plane.pdf_value(origin, direction)
+    fn pdf_value(&self, origin: &Point3f, direction: &Vec3f, time: f64) -> f64 {
+        self.plane.pdf_value(origin, direction, time)
+    }
+
+    fn random(&self, origin: &Point3f, u: (f64, f64), time: f64) -> Vec3f {
+        self.plane.random(origin, u, time)
     }
 
-    fn random(&self, origin: &Point3f) -> Vec3f {
-        self.plane.random(origin)
+    fn sample_surface(&self, u: (f64, f64), _time: f64) -> Option<SurfaceSample> {
+        let p: &Plane = &self.plane;
+        Some(SurfaceSample { point: p.orig + (u.0 * p.dir_a) + (u.1 * p.dir_b), normal: p.normal, pdf: 1.0 / p.area })
+    }
+
+    fn surface_pdf(&self, origin: &Point3f, direction: &Vec3f, time: f64) -> f64 {
+        match self.hit(&Ray::with_time(origin, direction, time), &Interval::new(0.001, f64::INFINITY)) {
+            Some(_) => 1.0 / self.plane.area,
+            None => 0.0
+        }
     }
 }
 
@@ -245,6 +276,8 @@ impl Hittable for Quad {
 #[derive(Clone)]
 pub struct Tri {
     plane: Plane,
+    normals: Option<[Vec3f; 3]>,        // Vertex normals at orig, orig + dir_a and orig + dir_b
+    uvs: Option<[(f64, f64); 3]>        // Vertex texture coordinates, in the same order
 }
 
 impl fmt::Display for Tri {
@@ -255,13 +288,36 @@ impl fmt::Display for Tri {
 
 impl Tri {
     pub fn new(orig: &Point3f, dir_a: &Vec3f, dir_b: &Vec3f, mat: Arc<dyn Material>) -> Self {
+        Self::with_vertex_attributes(orig, dir_a, dir_b, None, None, mat)
+    }
+
+    pub fn with_vertex_attributes(
+        orig: &Point3f, 
+        dir_a: &Vec3f, 
+        dir_b: &Vec3f, 
+        normals: Option<[Vec3f; 3]>, 
+        uvs: Option<[(f64, f64); 3]>, 
+        mat: Arc<dyn Material>
+    ) -> Self {
         let mut plane: Plane = Plane::new(orig, dir_a, dir_b, mat);
 
         let diagonal1: AABB = AABB::from_point(orig, &(orig + dir_a + dir_b));
         let diagonal2: AABB = AABB::from_point(&(orig + dir_a), &(orig + dir_b));
         let bounding_box: AABB = AABB::from_bounding_box(&diagonal1, &diagonal2);
         plane.bounding_box = bounding_box;
-        Self { plane }
+        Self { plane, normals, uvs }
+    }
+
+    pub fn plane(&self) -> &Plane {
+        &self.plane
+    }
+
+    pub fn normals(&self) -> Option<&[Vec3f; 3]> {
+        self.normals.as_ref()
+    }
+
+    pub fn uvs(&self) -> Option<&[(f64, f64); 3]> {
+        self.uvs.as_ref()
     }
 }
 
@@ -277,9 +333,20 @@ impl Interior for Tri {
 impl Hittable for Tri {
     fn hit(&self, ray: &Ray, ray_t: &Interval) -> Option<HitRecord> {
         if let Some(mut rec) = self.plane.hit(ray, ray_t) {
-            if let Some(plane_coord) = Self::is_interior(self.plane.planar_hit_coordinates(&rec.point)) {
-                // Ray hits the 2D shape; update hit record
-                rec.uv = plane_coord;
+            if let Some((alpha, beta)) = Self::is_interior(self.plane.planar_hit_coordinates(&rec.point)) {
+                // Ray hits the 2D shape; update hit record. The planar coordinates are the
+                // barycentric weights of the vertices at orig + dir_a and orig + dir_b.
+                let gamma: f64 = 1.0 - alpha - beta;
+                rec.uv = match self.uvs {
+                    Some([uv0, uv1, uv2]) => (
+                        gamma * uv0.0 + alpha * uv1.0 + beta * uv2.0,
+                        gamma * uv0.1 + alpha * uv1.1 + beta * uv2.1
+                    ),
+                    None => (alpha, beta)
+                };
+                if let Some([n0, n1, n2]) = self.normals {
+                    rec.set_shading_normal(&(gamma * n0 + alpha * n1 + beta * n2));
+                }
                 return Some(rec);
             }
             return None;
@@ -291,11 +358,83 @@ impl Hittable for Tri {
         &self.plane.bounding_box
     }
 
-    fn pdf_value(&self, origin: &Point3f, direction: &Vec3f) -> f64 {
-        self.plane.pdf_value(origin, direction)
+    fn pdf_value(&self, origin: &Point3f, direction: &Vec3f, time: f64) -> f64 {
+        self.plane.pdf_value(origin, direction, time)
+    }
+
+    fn random(&self, origin: &Point3f, u: (f64, f64), time: f64) -> Vec3f {
+        self.plane.random(origin, u, time)
     }
 
-    fn random(&self, origin: &Point3f) -> Vec3f {
-        self.plane.random(origin)
+    fn sample_surface(&self, u: (f64, f64), _time: f64) -> Option<SurfaceSample> {
+        // The triangle is half of the plane's parallelogram.
+        let p: &Plane = &self.plane;
+        let su: f64 = f64::sqrt(u.0);
+        let (alpha, beta) = (1.0 - su, u.1 * su);
+        Some(SurfaceSample { point: p.orig + (alpha * p.dir_a) + (beta * p.dir_b), normal: p.normal, pdf: 2.0 / p.area })
     }
-}
\ No newline at end of file
+
+    fn surface_pdf(&self, origin: &Point3f, direction: &Vec3f, time: f64) -> f64 {
+        match self.hit(&Ray::with_time(origin, direction, time), &Interval::new(0.001, f64::INFINITY)) {
+            Some(_) => 2.0 / self.plane.area,
+            None => 0.0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::color::Color;
+    use crate::material::Lambertian;
+    use crate::plane::*;
+
+    fn tri(normals: Option<[Vec3f; 3]>, uvs: Option<[(f64, f64); 3]>) -> Tri {
+        Tri::with_vertex_attributes(
+            &Point3f::ZERO, &Vec3f::E1, &Vec3f::E2, normals, uvs, Arc::new(Lambertian::from_color(&Color::ONE))
+        )
+    }
+
+    #[test]
+    fn flat_tri() {
+        let ray: Ray = Ray::new(&Point3f::new(0.25, 0.5, 1.0), &-Vec3f::E3);
+        let rec: HitRecord = tri(None, None).hit(&ray, &Interval::new(0.001, f64::INFINITY)).unwrap();
+        assert_eq!(rec.normal, Vec3f::E3);
+        assert_eq!(rec.geometric_normal, Vec3f::E3);
+        assert_eq!(rec.uv, (0.25, 0.5));
+
+        let miss: Ray = Ray::new(&Point3f::new(0.75, 0.5, 1.0), &-Vec3f::E3);
+        assert!(tri(None, None).hit(&miss, &Interval::new(0.001, f64::INFINITY)).is_none());
+    }
+
+    #[test]
+    fn smooth_tri() {
+        let tilted: Vec3f = Vec3f::unit_vector(&Vec3f::new(1.0, 0.0, 1.0));
+        let smooth: Tri = tri(
+            Some([Vec3f::E3, tilted, Vec3f::E3]),
+            Some([(0.0, 0.0), (0.5, 0.0), (0.0, 0.5)])
+        );
+
+        // Hit from behind, so both normals face the ray; only the shading normal is interpolated.
+        let ray: Ray = Ray::new(&Point3f::new(0.5, 0.0, -1.0), &Vec3f::E3);
+        let rec: HitRecord = smooth.hit(&ray, &Interval::new(0.001, f64::INFINITY)).unwrap();
+        assert!(!rec.front_face);
+        assert_eq!(rec.geometric_normal, -Vec3f::E3);
+        let expected: Vec3f = -Vec3f::unit_vector(&(0.5 * Vec3f::E3 + 0.5 * tilted));
+        assert!((rec.normal - expected).length() < 1e-12);
+        assert_eq!(rec.uv, (0.25, 0.0));
+    }
+
+    #[test]
+    fn no_light_leak() {
+        // A shading normal tilted away from the geometric one must not let a diffuse surface
+        // scatter light through itself.
+        let tilted: Vec3f = Vec3f::unit_vector(&Vec3f::new(1.0, 0.0, 0.2));
+        let smooth: Tri = tri(Some([tilted, tilted, tilted]), None);
+        let ray: Ray = Ray::new(&Point3f::new(0.25, 0.25, 1.0), &-Vec3f::E3);
+        let rec: HitRecord = smooth.hit(&ray, &Interval::new(0.001, f64::INFINITY)).unwrap();
+
+        let below: Ray = Ray::new(&rec.point, &Vec3f::new(1.0, 0.0, -0.1));
+        assert!(Vec3f::dot(&rec.normal, below.direction()) > 0.0);
+        assert_eq!(rec.mat.scattering_pdf(&ray, &rec, &below), 0.0);
+    }
+}
@@ -0,0 +1,477 @@
+use std::fs;
+use std::path::Path;
+use std::str::SplitAsciiWhitespace;
+use std::sync::Arc;
+
+use crate::color::{self, Color};
+use crate::material::{Lambertian, Material};
+use crate::mesh::TriangleMesh;
+use crate::texture::VertexColor;
+use crate::vec3::{Point3f, Vec3f};
+
+
+pub fn load(filepath: &Path, override_mat: Option<Arc<dyn Material>>) -> Result<TriangleMesh, String> {
+    // Loads the vertex and face elements of a PLY file, in ASCII or binary encoding, as a
+    // triangle mesh. The mesh uses its vertex colors unless override_mat is given.
+    let bytes: Vec<u8> = fs::read(filepath).map_err(|err| format!("{}: {}", filepath.display(), err))?;
+    from_bytes(&bytes, override_mat).map_err(|err| format!("{}: {}", filepath.display(), err))
+}
+
+pub fn from_bytes(bytes: &[u8], override_mat: Option<Arc<dyn Material>>) -> Result<TriangleMesh, String> {
+    let ply: PlyMesh = PlyMesh::read(bytes)?;
+
+    let mat: Arc<dyn Material> = match override_mat {
+        Some(override_mat) => override_mat,
+        None if !ply.colors.is_empty() => Arc::new(Lambertian::from_texture(Arc::new(VertexColor::new(ply.colors)))),
+        None => Arc::new(Lambertian::from_color(&Color::new(0.73, 0.73, 0.73)))
+    };
+    TriangleMesh::new(ply.positions, ply.normals, ply.uvs, ply.indices, mat)
+}
+
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Encoding {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ScalarType {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl ScalarType {
+    fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "char" | "int8" => Ok(Self::I8),
+            "uchar" | "uint8" => Ok(Self::U8),
+            "short" | "int16" => Ok(Self::I16),
+            "ushort" | "uint16" => Ok(Self::U16),
+            "int" | "int32" => Ok(Self::I32),
+            "uint" | "uint32" => Ok(Self::U32),
+            "float" | "float32" => Ok(Self::F32),
+            "double" | "float64" => Ok(Self::F64),
+            _ => Err(format!("unknown property type '{}'", name))
+        }
+    }
+
+    fn is_srgb(&self) -> bool {
+        // Integer colors are display values, encoded in sRGB; float colors are linear.
+        matches!(self, Self::U8 | Self::U16)
+    }
+
+    fn color_scale(&self) -> f64 {
+        // Integer colors span the full range of their type; float colors are already in [0,1].
+        match self {
+            Self::U8 => 1.0 / 255.0,
+            Self::U16 => 1.0 / 65535.0,
+            _ => 1.0
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Property {
+    Scalar { name: String, value_type: ScalarType },
+    List { name: String, count_type: ScalarType, item_type: ScalarType },
+}
+
+impl Property {
+    fn name(&self) -> &str {
+        match self {
+            Self::Scalar { name, .. } | Self::List { name, .. } => name
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+impl Element {
+    fn scalar(&self, names: &[&str]) -> Option<usize> {
+        // Index of the first scalar property with one of the given names.
+        self.properties.iter().position(
+            |property| matches!(property, Property::Scalar { name, .. } if names.contains(&name.as_str()))
+        )
+    }
+
+    fn scalar_type(&self, index: usize) -> ScalarType {
+        match self.properties[index] {
+            Property::Scalar { value_type, .. } => value_type,
+            Property::List { item_type, .. } => item_type
+        }
+    }
+}
+
+
+// Reads the values of the body one property at a time, whatever the encoding.
+enum BodyReader<'a> {
+    Ascii(SplitAsciiWhitespace<'a>),
+    Binary { bytes: &'a [u8], little_endian: bool },
+}
+
+impl BodyReader<'_> {
+    fn read(&mut self, value_type: ScalarType) -> Result<f64, String> {
+        match self {
+            Self::Ascii(tokens) => {
+                let token: &str = tokens.next().ok_or("unexpected end of file")?;
+                token.parse::<f64>().map_err(|_| format!("invalid number '{}'", token))
+            },
+            Self::Binary { bytes, little_endian } => {
+                let size: usize = match value_type {
+                    ScalarType::I8 | ScalarType::U8 => 1,
+                    ScalarType::I16 | ScalarType::U16 => 2,
+                    ScalarType::I32 | ScalarType::U32 | ScalarType::F32 => 4,
+                    ScalarType::F64 => 8,
+                };
+                if bytes.len() < size {
+                    return Err("unexpected end of file".to_string());
+                }
+                let (value, rest) = bytes.split_at(size);
+                *bytes = rest;
+
+                // Work in big-endian order, which reads left to right.
+                let mut b: [u8; 8] = [0; 8];
+                b[..size].copy_from_slice(value);
+                if *little_endian {
+                    b[..size].reverse();
+                }
+                let value: f64 = match value_type {
+                    ScalarType::I8 => f64::from(i8::from_be_bytes([b[0]])),
+                    ScalarType::U8 => f64::from(b[0]),
+                    ScalarType::I16 => f64::from(i16::from_be_bytes([b[0], b[1]])),
+                    ScalarType::U16 => f64::from(u16::from_be_bytes([b[0], b[1]])),
+                    ScalarType::I32 => f64::from(i32::from_be_bytes([b[0], b[1], b[2], b[3]])),
+                    ScalarType::U32 => f64::from(u32::from_be_bytes([b[0], b[1], b[2], b[3]])),
+                    ScalarType::F32 => f64::from(f32::from_be_bytes([b[0], b[1], b[2], b[3]])),
+                    ScalarType::F64 => f64::from_be_bytes(b),
+                };
+                Ok(value)
+            }
+        }
+    }
+
+    fn read_index(&mut self, value_type: ScalarType) -> Result<u32, String> {
+        let value: f64 = self.read(value_type)?;
+        PlyMesh::index(value)
+    }
+}
+
+
+#[derive(Debug, Default)]
+struct PlyMesh {
+    positions: Vec<Point3f>,
+    normals: Vec<Vec3f>,
+    uvs: Vec<(f64, f64)>,
+    colors: Vec<Color>,
+    indices: Vec<[u32; 3]>,
+}
+
+impl PlyMesh {
+    fn read(bytes: &[u8]) -> Result<Self, String> {
+        let (encoding, elements, body) = Self::read_header(bytes)?;
+        let mut reader: BodyReader = match encoding {
+            Encoding::Ascii => BodyReader::Ascii(
+                std::str::from_utf8(body).map_err(|_| "ASCII body is not valid text".to_string())?.split_ascii_whitespace()
+            ),
+            Encoding::BinaryLittleEndian => BodyReader::Binary { bytes: body, little_endian: true },
+            Encoding::BinaryBigEndian => BodyReader::Binary { bytes: body, little_endian: false },
+        };
+
+        let mut mesh: PlyMesh = PlyMesh::default();
+        for element in &elements {
+            match element.name.as_str() {
+                "vertex" => mesh.read_vertices(element, &mut reader)?,
+                "face" => mesh.read_faces(element, &mut reader)?,
+                _ => {
+                    let mut values: Vec<f64> = Vec::new();
+                    for _ in 0..element.count {
+                        Self::read_values(element, &mut reader, &mut values, &mut Vec::new(), &[])?;
+                    }
+                }
+            }
+        }
+        Ok(mesh)
+    }
+
+    fn index(value: f64) -> Result<u32, String> {
+        if value < 0.0 || value > u32::MAX as f64 || value.fract() != 0.0 {
+            return Err(format!("invalid index {}", value));
+        }
+        Ok(value as u32)
+    }
+
+    fn read_header(bytes: &[u8]) -> Result<(Encoding, Vec<Element>, &[u8]), String> {
+        const END_HEADER: &[u8] = b"end_header";
+        let header_end: usize = bytes.windows(END_HEADER.len()).position(|window| window == END_HEADER)
+            .ok_or("missing end_header")?;
+        let header: &str = std::str::from_utf8(&bytes[..header_end]).map_err(|_| "header is not valid text".to_string())?;
+
+        // The body starts after the line ending of end_header.
+        let mut body: &[u8] = &bytes[header_end + END_HEADER.len()..];
+        if body.starts_with(b"\r") {
+            body = &body[1..];
+        }
+        if body.starts_with(b"\n") {
+            body = &body[1..];
+        }
+
+        let mut lines = header.lines().map(str::trim);
+        if lines.next() != Some("ply") {
+            return Err("not a PLY file".to_string());
+        }
+
+        let mut encoding: Option<Encoding> = None;
+        let mut elements: Vec<Element> = Vec::new();
+        for line in lines {
+            let words: Vec<&str> = line.split_ascii_whitespace().collect();
+            match words[..] {
+                [] | ["comment", ..] | ["obj_info", ..] => (),
+                ["format", format, _version] => {
+                    encoding = Some(match format {
+                        "ascii" => Encoding::Ascii,
+                        "binary_little_endian" => Encoding::BinaryLittleEndian,
+                        "binary_big_endian" => Encoding::BinaryBigEndian,
+                        _ => return Err(format!("unknown format '{}'", format))
+                    });
+                },
+                ["element", name, count] => {
+                    let count: usize = count.parse().map_err(|_| format!("invalid element count '{}'", count))?;
+                    elements.push(Element { name: name.to_string(), count, properties: Vec::new() });
+                },
+                ["property", "list", count_type, item_type, name] => {
+                    let element: &mut Element = elements.last_mut().ok_or("property before any element")?;
+                    element.properties.push(Property::List {
+                        name: name.to_string(),
+                        count_type: ScalarType::parse(count_type)?,
+                        item_type: ScalarType::parse(item_type)?
+                    });
+                },
+                ["property", value_type, name] => {
+                    let element: &mut Element = elements.last_mut().ok_or("property before any element")?;
+                    element.properties.push(Property::Scalar { name: name.to_string(), value_type: ScalarType::parse(value_type)? });
+                },
+                _ => return Err(format!("invalid header line '{}'", line))
+            }
+        }
+
+        let encoding: Encoding = encoding.ok_or("missing format")?;
+        Ok((encoding, elements, body))
+    }
+
+    fn read_values(
+        element: &Element, 
+        reader: &mut BodyReader, 
+        scalars: &mut Vec<f64>, 
+        list: &mut Vec<u32>, 
+        list_names: &[&str]
+    ) -> Result<(), String> {
+        // Reads one instance of the element: its scalar properties into scalars, in order, and the
+        // items of the list property named in list_names into list. Other lists are skipped.
+        scalars.clear();
+        for property in &element.properties {
+            match property {
+                Property::Scalar { value_type, .. } => scalars.push(reader.read(*value_type)?),
+                Property::List { name, count_type, item_type } => {
+                    scalars.push(0.0);
+                    let count: u32 = reader.read_index(*count_type)?;
+                    let keep: bool = list_names.contains(&name.as_str());
+                    if keep {
+                        list.clear();
+                    }
+                    for _ in 0..count {
+                        let item: f64 = reader.read(*item_type)?;
+                        if keep {
+                            list.push(Self::index(item)?);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn read_vertices(&mut self, element: &Element, reader: &mut BodyReader) -> Result<(), String> {
+        let find = |names: [&[&str]; 3]| -> Option<[usize; 3]> {
+            Some([element.scalar(names[0])?, element.scalar(names[1])?, element.scalar(names[2])?])
+        };
+        let position: [usize; 3] = find([&["x"], &["y"], &["z"]]).ok_or("vertices have no x, y, z")?;
+        let normal: Option<[usize; 3]> = find([&["nx"], &["ny"], &["nz"]]);
+        let uv: Option<[usize; 2]> = element.scalar(&["u", "s", "texture_u", "texture_s"])
+            .zip(element.scalar(&["v", "t", "texture_v", "texture_t"]))
+            .map(|(u, v)| [u, v]);
+        let color: Option<[usize; 3]> = find([&["red", "r", "diffuse_red"], &["green", "g", "diffuse_green"], &["blue", "b", "diffuse_blue"]]);
+        let color_type: Option<ScalarType> = color.map(|[r, _, _]| element.scalar_type(r));
+        let color_scale: f64 = color_type.map_or(1.0, |color_type| color_type.color_scale());
+        let srgb: bool = color_type.is_some_and(|color_type| color_type.is_srgb());
+        let decode = |value: f64| -> f64 {
+            if srgb { color::srgb_to_linear(color_scale * value) } else { color_scale * value }
+        };
+
+        let mut values: Vec<f64> = Vec::with_capacity(element.properties.len());
+        let mut list: Vec<u32> = Vec::new();
+        for _ in 0..element.count {
+            Self::read_values(element, reader, &mut values, &mut list, &[])?;
+            self.positions.push(Point3f::new(values[position[0]], values[position[1]], values[position[2]]));
+            if let Some([x, y, z]) = normal {
+                self.normals.push(Vec3f::new(values[x], values[y], values[z]));
+            }
+            if let Some([u, v]) = uv {
+                self.uvs.push((values[u], values[v]));
+            }
+            if let Some([r, g, b]) = color {
+                self.colors.push(Color::new(decode(values[r]), decode(values[g]), decode(values[b])));
+            }
+        }
+        Ok(())
+    }
+
+    fn read_faces(&mut self, element: &Element, reader: &mut BodyReader) -> Result<(), String> {
+        const INDEX_NAMES: [&str; 2] = ["vertex_indices", "vertex_index"];
+        let has_indices: bool = element.properties.iter().any(
+            |property| matches!(property, Property::List { .. }) && INDEX_NAMES.contains(&property.name())
+        );
+        if !has_indices {
+            return Err("faces have no vertex_indices".to_string());
+        }
+
+        let mut values: Vec<f64> = Vec::with_capacity(element.properties.len());
+        let mut list: Vec<u32> = Vec::new();
+        for _ in 0..element.count {
+            Self::read_values(element, reader, &mut values, &mut list, &INDEX_NAMES)?;
+
+            // Triangulate polygons as a fan around their first vertex.
+            for i in 2..list.len() {
+                self.indices.push([list[0], list[i - 1], list[i]]);
+            }
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::hittable::{HitRecord, Hittable};
+    use crate::interval::Interval;
+    use crate::ply::*;
+    use crate::ray::Ray;
+    use crate::texture::Texture;
+
+    const ASCII_SQUARE: &str = "\
+ply
+format ascii 1.0
+comment unit square with a red and a blue edge
+element vertex 4
+property float x
+property float y
+property float z
+property uchar red
+property uchar green
+property uchar blue
+element face 1
+property list uchar int vertex_indices
+property list uchar float texcoord
+end_header
+0 0 0 255 0 0
+1 0 0 255 0 0
+1 1 0 0 0 255
+0 1 0 0 0 255
+4 0 1 2 3 8 0 0 1 0 1 1 0 1
+";
+
+    fn binary_square(little_endian: bool) -> Vec<u8> {
+        let format: &str = if little_endian { "binary_little_endian" } else { "binary_big_endian" };
+        let mut bytes: Vec<u8> = format!("\
+ply
+format {} 1.0
+element vertex 4
+property double x
+property double y
+property double z
+property float red
+property float green
+property float blue
+element face 2
+property list uchar uint vertex_indices
+end_header
+", format).into_bytes();
+
+        let vertices: [([f64; 3], [f32; 3]); 4] = [
+            ([0.0, 0.0, 0.0], [1.0, 0.0, 0.0]),
+            ([1.0, 0.0, 0.0], [1.0, 0.0, 0.0]),
+            ([1.0, 1.0, 0.0], [0.0, 0.0, 1.0]),
+            ([0.0, 1.0, 0.0], [0.0, 0.0, 1.0]),
+        ];
+        for (position, color) in vertices {
+            for x in position {
+                bytes.extend(if little_endian { x.to_le_bytes() } else { x.to_be_bytes() });
+            }
+            for c in color {
+                bytes.extend(if little_endian { c.to_le_bytes() } else { c.to_be_bytes() });
+            }
+        }
+        for face in [[0u32, 1, 2], [0, 2, 3]] {
+            bytes.push(3);
+            for index in face {
+                bytes.extend(if little_endian { index.to_le_bytes() } else { index.to_be_bytes() });
+            }
+        }
+        bytes
+    }
+
+    fn assert_interpolated_colors(mesh: &TriangleMesh) {
+        assert_eq!(mesh.triangle_count(), 2);
+
+        // Halfway up the square the color is halfway between the red and blue edges.
+        let ray: Ray = Ray::new(&Point3f::new(0.25, 0.5, 1.0), &-Vec3f::E3);
+        let rec: HitRecord = mesh.hit(&ray, &Interval::new(0.001, f64::INFINITY)).unwrap();
+        let scatter_color: Color = rec.mat.scatter(&ray, &rec, (0.5, 0.5)).unwrap().attenuation;
+        assert!((scatter_color - Color::new(0.5, 0.0, 0.5)).length() < 1e-6, "{}", scatter_color);
+    }
+
+    #[test]
+    fn ascii() {
+        assert_interpolated_colors(&from_bytes(ASCII_SQUARE.as_bytes(), None).unwrap());
+    }
+
+    #[test]
+    fn srgb_colors() {
+        // 8-bit colors are sRGB encoded: mid grey is about a fifth of white in linear terms.
+        let ply: PlyMesh = PlyMesh::read(ASCII_SQUARE.replace("255 0 0", "128 128 128").as_bytes()).unwrap();
+        assert!((ply.colors[0] - Color::new(0.2158605, 0.2158605, 0.2158605)).length() < 1e-6, "{}", ply.colors[0]);
+        assert_eq!(ply.colors[2], Color::new(0.0, 0.0, 1.0));
+
+        // Without a face to interpolate over, vertex colors give their mean.
+        let texture: VertexColor = VertexColor::new(ply.colors);
+        assert!((texture.value((0.0, 0.0), &Point3f::ZERO) - Color::new(0.1079302, 0.1079302, 0.6079302)).length() < 1e-6);
+    }
+
+    #[test]
+    fn binary() {
+        assert_interpolated_colors(&from_bytes(&binary_square(true), None).unwrap());
+        assert_interpolated_colors(&from_bytes(&binary_square(false), None).unwrap());
+    }
+
+    #[test]
+    fn truncated() {
+        let bytes: Vec<u8> = binary_square(true);
+        let err: String = from_bytes(&bytes[..bytes.len() - 2], None).err().unwrap();
+        assert_eq!(err, "unexpected end of file");
+
+        let err: String = from_bytes(b"ply\nformat ascii 1.0\nelement vertex 1\nproperty float x\n", None).err().unwrap();
+        assert_eq!(err, "missing end_header");
+    }
+}
@@ -0,0 +1,166 @@
+use std::fmt;
+use std::ops::Mul;
+
+use crate::matrix::Matrix4;
+use crate::utilities;
+use crate::vec3::{Axis, Vec3f};
+
+// A unit quaternion w + xi + yj + zk representing a rotation. Unlike Euler angles, rotations
+// interpolate smoothly along the shortest arc between two orientations.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quaternion {
+    w: f64,
+    x: f64,
+    y: f64,
+    z: f64
+}
+
+impl fmt::Display for Quaternion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {} {}", self.w, self.x, self.y, self.z)
+    }
+}
+
+impl Default for Quaternion {
+    fn default() -> Self {
+        Quaternion::IDENTITY
+    }
+}
+
+impl Quaternion {
+    pub const IDENTITY: Quaternion = Quaternion { w: 1.0, x: 0.0, y: 0.0, z: 0.0 };
+
+    pub fn from_axis_angle(axis: &Vec3f, degrees: f64) -> Self {
+        let half_angle: f64 = 0.5 * utilities::degrees_to_radians(degrees);
+        let (sin, cos) = half_angle.sin_cos();
+        let axis: Vec3f = Vec3f::unit_vector(axis) * sin;
+        Self { w: cos, x: axis.component(Axis::X), y: axis.component(Axis::Y), z: axis.component(Axis::Z) }
+    }
+
+    pub fn from_euler(degrees: &Vec3f) -> Self {
+        // Same convention as Matrix4::euler_rotation: about X first, then Y, then Z.
+        Self::from_axis_angle(&Vec3f::E3, degrees.component(Axis::Z))
+            * Self::from_axis_angle(&Vec3f::E2, degrees.component(Axis::Y))
+            * Self::from_axis_angle(&Vec3f::E1, degrees.component(Axis::X))
+    }
+
+    pub fn from_matrix(matrix: &Matrix4) -> Self {
+        // Rotation matrix to quaternion, dividing by the largest of the four components for
+        // precision.
+        let m = |row: usize, column: usize| matrix.get(row, column);
+        let trace: f64 = m(0, 0) + m(1, 1) + m(2, 2);
+        let quaternion: Quaternion = if trace > 0.0 {
+            let s: f64 = 2.0 * (trace + 1.0).sqrt();
+            Quaternion { w: 0.25 * s, x: (m(2, 1) - m(1, 2)) / s, y: (m(0, 2) - m(2, 0)) / s, z: (m(1, 0) - m(0, 1)) / s }
+        }
+        else if m(0, 0) > m(1, 1) && m(0, 0) > m(2, 2) {
+            let s: f64 = 2.0 * (1.0 + m(0, 0) - m(1, 1) - m(2, 2)).sqrt();
+            Quaternion { w: (m(2, 1) - m(1, 2)) / s, x: 0.25 * s, y: (m(0, 1) + m(1, 0)) / s, z: (m(0, 2) + m(2, 0)) / s }
+        }
+        else if m(1, 1) > m(2, 2) {
+            let s: f64 = 2.0 * (1.0 + m(1, 1) - m(0, 0) - m(2, 2)).sqrt();
+            Quaternion { w: (m(0, 2) - m(2, 0)) / s, x: (m(0, 1) + m(1, 0)) / s, y: 0.25 * s, z: (m(1, 2) + m(2, 1)) / s }
+        }
+        else {
+            let s: f64 = 2.0 * (1.0 + m(2, 2) - m(0, 0) - m(1, 1)).sqrt();
+            Quaternion { w: (m(1, 0) - m(0, 1)) / s, x: (m(0, 2) + m(2, 0)) / s, y: (m(1, 2) + m(2, 1)) / s, z: 0.25 * s }
+        };
+        quaternion.normalized()
+    }
+
+    #[inline]
+    pub fn dot(&self, other: &Quaternion) -> f64 {
+        self.w * other.w + self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    pub fn angle_to(&self, other: &Quaternion) -> f64 {
+        // Angle in radians of the rotation taking self to other, the short way round.
+        2.0 * self.dot(other).abs().min(1.0).acos()
+    }
+
+    pub fn normalized(&self) -> Self {
+        let recip_length: f64 = 1.0 / self.dot(self).sqrt();
+        Self { w: self.w * recip_length, x: self.x * recip_length, y: self.y * recip_length, z: self.z * recip_length }
+    }
+
+    pub fn slerp(&self, other: &Quaternion, t: f64) -> Self {
+        // q and -q are the same rotation; flipping one onto the same hemisphere takes the short way.
+        let mut cos_theta: f64 = self.dot(other);
+        let other: Quaternion = if cos_theta < 0.0 {
+            cos_theta = -cos_theta;
+            Quaternion { w: -other.w, x: -other.x, y: -other.y, z: -other.z }
+        }
+        else {
+            *other
+        };
+
+        // Nearly parallel rotations fall back to a normalized linear interpolation.
+        let (a, b) = if cos_theta > 0.9995 {
+            (1.0 - t, t)
+        }
+        else {
+            let theta: f64 = cos_theta.acos();
+            let recip_sin_theta: f64 = 1.0 / theta.sin();
+            (((1.0 - t) * theta).sin() * recip_sin_theta, (t * theta).sin() * recip_sin_theta)
+        };
+        Quaternion {
+            w: a * self.w + b * other.w,
+            x: a * self.x + b * other.x,
+            y: a * self.y + b * other.y,
+            z: a * self.z + b * other.z
+        }.normalized()
+    }
+
+    pub fn to_matrix(&self) -> Matrix4 {
+        let Quaternion { w, x, y, z } = *self;
+        Matrix4::new([
+            [1.0 - 2.0 * (y * y + z * z), 2.0 * (x * y - w * z), 2.0 * (x * z + w * y), 0.0],
+            [2.0 * (x * y + w * z), 1.0 - 2.0 * (x * x + z * z), 2.0 * (y * z - w * x), 0.0],
+            [2.0 * (x * z - w * y), 2.0 * (y * z + w * x), 1.0 - 2.0 * (x * x + y * y), 0.0],
+            [0.0, 0.0, 0.0, 1.0]
+        ])
+    }
+}
+
+impl Mul for Quaternion {
+    type Output = Quaternion;
+
+    fn mul(self, other: Quaternion) -> Quaternion {
+        // Hamilton product: the result rotates by other first, then by self.
+        Quaternion {
+            w: self.w * other.w - self.x * other.x - self.y * other.y - self.z * other.z,
+            x: self.w * other.x + self.x * other.w + self.y * other.z - self.z * other.y,
+            y: self.w * other.y - self.x * other.z + self.y * other.w + self.z * other.x,
+            z: self.w * other.z + self.x * other.y - self.y * other.x + self.z * other.w
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::quaternion::*;
+
+    #[test]
+    fn rotation() {
+        for degrees in [Vec3f::new(90.0, 0.0, 0.0), Vec3f::new(10.0, 20.0, 30.0), Vec3f::new(-45.0, 170.0, 80.0)] {
+            let expected: Matrix4 = Matrix4::euler_rotation(&degrees);
+            let matrix: Matrix4 = Quaternion::from_euler(&degrees).to_matrix();
+            for row in 0..4 {
+                for column in 0..4 {
+                    assert!((matrix.get(row, column) - expected.get(row, column)).abs() < 1e-12, "{} != {}", matrix, expected);
+                }
+            }
+        }
+
+        // Halfway between 0 and 90 degrees about Y, and the short way round from 350 to 10 degrees.
+        let halfway: Quaternion = Quaternion::IDENTITY.slerp(&Quaternion::from_axis_angle(&Vec3f::E2, 90.0), 0.5);
+        let rotated: Vec3f = halfway.to_matrix().transform_vector(&Vec3f::E1);
+        assert!((rotated - Vec3f::new(1.0, 0.0, -1.0) / 2.0_f64.sqrt()).length() < 1e-12);
+        assert!((halfway.angle_to(&Quaternion::IDENTITY) - utilities::degrees_to_radians(45.0)).abs() < 1e-12);
+        let recovered: Quaternion = Quaternion::from_matrix(&halfway.to_matrix());
+        assert!((recovered.angle_to(&halfway)).abs() < 1e-6);
+        let wrapped: Quaternion = Quaternion::from_axis_angle(&Vec3f::E2, 350.0).slerp(&Quaternion::from_axis_angle(&Vec3f::E2, 10.0), 0.5);
+        assert!((wrapped.to_matrix().transform_vector(&Vec3f::E1) - Vec3f::E1).length() < 1e-12);
+    }
+}
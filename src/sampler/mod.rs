@@ -0,0 +1,487 @@
+use std::sync::{Once, OnceLock};
+
+use clap::ValueEnum;
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::utilities;
+
+// Samplers supply the random numbers of each pixel sample one dimension at a time: the position in
+// the pixel, the lens, the time, then the numbers drawn at each bounce. Better than independent
+// numbers, the samples of a pixel cover each dimension evenly, so fewer are needed for the same
+// noise. Every sampler handles any number of samples per pixel.
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SamplerMethod {
+    /// Independent uniform random numbers
+    Independent,
+    /// Correlated multi-jittered strata in each dimension
+    Stratified,
+    /// Owen-scrambled Halton sequence
+    Halton,
+    /// Owen-scrambled Sobol points, shuffled independently for each dimension
+    #[default]
+    Sobol,
+    /// Sobol points ordered along a Morton curve over the image, which leaves the remaining noise
+    /// as blue noise between neighbouring pixels
+    BlueNoise,
+}
+
+impl SamplerMethod {
+    pub fn sampler(self, samples_per_pixel: u32, seed: u64, resolution: (u32, u32)) -> Box<dyn Sampler> {
+        let samples_per_pixel: u32 = samples_per_pixel.max(1);
+        match self {
+            SamplerMethod::Independent => Box::new(IndependentSampler),
+            SamplerMethod::Stratified => Box::new(StratifiedSampler { dimensions: Dimensions::new(seed), samples_per_pixel }),
+            SamplerMethod::Halton => Box::new(HaltonSampler { dimensions: Dimensions::new(seed) }),
+            SamplerMethod::Sobol => Box::new(SobolSampler { dimensions: Dimensions::new(seed), samples_per_pixel }),
+            SamplerMethod::BlueNoise => {
+                if BlueNoiseSampler::index_bits(samples_per_pixel, resolution) > 32 {
+                    // Longer indices would wrap around, and distant pixels would share samples.
+                    static WARNING: Once = Once::new();
+                    WARNING.call_once(|| warn!(
+                        "The blue-noise sampler can't index {} samples for each of {}x{} pixels; using sobol instead",
+                        samples_per_pixel, resolution.0, resolution.1
+                    ));
+                    return SamplerMethod::Sobol.sampler(samples_per_pixel, seed, resolution);
+                }
+                Box::new(BlueNoiseSampler::new(samples_per_pixel, seed, resolution))
+            },
+        }
+    }
+}
+
+
+pub trait Sampler {
+    // Starts sample `index` of the pixel, from its first dimension again.
+    fn start_pixel_sample(&mut self, pixel: (u32, u32), index: u32);
+
+    fn get_1d(&mut self) -> f64;
+
+    fn get_2d(&mut self) -> (f64, f64);
+}
+
+
+// Draws from the thread's random numbers, which the camera seeds for every pixel sample.
+pub struct IndependentSampler;
+
+impl Sampler for IndependentSampler {
+    fn start_pixel_sample(&mut self, _pixel: (u32, u32), _index: u32) {}
+
+    fn get_1d(&mut self) -> f64 {
+        utilities::random()
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (utilities::random(), utilities::random())
+    }
+}
+
+
+// The pixel, sample and dimension being sampled. Each dimension of a pixel gets its own hash,
+// which decorrelates the patterns of different dimensions and pixels while keeping the samples of
+// one pixel in one dimension a single well spread set.
+struct Dimensions {
+    seed: u64,
+    pixel_hash: u64,
+    index: u32,
+    dimension: u64
+}
+
+impl Dimensions {
+    fn new(seed: u64) -> Self {
+        Self { seed, pixel_hash: 0, index: 0, dimension: 0 }
+    }
+
+    fn start(&mut self, pixel: (u32, u32), index: u32) {
+        self.pixel_hash = utilities::hash(&[self.seed, pixel.0 as u64, pixel.1 as u64]);
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn next(&mut self, count: u64) -> (u64, u64) {
+        // Returns the first of the next count dimensions and its hash.
+        let dimension: u64 = self.dimension;
+        self.dimension += count;
+        (dimension, utilities::hash(&[self.pixel_hash, dimension]))
+    }
+}
+
+
+pub struct StratifiedSampler {
+    dimensions: Dimensions,
+    samples_per_pixel: u32
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_pixel_sample(&mut self, pixel: (u32, u32), index: u32) {
+        self.dimensions.start(pixel, index);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        // One sample in each of samples_per_pixel strata, visited in a random order.
+        let (_, hash) = self.dimensions.next(1);
+        let index: u32 = self.dimensions.index;
+        let stratum: u32 = permute(index, self.samples_per_pixel, hash as u32);
+        (stratum as f64 + jitter(index, hash >> 32)) / self.samples_per_pixel as f64
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        // Kensler's correlated multi-jittered sampling: stratified on an m x n grid and in each of
+        // the m and n columns and rows as well. When the count doesn't fill the grid, the samples
+        // take a random subset of its cells so that none of the pixel is favoured.
+        let (_, hash) = self.dimensions.next(2);
+        let (count, p) = (self.samples_per_pixel, hash as u32);
+        let m: u32 = (count as f64).sqrt() as u32;
+        let n: u32 = count.div_ceil(m);
+        let s: u32 = permute(self.dimensions.index, m * n, p.wrapping_mul(0x51633e2d));
+        let sx: u32 = permute(s % m, m, p.wrapping_mul(0xa511e9b3));
+        let sy: u32 = permute(s / m, n, p.wrapping_mul(0x63d83595));
+        let jx: f64 = jitter(s, hash ^ 0xa399d265);
+        let jy: f64 = jitter(s, hash ^ 0x711ad6a5);
+        (
+            ((s % m) as f64 + (sy as f64 + jx) / n as f64) / m as f64,
+            ((s / m) as f64 + (sx as f64 + jy) / m as f64) / n as f64
+        )
+    }
+}
+
+
+pub struct HaltonSampler {
+    dimensions: Dimensions
+}
+
+impl HaltonSampler {
+    const PRIME_COUNT: usize = 256;
+
+    fn prime(dimension: u64) -> u64 {
+        // Dimensions beyond the table reuse its bases with a different scramble.
+        static PRIMES: OnceLock<Vec<u64>> = OnceLock::new();
+        let primes: &Vec<u64> = PRIMES.get_or_init(
+            || {
+                let mut primes: Vec<u64> = Vec::with_capacity(Self::PRIME_COUNT);
+                let mut candidate: u64 = 2;
+                while primes.len() < Self::PRIME_COUNT {
+                    if primes.iter().take_while(|&&p| p * p <= candidate).all(|&p| !candidate.is_multiple_of(p)) {
+                        primes.push(candidate);
+                    }
+                    candidate += 1;
+                }
+                primes
+            }
+        );
+        primes[dimension as usize % Self::PRIME_COUNT]
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn start_pixel_sample(&mut self, pixel: (u32, u32), index: u32) {
+        self.dimensions.start(pixel, index);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let (dimension, hash) = self.dimensions.next(1);
+        owen_scrambled_radical_inverse(Self::prime(dimension), self.dimensions.index as u64, hash)
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (self.get_1d(), self.get_1d())
+    }
+}
+
+
+// Every dimension draws from the first two dimensions of the Sobol sequence, which need no tables,
+// with the sample order shuffled per dimension so that dimensions are not correlated.
+pub struct SobolSampler {
+    dimensions: Dimensions,
+    samples_per_pixel: u32
+}
+
+impl Sampler for SobolSampler {
+    fn start_pixel_sample(&mut self, pixel: (u32, u32), index: u32) {
+        self.dimensions.start(pixel, index);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let (_, hash) = self.dimensions.next(1);
+        let index: u32 = permute(self.dimensions.index, self.samples_per_pixel, hash as u32);
+        to_unit(owen_scramble(sobol(index, 0), utilities::mix_bits(hash)))
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let (_, hash) = self.dimensions.next(2);
+        let index: u32 = permute(self.dimensions.index, self.samples_per_pixel, hash as u32);
+        (
+            to_unit(owen_scramble(sobol(index, 0), utilities::mix_bits(hash))),
+            to_unit(owen_scramble(sobol(index, 1), utilities::mix_bits(hash ^ 1)))
+        )
+    }
+}
+
+
+// Ahmed and Wonka's z-order Sobol sampler: the pixels' samples are consecutive blocks of one Sobol
+// sequence, ordered along a Morton curve and shuffled by randomly permuting base 4 digits, so
+// neighbouring pixels get complementary samples. The sequence index is 32 bits, enough for 4096 x
+// 4096 pixels at 256 samples each; SamplerMethod::sampler falls back to Sobol beyond that.
+pub struct BlueNoiseSampler {
+    seed: u64,
+    log2_samples: u32,
+    base4_digits: u32,
+    morton_index: u64,
+    dimension: u64
+}
+
+impl BlueNoiseSampler {
+    const PERMUTATIONS: [[u64; 4]; 24] = [
+        [0, 1, 2, 3], [0, 1, 3, 2], [0, 2, 1, 3], [0, 2, 3, 1], [0, 3, 2, 1], [0, 3, 1, 2],
+        [1, 0, 2, 3], [1, 0, 3, 2], [1, 2, 0, 3], [1, 2, 3, 0], [1, 3, 2, 0], [1, 3, 0, 2],
+        [2, 1, 0, 3], [2, 1, 3, 0], [2, 0, 1, 3], [2, 0, 3, 1], [2, 3, 0, 1], [2, 3, 1, 0],
+        [3, 1, 2, 0], [3, 1, 0, 2], [3, 2, 1, 0], [3, 2, 0, 1], [3, 0, 2, 1], [3, 0, 1, 2]
+    ];
+
+    fn log2_sizes(samples_per_pixel: u32, resolution: (u32, u32)) -> (u32, u32) {
+        // The powers of two the samples of a pixel and the image's square of pixels round up to.
+        let log2_samples: u32 = samples_per_pixel.max(1).next_power_of_two().trailing_zeros();
+        let log2_resolution: u32 = resolution.0.max(resolution.1).max(1).next_power_of_two().trailing_zeros();
+        (log2_samples, log2_resolution)
+    }
+
+    fn index_bits(samples_per_pixel: u32, resolution: (u32, u32)) -> u32 {
+        let (log2_samples, log2_resolution) = Self::log2_sizes(samples_per_pixel, resolution);
+        2 * log2_resolution + log2_samples
+    }
+
+    fn new(samples_per_pixel: u32, seed: u64, resolution: (u32, u32)) -> Self {
+        let (log2_samples, log2_resolution) = Self::log2_sizes(samples_per_pixel, resolution);
+        Self { seed, log2_samples, base4_digits: log2_resolution + log2_samples.div_ceil(2), morton_index: 0, dimension: 0 }
+    }
+
+    fn sample_index(&self) -> u32 {
+        // Permutes each base 4 digit of the Morton index depending on the digits above it and on the
+        // dimension. An odd power of two samples leaves a last base 2 digit, which is flipped or not.
+        let odd_samples: bool = self.log2_samples & 1 == 1;
+        let dimension_hash: u64 = 0x5555_5555_u64.wrapping_mul(self.dimension) ^ self.seed;
+        let mut index: u64 = 0;
+        for digit_index in (odd_samples as u32..self.base4_digits).rev() {
+            let shift: u32 = 2 * digit_index - odd_samples as u32;
+            let digit: u64 = (self.morton_index >> shift) & 3;
+            let higher_digits: u64 = self.morton_index >> (shift + 2);
+            let permutation: usize = ((utilities::mix_bits(higher_digits ^ dimension_hash) >> 24) % 24) as usize;
+            index |= Self::PERMUTATIONS[permutation][digit as usize] << shift;
+        }
+        if odd_samples {
+            let digit: u64 = self.morton_index & 1;
+            index |= digit ^ (utilities::mix_bits((self.morton_index >> 1) ^ dimension_hash) & 1);
+        }
+        index as u32
+    }
+
+    fn next(&mut self, count: u64) -> (u32, u64) {
+        let index: u32 = self.sample_index();
+        let hash: u64 = utilities::hash(&[self.seed, self.dimension]);
+        self.dimension += count;
+        (index, hash)
+    }
+}
+
+impl Sampler for BlueNoiseSampler {
+    fn start_pixel_sample(&mut self, pixel: (u32, u32), index: u32) {
+        self.morton_index = (morton(pixel.0, pixel.1) << self.log2_samples) | index as u64;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let (index, hash) = self.next(1);
+        to_unit(owen_scramble(sobol(index, 0), hash))
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let (index, hash) = self.next(2);
+        (
+            to_unit(owen_scramble(sobol(index, 0), hash)),
+            to_unit(owen_scramble(sobol(index, 1), utilities::mix_bits(hash)))
+        )
+    }
+}
+
+
+#[inline]
+fn to_unit(bits: u32) -> f64 {
+    bits as f64 / 4294967296.0
+}
+
+#[inline]
+fn jitter(index: u32, hash: u64) -> f64 {
+    to_unit((utilities::mix_bits(hash ^ ((index as u64) << 32)) >> 32) as u32)
+}
+
+fn permute(mut i: u32, count: u32, p: u32) -> u32 {
+    // Kensler's hashed permutation: element i of a random permutation of 0..count chosen by p,
+    // without storing the permutation.
+    let mut w: u32 = count - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= p;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= p >> 16;
+        i ^= (i & w) >> 4;
+        i ^= p >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= p >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | p >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < count {
+            break;
+        }
+    }
+    ((i as u64 + p as u64) % count as u64) as u32
+}
+
+fn sobol(index: u32, dimension: usize) -> u32 {
+    // The first two dimensions of the Sobol sequence: the base 2 radical inverse, and the one
+    // generated by the polynomial x + 1.
+    if dimension == 0 {
+        return index.reverse_bits();
+    }
+    let (mut result, mut direction, mut index) = (0_u32, 1_u32 << 31, index);
+    while index != 0 {
+        if index & 1 == 1 {
+            result ^= direction;
+        }
+        index >>= 1;
+        direction ^= direction >> 1;
+    }
+    result
+}
+
+fn owen_scramble(mut value: u32, seed: u64) -> u32 {
+    // Flips each bit depending on a hash of the bits above it, which keeps the stratification of
+    // the sequence while randomizing it.
+    if seed & 1 == 1 {
+        value ^= 1 << 31;
+    }
+    for bit in 1..32 {
+        let mask: u32 = !0 << (32 - bit);
+        if utilities::mix_bits((value & mask) as u64 ^ seed) & (1 << bit) != 0 {
+            value ^= 1 << (31 - bit);
+        }
+    }
+    value
+}
+
+fn owen_scrambled_radical_inverse(base: u64, mut index: u64, hash: u64) -> f64 {
+    // Mirrors the base b digits of index about the radix point, permuting each digit depending on
+    // the digits before it, until the digits are below double precision.
+    let recip_base: f64 = 1.0 / base as f64;
+    let mut recip_base_power: f64 = 1.0;
+    let mut reversed_digits: u64 = 0;
+    while 1.0 - recip_base_power < 1.0 {
+        let next: u64 = index / base;
+        let digit: u64 = index - next * base;
+        let digit: u64 = permute(digit as u32, base as u32, utilities::mix_bits(hash ^ reversed_digits) as u32) as u64;
+        reversed_digits = reversed_digits * base + digit;
+        recip_base_power *= recip_base;
+        index = next;
+    }
+    (recip_base_power * reversed_digits as f64).min(1.0 - f64::EPSILON / 2.0)
+}
+
+fn morton(x: u32, y: u32) -> u64 {
+    // Interleaves the bits of x and y.
+    let spread = |value: u32| -> u64 {
+        let mut value: u64 = value as u64;
+        value = (value | (value << 16)) & 0x0000_ffff_0000_ffff;
+        value = (value | (value << 8)) & 0x00ff_00ff_00ff_00ff;
+        value = (value | (value << 4)) & 0x0f0f_0f0f_0f0f_0f0f;
+        value = (value | (value << 2)) & 0x3333_3333_3333_3333;
+        (value | (value << 1)) & 0x5555_5555_5555_5555
+    };
+    spread(x) | (spread(y) << 1)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::sampler::*;
+
+    fn pixel_samples(method: SamplerMethod, count: u32, pixel: (u32, u32)) -> Vec<((f64, f64), f64)> {
+        let mut sampler: Box<dyn Sampler> = method.sampler(count, 3, (64, 48));
+        (0..count).map(
+            |index| {
+                sampler.start_pixel_sample(pixel, index);
+                (sampler.get_2d(), sampler.get_1d())
+            }
+        ).collect()
+    }
+
+    #[test]
+    fn stratification() {
+        // Sixteen samples put one in each cell of a 4 x 4 grid, and one in each sixteenth of 1D.
+        for method in [SamplerMethod::Stratified, SamplerMethod::Sobol, SamplerMethod::BlueNoise] {
+            for pixel in [(0, 0), (17, 5), (63, 47)] {
+                let samples = pixel_samples(method, 16, pixel);
+                let mut cells: Vec<usize> = samples.iter().map(|((x, y), _)| (*x * 4.0) as usize * 4 + (*y * 4.0) as usize).collect();
+                cells.sort_unstable();
+                assert_eq!(cells, (0..16).collect::<Vec<usize>>(), "{:?} at {:?}", method, pixel);
+            }
+        }
+        for method in [SamplerMethod::Stratified, SamplerMethod::Sobol, SamplerMethod::BlueNoise] {
+            let mut strata: Vec<usize> = pixel_samples(method, 16, (3, 4)).iter().map(|(_, u)| (u * 16.0) as usize).collect();
+            strata.sort_unstable();
+            assert_eq!(strata, (0..16).collect::<Vec<usize>>(), "{:?}", method);
+        }
+
+        // Halton points in bases 2 and 3 fill a 2 x 3 grid every six samples.
+        let mut cells: Vec<usize> = pixel_samples(SamplerMethod::Halton, 6, (3, 4)).iter().map(|((x, y), _)| (*x * 2.0) as usize * 3 + (*y * 3.0) as usize).collect();
+        cells.sort_unstable();
+        assert_eq!(cells, (0..6).collect::<Vec<usize>>());
+    }
+
+    #[test]
+    fn any_sample_count() {
+        utilities::seed(1);
+        for method in [SamplerMethod::Independent, SamplerMethod::Stratified, SamplerMethod::Halton, SamplerMethod::Sobol, SamplerMethod::BlueNoise] {
+            let samples = pixel_samples(method, 50, (9, 2));
+            assert!(samples.iter().all(|((x, y), u)| [x, y, u].iter().all(|&&value| (0.0..1.0).contains(&value))));
+            let mean: f64 = samples.iter().map(|((x, _), _)| x).sum::<f64>() / 50.0;
+            assert!((mean - 0.5).abs() < 0.1, "{:?} mean {}", method, mean);
+            if method != SamplerMethod::Independent {
+                assert_eq!(samples, pixel_samples(method, 50, (9, 2)), "{:?}", method);
+                assert_ne!(samples, pixel_samples(method, 50, (10, 2)), "{:?}", method);
+            }
+        }
+    }
+
+    #[test]
+    fn blue_noise_index_limit() {
+        // 4096 x 4096 pixels fit 256 samples each in a 32 bit index, but not 512; 8192 x 8192 pixels
+        // don't fit 128. Beyond the limit the samples are Sobol's.
+        let samples = |method: SamplerMethod, count: u32, resolution: (u32, u32)| -> Vec<(f64, f64)> {
+            let mut sampler: Box<dyn Sampler> = method.sampler(count, 3, resolution);
+            (0..count).map(
+                |index| {
+                    sampler.start_pixel_sample((4000, 17), index);
+                    sampler.get_2d()
+                }
+            ).collect()
+        };
+        assert_ne!(samples(SamplerMethod::BlueNoise, 256, (4096, 4096)), samples(SamplerMethod::Sobol, 256, (4096, 4096)));
+        for (count, resolution) in [(512, (4096, 4096)), (128, (8192, 4320))] {
+            assert_eq!(samples(SamplerMethod::BlueNoise, count, resolution), samples(SamplerMethod::Sobol, count, resolution));
+        }
+    }
+}
@@ -0,0 +1,1575 @@
+use std::any::Any;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize};
+use toml::Spanned;
+
+use crate::animation::{AnimatedObject, Animation, CameraAnimation, Interpolate, Interpolation, Keyframe, Track, TransformAnimation};
+use crate::bvh_node::BVHNode;
+use crate::camera::Camera;
+use crate::color::Color;
+use crate::constant_medium::ConstantMedium;
+use crate::gltf_scene::{self, GltfTexture};
+use crate::hittable::Hittable;
+use crate::hittable_list::HittableList;
+use crate::instance::Instance;
+use crate::material::{Dielectric, DiffuseLight, Empty, Isotropic, Lambertian, Material, Metal};
+use crate::matrix::Matrix4;
+use crate::mesh::TriangleMesh;
+use crate::obj;
+use crate::perlin::{Perlin, PerlinTexture};
+use crate::plane::{Plane, Quad, Tri};
+use crate::ply;
+use crate::quaternion::Quaternion;
+use crate::ray::Ray;
+use crate::sampler::SamplerMethod;
+use crate::shutter::{Shutter, ShutterCurve};
+use crate::sphere::Sphere;
+use crate::texture::{Checker, Image, Noise, Scaled, Solid, Texture, VertexColor};
+use crate::transform::{MotionTransformed, Transform, Transformed};
+use crate::vec3::{Point3f, Vec3f};
+
+// A scene file is a TOML document describing a camera, named textures and materials, and the
+// objects and light sampling shapes built from them:
+//
+//     [camera]
+//     aspect_ratio = 1.0
+//     image_width = 600
+//     ...
+//
+//     [materials.white]
+//     type = "lambertian"
+//     albedo = [0.73, 0.73, 0.73]
+//
+//     [[objects]]
+//     type = "box"
+//     a = [0, 0, 0]
+//     b = [165, 330, 165]
+//     material = "white"
+//     transform = [{ rotate = [0, 15, 0] }, { translate = [265, 0, 295] }]
+//
+// Named [prototypes.*] are objects that are only built once and placed by "instance" objects,
+// each with its own transform and optionally its own material.
+//
+// Any object can move within the shutter interval of a frame, from time 0 to 1, for motion blur.
+// Its motion keys are placements applied after its transform list:
+//
+//     motion = [{ time = 0, transform = [] }, { time = 1, transform = [{ rotate = [0, 30, 0] }] }]
+//
+// Top-level objects, lights and the camera may be animated with keyframes over scene time, in
+// seconds. An object's animation is applied after its static transform:
+//
+//     [objects.animation]
+//     interpolation = "spline"
+//     translate = [{ time = 0, value = [0, 0, 0] }, { time = 2, value = [100, 0, 0] }]
+//     rotate = [{ time = 0, value = [0, 0, 0] }, { time = 2, value = [0, 90, 0] }]
+
+// Objects are first read as plain tables, O = toml::Table, and only then as ObjectDesc. An object's
+// shape is read from a copy of its keys, which has lost their positions, so errors in it are
+// reported at the object instead.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields, bound(deserialize = "O: Deserialize<'de>"))]
+pub struct SceneDesc<O = ObjectDesc> {
+    pub camera: CameraDesc,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub textures: BTreeMap<String, Spanned<TextureDesc>>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub materials: BTreeMap<String, Spanned<MaterialDesc>>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub prototypes: BTreeMap<String, Spanned<O>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub objects: Vec<Spanned<O>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub lights: Vec<Spanned<O>>,
+}
+
+impl SceneDesc<toml::Table> {
+    fn read_objects(self, contents: &str) -> Result<SceneDesc, String> {
+        let read = |table: Spanned<toml::Table>| -> Result<Spanned<ObjectDesc>, String> {
+            let span = table.span();
+            toml::Value::Table(table.into_inner()).try_into().map(|desc| Spanned::new(span.clone(), desc))
+                .map_err(|err: toml::de::Error| format!("{}: {}", line_column(contents, span.start), err.message()))
+        };
+        Ok(SceneDesc {
+            camera: self.camera,
+            textures: self.textures,
+            materials: self.materials,
+            prototypes: self.prototypes.into_iter().map(|(name, table)| read(table).map(|desc| (name, desc))).collect::<Result<_, String>>()?,
+            objects: self.objects.into_iter().map(read).collect::<Result<_, String>>()?,
+            lights: self.lights.into_iter().map(read).collect::<Result<_, String>>()?,
+        })
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct CameraDesc {
+    pub aspect_ratio: f64,
+    pub image_width: u32,
+    pub samples_per_pixel: u32,
+    pub max_depth: u32,
+    #[serde(default = "CameraDesc::default_russian_roulette_depth")]
+    pub russian_roulette_depth: u32,
+    pub background: Color,
+    pub vertical_fov: f64,
+    pub lookfrom: Point3f,
+    pub lookat: Point3f,
+    #[serde(default = "CameraDesc::default_vup")]
+    pub vup: Vec3f,
+    #[serde(default)]
+    pub defocus_angle: f64,
+    #[serde(default = "CameraDesc::default_focus_dist")]
+    pub focus_dist: f64,
+    // Fractions of the frame duration, see Shutter.
+    #[serde(default)]
+    pub shutter_open: f64,
+    #[serde(default = "CameraDesc::default_shutter_close")]
+    pub shutter_close: f64,
+    #[serde(default)]
+    pub shutter_curve: ShutterCurve,
+    #[serde(default)]
+    pub sampler: SamplerMethod,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub animation: Option<CameraAnimationDesc>,
+}
+
+impl From<&Camera> for CameraDesc {
+    fn from(cam: &Camera) -> Self {
+        Self {
+            aspect_ratio: cam.aspect_ratio,
+            image_width: cam.image_width,
+            samples_per_pixel: cam.samples_per_pixel,
+            max_depth: cam.max_depth,
+            russian_roulette_depth: cam.russian_roulette_depth,
+            background: cam.background,
+            vertical_fov: cam.vertical_fov,
+            lookfrom: cam.lookfrom,
+            lookat: cam.lookat,
+            vup: cam.vup,
+            defocus_angle: cam.defocus_angle,
+            focus_dist: cam.focus_dist,
+            shutter_open: cam.shutter.open(),
+            shutter_close: cam.shutter.close(),
+            shutter_curve: cam.shutter.curve().clone(),
+            sampler: cam.sampler,
+            animation: None,
+        }
+    }
+}
+
+impl CameraDesc {
+    fn default_vup() -> Vec3f {
+        Vec3f::E2
+    }
+
+    fn default_focus_dist() -> f64 {
+        10.0
+    }
+
+    fn default_shutter_close() -> f64 {
+        1.0
+    }
+
+    fn default_russian_roulette_depth() -> u32 {
+        3
+    }
+}
+
+// Keyframed camera parameters; the ones left out keep their value from the [camera] table.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct CameraAnimationDesc {
+    #[serde(default)]
+    pub interpolation: Interpolation,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub lookfrom: Vec<Keyframe<Point3f>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub lookat: Vec<Keyframe<Point3f>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub vertical_fov: Vec<Keyframe<f64>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub focus_dist: Vec<Keyframe<f64>>,
+}
+
+impl CameraAnimationDesc {
+    fn animation(&self) -> Result<CameraAnimation, String> {
+        Ok(CameraAnimation {
+            lookfrom: track(&self.lookfrom, self.interpolation)?,
+            lookat: track(&self.lookat, self.interpolation)?,
+            vertical_fov: track(&self.vertical_fov, self.interpolation)?,
+            focus_dist: track(&self.focus_dist, self.interpolation)?
+        })
+    }
+}
+
+// Keyframed object transform: scaled, then rotated, then translated. Rotations are Euler angles in
+// degrees like TransformDesc::Rotate, and always take the shortest path between keyframes, so a
+// full turn needs at least three of them.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct AnimationDesc {
+    #[serde(default)]
+    pub interpolation: Interpolation,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub scale: Vec<Keyframe<Vec3f>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rotate: Vec<Keyframe<Vec3f>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub translate: Vec<Keyframe<Vec3f>>,
+}
+
+impl AnimationDesc {
+    fn animation(&self) -> Result<TransformAnimation, String> {
+        let rotations: Vec<Keyframe<Quaternion>> = self.rotate.iter().map(
+            |keyframe| Keyframe { time: keyframe.time, value: Quaternion::from_euler(&keyframe.value) }
+        ).collect();
+        Ok(TransformAnimation {
+            scale: track(&self.scale, self.interpolation)?,
+            rotation: track(&rotations, self.interpolation)?,
+            translation: track(&self.translate, self.interpolation)?
+        })
+    }
+}
+
+fn track<T: Interpolate>(keyframes: &[Keyframe<T>], interpolation: Interpolation) -> Result<Option<Track<T>>, String> {
+    // An empty list leaves the value unanimated.
+    if keyframes.is_empty() {
+        return Ok(None);
+    }
+    Track::new(keyframes.to_vec(), interpolation).map(Some)
+}
+
+// Either a literal color or the name of a texture from the [textures] table.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum TextureSource {
+    Color(Color),
+    Named(String),
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NoisePattern {
+    Normal,
+    Turbulence,
+    Marble,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum TextureDesc {
+    Solid {
+        color: Color,
+    },
+    Checker {
+        scale: f64,
+        even: TextureSource,
+        odd: TextureSource,
+    },
+    Image {
+        path: PathBuf,
+    },
+    Noise {
+        #[serde(default = "TextureDesc::default_point_count")]
+        point_count: usize,
+        pattern: NoisePattern,
+        #[serde(default = "TextureDesc::default_depth")]
+        depth: u32,
+        scale: f64,
+        // Fixed noise tables; random ones are generated when omitted.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        perlin: Option<Perlin>,
+    },
+    // Another texture multiplied by a color.
+    Scaled {
+        texture: TextureSource,
+        scale: Color,
+    },
+    // One color per mesh vertex, interpolated across each face.
+    VertexColor {
+        colors: Vec<Color>,
+    },
+}
+
+impl TextureDesc {
+    fn default_point_count() -> usize {
+        256
+    }
+
+    pub fn default_depth() -> u32 {
+        7
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum MaterialDesc {
+    Empty,
+    Lambertian {
+        albedo: TextureSource,
+    },
+    Metal {
+        albedo: TextureSource,
+        fuzz: f64,
+    },
+    Dielectric {
+        refractive_index: f64,
+    },
+    DiffuseLight {
+        emit: TextureSource,
+    },
+    Isotropic {
+        albedo: TextureSource,
+    },
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum TransformDesc {
+    Translate(Vec3f),
+    // Euler angles in degrees, applied about X, then Y, then Z.
+    Rotate(Vec3f),
+    Scale(Vec3f),
+    // Factors [xy, xz, yx, yz, zx, zy], e.g. x' = x + xy * y + xz * z.
+    Shear([f64; 6]),
+    // Row-major 4x4 matrix acting on column vectors.
+    Matrix(Matrix4),
+}
+
+impl TransformDesc {
+    fn transform(&self) -> Result<Transform, String> {
+        match self {
+            TransformDesc::Translate(offset) => Ok(Transform::translate(offset)),
+            TransformDesc::Rotate(angles) => Ok(Transform::rotate(angles)),
+            TransformDesc::Scale(factors) => Transform::scale(factors),
+            TransformDesc::Shear([xy, xz, yx, yz, zx, zy]) => Transform::shear(*xy, *xz, *yx, *yz, *zx, *zy),
+            TransformDesc::Matrix(matrix) => Transform::new(matrix)
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ObjectDesc {
+    #[serde(flatten)]
+    pub shape: ShapeDesc,
+    // Applied in order, so [{ rotate = ... }, { translate = ... }] rotates before translating.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub transform: Vec<TransformDesc>,
+    // Motion blur within each frame, applied after the transform list.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub motion: Vec<MotionKeyDesc>,
+    // Only allowed on top-level objects and lights.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub animation: Option<AnimationDesc>,
+}
+
+impl ObjectDesc {
+    pub fn new(shape: ShapeDesc) -> Self {
+        Self { shape, transform: Vec::new(), motion: Vec::new(), animation: None }
+    }
+}
+
+impl<'de> Deserialize<'de> for ObjectDesc {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        // The shape's keys sit next to the placement's. Flattening the shape into the object would
+        // hand it any misspelt placement key, which it ignores, so the placement keys are taken
+        // out first and the shape, which denies unknown keys, is built from the rest.
+        fn take<T: for<'a> Deserialize<'a>, E: Error>(table: &mut toml::Table, key: &str) -> Result<Option<T>, E> {
+            table.remove(key).map(toml::Value::try_into).transpose().map_err(E::custom)
+        }
+        let mut table: toml::Table = toml::Table::deserialize(deserializer)?;
+        let transform: Option<Vec<TransformDesc>> = take(&mut table, "transform")?;
+        let motion: Option<Vec<MotionKeyDesc>> = take(&mut table, "motion")?;
+        let animation: Option<AnimationDesc> = take(&mut table, "animation")?;
+        let shape: ShapeDesc = table.try_into().map_err(D::Error::custom)?;
+        Ok(Self { shape, transform: transform.unwrap_or_default(), motion: motion.unwrap_or_default(), animation })
+    }
+}
+
+// Placement of a moving object at a time within the shutter interval, from 0 to 1. Objects move
+// between these, and stay at the first and last placements outside of them.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct MotionKeyDesc {
+    pub time: f64,
+    pub transform: Vec<TransformDesc>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum ShapeDesc {
+    Sphere {
+        center: Point3f,
+        // End position for a sphere moving over the shutter interval.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        center2: Option<Point3f>,
+        radius: f64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        material: Option<String>,
+    },
+    Quad {
+        origin: Point3f,
+        u: Vec3f,
+        v: Vec3f,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        material: Option<String>,
+    },
+    Tri {
+        origin: Point3f,
+        u: Vec3f,
+        v: Vec3f,
+        // Vertex normals and texture coordinates at origin, origin + u and origin + v.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        normals: Option<[Vec3f; 3]>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        uvs: Option<[(f64, f64); 3]>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        material: Option<String>,
+    },
+    Plane {
+        origin: Point3f,
+        u: Vec3f,
+        v: Vec3f,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        material: Option<String>,
+    },
+    Mesh {
+        positions: Vec<Point3f>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        normals: Vec<Vec3f>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        uvs: Vec<(f64, f64)>,
+        indices: Vec<[u32; 3]>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        material: Option<String>,
+    },
+    // Wavefront OBJ file; its MTL materials are used unless a material is given.
+    Obj {
+        path: PathBuf,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        material: Option<String>,
+    },
+    // PLY file; its vertex colors are used unless a material is given.
+    Ply {
+        path: PathBuf,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        material: Option<String>,
+    },
+    // glTF 2.0 file; only its meshes are added, and its cameras are ignored. Its emissive meshes
+    // are added to the lights, placed like the object.
+    Gltf {
+        path: PathBuf,
+    },
+    Box {
+        a: Point3f,
+        b: Point3f,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        material: Option<String>,
+    },
+    List {
+        objects: Vec<ObjectDesc>,
+    },
+    Bvh {
+        objects: Vec<ObjectDesc>,
+    },
+    ConstantMedium {
+        boundary: Box<ObjectDesc>,
+        density: f64,
+        albedo: TextureSource,
+    },
+    // A placement of a named prototype; the material, if given, replaces all of the prototype's.
+    Instance {
+        prototype: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        material: Option<String>,
+    },
+}
+
+
+pub fn load(filepath: &Path) -> Result<(HittableList, HittableList, Camera), String> {
+    // Animated objects and the camera are placed as they are at time 0.
+    load_animated(filepath).map(|(scene, lights, cam, _)| (scene, lights, cam))
+}
+
+pub fn load_animated(filepath: &Path) -> Result<(HittableList, HittableList, Camera, Animation), String> {
+    // Relative paths inside the scene file, such as image textures, are resolved against the
+    // directory containing it.
+    let contents: String = fs::read_to_string(filepath)
+        .map_err(|err| format!("{}: {}", filepath.display(), err))?;
+    let base_dir: &Path = filepath.parent().unwrap_or(Path::new(""));
+    from_str_animated(&contents, base_dir).map_err(|err| format!("{}:{}", filepath.display(), err))
+}
+
+pub fn from_str(contents: &str, base_dir: &Path) -> Result<(HittableList, HittableList, Camera), String> {
+    from_str_animated(contents, base_dir).map(|(scene, lights, cam, _)| (scene, lights, cam))
+}
+
+pub fn from_str_animated(contents: &str, base_dir: &Path) -> Result<(HittableList, HittableList, Camera, Animation), String> {
+    let desc: SceneDesc<toml::Table> = toml::from_str(contents).map_err(
+        |err| match err.span() {
+            Some(span) => format!("{}: {}", line_column(contents, span.start), err.message()),
+            None => format!(" {}", err.message())
+        }
+    )?;
+    let desc: SceneDesc = desc.read_objects(contents)?;
+    SceneLoader::new(&desc, contents, base_dir).build()
+}
+
+fn line_column(contents: &str, offset: usize) -> String {
+    // 1-based line and column of a byte offset, formatted as "line:column".
+    let before: &str = &contents[..offset.min(contents.len())];
+    let line: usize = before.matches('\n').count() + 1;
+    let column: usize = before.len() - before.rfind('\n').map_or(0, |i| i + 1) + 1;
+    format!("{}:{}", line, column)
+}
+
+
+pub fn to_string(scene: &HittableList, lights: &HittableList, cam: &Camera) -> Result<String, String> {
+    let mut exporter: SceneExporter = SceneExporter::new();
+    let objects: Vec<Spanned<ObjectDesc>> = scene.objects.iter().map(
+        |object| exporter.object(object).map(|desc| Spanned::new(0..0, desc))
+    ).collect::<Result<Vec<Spanned<ObjectDesc>>, String>>()?;
+    let lights: Vec<Spanned<ObjectDesc>> = lights.objects.iter().map(
+        |light| exporter.object(light).map(|desc| Spanned::new(0..0, desc))
+    ).collect::<Result<Vec<Spanned<ObjectDesc>>, String>>()?;
+
+    let desc: SceneDesc = SceneDesc {
+        camera: CameraDesc::from(cam),
+        textures: exporter.textures,
+        materials: exporter.materials,
+        prototypes: exporter.prototypes,
+        objects,
+        lights
+    };
+    toml::to_string(&desc).map_err(|err| err.to_string())
+}
+
+pub fn save(filepath: &Path, scene: &HittableList, lights: &HittableList, cam: &Camera) -> Result<(), String> {
+    let contents: String = to_string(scene, lights, cam)?;
+    fs::write(filepath, contents).map_err(|err| format!("{}: {}", filepath.display(), err))
+}
+
+
+// Collects the descriptions of shared textures, materials and instance prototypes while objects
+// are exported, so each is written once and referred to by name.
+pub struct SceneExporter {
+    textures: BTreeMap<String, Spanned<TextureDesc>>,
+    texture_names: HashMap<usize, String>,
+    materials: BTreeMap<String, Spanned<MaterialDesc>>,
+    material_names: HashMap<usize, String>,
+    prototypes: BTreeMap<String, Spanned<ObjectDesc>>,
+    prototype_names: HashMap<usize, String>,
+}
+
+impl SceneExporter {
+    fn new() -> Self {
+        Self {
+            textures: BTreeMap::new(),
+            texture_names: HashMap::new(),
+            materials: BTreeMap::new(),
+            material_names: HashMap::new(),
+            prototypes: BTreeMap::new(),
+            prototype_names: HashMap::new()
+        }
+    }
+
+    pub fn object(&mut self, object: &Arc<dyn Hittable>) -> Result<ObjectDesc, String> {
+        // The exportable types are told apart by downcasting, so the core traits know nothing of
+        // scene files.
+        let object: &dyn Any = &**object;
+        if let Some(sphere) = object.downcast_ref::<Sphere>() {
+            let center: &Ray = sphere.center();
+            let center2: Option<Point3f> = if center.direction().near_zero() {
+                None
+            }
+            else {
+                Some(center.at(1.0))
+            };
+            Ok(ObjectDesc::new(ShapeDesc::Sphere {
+                center: center.at(0.0),
+                center2,
+                radius: sphere.radius(),
+                material: Some(self.material(sphere.material())?)
+            }))
+        }
+        else if let Some(plane) = object.downcast_ref::<Plane>() {
+            Ok(ObjectDesc::new(ShapeDesc::Plane {
+                origin: *plane.origin(), u: *plane.u(), v: *plane.v(), material: Some(self.material(plane.material())?)
+            }))
+        }
+        else if let Some(quad) = object.downcast_ref::<Quad>() {
+            let p: &Plane = quad.plane();
+            Ok(ObjectDesc::new(ShapeDesc::Quad {
+                origin: *p.origin(), u: *p.u(), v: *p.v(), material: Some(self.material(p.material())?)
+            }))
+        }
+        else if let Some(tri) = object.downcast_ref::<Tri>() {
+            let p: &Plane = tri.plane();
+            Ok(ObjectDesc::new(ShapeDesc::Tri {
+                origin: *p.origin(), u: *p.u(), v: *p.v(), normals: tri.normals().copied(), uvs: tri.uvs().copied(),
+                material: Some(self.material(p.material())?)
+            }))
+        }
+        else if let Some(mesh) = object.downcast_ref::<TriangleMesh>() {
+            Ok(ObjectDesc::new(ShapeDesc::Mesh {
+                positions: mesh.positions().to_vec(),
+                normals: mesh.normals().to_vec(),
+                uvs: mesh.uvs().to_vec(),
+                indices: mesh.indices().to_vec(),
+                material: Some(self.material(mesh.material())?)
+            }))
+        }
+        else if let Some(medium) = object.downcast_ref::<ConstantMedium>() {
+            let MaterialDesc::Isotropic { albedo } = self.material_desc(medium.phase_function().as_ref())? else {
+                return Err("constant medium phase function must be isotropic".to_string());
+            };
+            Ok(ObjectDesc::new(ShapeDesc::ConstantMedium {
+                boundary: Box::new(self.object(medium.boundary())?),
+                density: medium.density(),
+                albedo
+            }))
+        }
+        else if let Some(list) = object.downcast_ref::<HittableList>() {
+            let objects: Vec<ObjectDesc> = list.objects.iter().map(
+                |object| self.object(object)
+            ).collect::<Result<Vec<ObjectDesc>, String>>()?;
+            Ok(ObjectDesc::new(ShapeDesc::List { objects }))
+        }
+        else if let Some(bvh) = object.downcast_ref::<BVHNode>() {
+            // Export the objects only; the hierarchy is rebuilt when the scene is loaded.
+            let mut objects: Vec<ObjectDesc> = Vec::new();
+            for object in bvh.objects() {
+                let object_desc: ObjectDesc = self.object(object)?;
+                match object_desc {
+                    ObjectDesc { shape: ShapeDesc::Bvh { objects: nested }, transform, motion, .. } if transform.is_empty() && motion.is_empty() => {
+                        objects.extend(nested);
+                    },
+                    _ => objects.push(object_desc)
+                }
+            }
+            Ok(ObjectDesc::new(ShapeDesc::Bvh { objects }))
+        }
+        else if let Some(instance) = object.downcast_ref::<Instance>() {
+            let material: Option<String> = match instance.material() {
+                Some(mat) => Some(self.material(mat)?),
+                None => None
+            };
+            let mut desc: ObjectDesc = ObjectDesc::new(ShapeDesc::Instance {
+                prototype: self.prototype(instance.prototype())?, material
+            });
+            if !instance.transform().is_identity() {
+                desc.transform.push(TransformDesc::Matrix(*instance.transform().matrix()));
+            }
+            Ok(desc)
+        }
+        else if let Some(transformed) = object.downcast_ref::<Transformed>() {
+            // The transform list applies before any motion, so a moving object is nested in a list.
+            let mut desc: ObjectDesc = self.object(transformed.object())?;
+            if !desc.motion.is_empty() {
+                desc = ObjectDesc::new(ShapeDesc::List { objects: vec![desc] });
+            }
+            desc.transform.push(TransformDesc::Matrix(*transformed.transform().matrix()));
+            Ok(desc)
+        }
+        else if let Some(transformed) = object.downcast_ref::<MotionTransformed>() {
+            let mut desc: ObjectDesc = self.object(transformed.object())?;
+            if !desc.motion.is_empty() {
+                desc = ObjectDesc::new(ShapeDesc::List { objects: vec![desc] });
+            }
+            desc.motion = transformed.keyframes().iter().map(
+                |keyframe| MotionKeyDesc { time: keyframe.time, transform: vec![TransformDesc::Matrix(*keyframe.value.matrix())] }
+            ).collect();
+            Ok(desc)
+        }
+        else {
+            Err("object cannot be exported".to_string())
+        }
+    }
+
+    fn texture_desc(&mut self, texture: &dyn Texture) -> Result<TextureDesc, String> {
+        let any: &dyn Any = texture;
+        if let Some(solid) = any.downcast_ref::<Solid>() {
+            Ok(TextureDesc::Solid { color: *solid.albedo() })
+        }
+        else if let Some(checker) = any.downcast_ref::<Checker>() {
+            Ok(TextureDesc::Checker {
+                scale: checker.scale(),
+                even: self.texture(checker.even())?,
+                odd: self.texture(checker.odd())?
+            })
+        }
+        else if let Some(image) = any.downcast_ref::<Image>() {
+            // Scene files resolve relative paths against their own directory, so export an absolute path.
+            let filepath: &Path = image.filepath().ok_or("embedded image texture cannot be exported")?;
+            let path: PathBuf = filepath.canonicalize().unwrap_or_else(|_| filepath.to_path_buf());
+            Ok(TextureDesc::Image { path })
+        }
+        else if let Some(gltf_texture) = any.downcast_ref::<GltfTexture>() {
+            // Scene file images have no sampler or sRGB decoding, so only the image is kept.
+            self.texture_desc(gltf_texture.image().as_ref())
+        }
+        else if let Some(scaled) = any.downcast_ref::<Scaled>() {
+            Ok(TextureDesc::Scaled { texture: self.texture(scaled.texture())?, scale: *scaled.scale() })
+        }
+        else if let Some(vertex_color) = any.downcast_ref::<VertexColor>() {
+            Ok(TextureDesc::VertexColor { colors: vertex_color.colors().to_vec() })
+        }
+        else if let Some(noise) = any.downcast_ref::<Noise>() {
+            // The random Perlin tables are exported too, so the reloaded texture looks identical.
+            let (pattern, depth): (NoisePattern, u32) = match noise.perlin_texture() {
+                PerlinTexture::Normal => (NoisePattern::Normal, TextureDesc::default_depth()),
+                PerlinTexture::Turbulence(depth) => (NoisePattern::Turbulence, *depth),
+                PerlinTexture::Marble(depth) => (NoisePattern::Marble, *depth),
+            };
+            Ok(TextureDesc::Noise {
+                point_count: noise.noise().point_count(),
+                pattern,
+                depth,
+                scale: noise.scale(),
+                perlin: Some(noise.noise().clone())
+            })
+        }
+        else {
+            Err(format!("{} cannot be exported", texture))
+        }
+    }
+
+    fn material_desc(&mut self, material: &dyn Material) -> Result<MaterialDesc, String> {
+        let any: &dyn Any = material;
+        if any.is::<Empty>() {
+            Ok(MaterialDesc::Empty)
+        }
+        else if let Some(lambertian) = any.downcast_ref::<Lambertian>() {
+            Ok(MaterialDesc::Lambertian { albedo: self.texture(lambertian.texture())? })
+        }
+        else if let Some(metal) = any.downcast_ref::<Metal>() {
+            Ok(MaterialDesc::Metal { albedo: self.texture(metal.albedo())?, fuzz: metal.fuzz() })
+        }
+        else if let Some(dielectric) = any.downcast_ref::<Dielectric>() {
+            Ok(MaterialDesc::Dielectric { refractive_index: dielectric.refractive_index() })
+        }
+        else if let Some(light) = any.downcast_ref::<DiffuseLight>() {
+            Ok(MaterialDesc::DiffuseLight { emit: self.texture(light.texture())? })
+        }
+        else if let Some(isotropic) = any.downcast_ref::<Isotropic>() {
+            Ok(MaterialDesc::Isotropic { albedo: self.texture(isotropic.texture())? })
+        }
+        else {
+            Err(format!("{} cannot be exported", material))
+        }
+    }
+
+    pub fn texture(&mut self, texture: &Arc<dyn Texture>) -> Result<TextureSource, String> {
+        // Solid colors are written inline; anything else becomes a named texture.
+        let key: usize = Arc::as_ptr(texture) as *const () as usize;
+        if let Some(name) = self.texture_names.get(&key) {
+            return Ok(TextureSource::Named(name.clone()));
+        }
+
+        let desc: TextureDesc = self.texture_desc(texture.as_ref())?;
+        if let TextureDesc::Solid { color } = desc {
+            return Ok(TextureSource::Color(color));
+        }
+
+        let name: String = format!("texture{}", self.textures.len());
+        self.textures.insert(name.clone(), Spanned::new(0..0, desc));
+        self.texture_names.insert(key, name.clone());
+        Ok(TextureSource::Named(name))
+    }
+
+    pub fn material(&mut self, material: &Arc<dyn Material>) -> Result<String, String> {
+        let key: usize = Arc::as_ptr(material) as *const () as usize;
+        if let Some(name) = self.material_names.get(&key) {
+            return Ok(name.clone());
+        }
+
+        let desc: MaterialDesc = self.material_desc(material.as_ref())?;
+        let name: String = format!("material{}", self.materials.len());
+        self.materials.insert(name.clone(), Spanned::new(0..0, desc));
+        self.material_names.insert(key, name.clone());
+        Ok(name)
+    }
+
+    pub fn prototype(&mut self, object: &Arc<dyn Hittable>) -> Result<String, String> {
+        let key: usize = Arc::as_ptr(object) as *const () as usize;
+        if let Some(name) = self.prototype_names.get(&key) {
+            return Ok(name.clone());
+        }
+
+        let desc: ObjectDesc = self.object(object)?;
+        let name: String = format!("prototype{}", self.prototypes.len());
+        self.prototypes.insert(name.clone(), Spanned::new(0..0, desc));
+        self.prototype_names.insert(key, name.clone());
+        Ok(name)
+    }
+}
+
+
+struct SceneLoader<'a> {
+    desc: &'a SceneDesc,
+    contents: &'a str,
+    base_dir: &'a Path,
+    textures: HashMap<String, Arc<dyn Texture>>,
+    materials: HashMap<String, Arc<dyn Material>>,
+    prototypes: HashMap<String, Arc<dyn Hittable>>,
+    prototype_emitters: HashMap<String, Vec<Arc<dyn Hittable>>>,
+    visiting_prototypes: HashSet<String>,
+    empty_material: Arc<dyn Material>,
+    // Light shapes found inside the object being built, such as a glTF file's emissive meshes,
+    // placed like the object so far. They end up in the scene's lights list.
+    emitters: Vec<Arc<dyn Hittable>>,
+}
+
+impl<'a> SceneLoader<'a> {
+    fn new(desc: &'a SceneDesc, contents: &'a str, base_dir: &'a Path) -> Self {
+        Self {
+            desc, contents, base_dir,
+            textures: HashMap::new(),
+            materials: HashMap::new(),
+            prototypes: HashMap::new(),
+            prototype_emitters: HashMap::new(),
+            visiting_prototypes: HashSet::new(),
+            empty_material: Arc::new(Empty),
+            emitters: Vec::new()
+        }
+    }
+
+    fn build(mut self) -> Result<(HittableList, HittableList, Camera, Animation), String> {
+        for name in self.desc.textures.keys() {
+            self.texture(name, &mut HashSet::new())?;
+        }
+
+        for (name, material_desc) in &self.desc.materials {
+            let material: Arc<dyn Material> = self.material(material_desc.get_ref())
+                .map_err(|err| self.located(material_desc.span().start, &err))?;
+            self.materials.insert(name.clone(), material);
+        }
+
+        let mut animation: Animation = Animation::default();
+        let mut scene: HittableList = HittableList::new();
+        let mut emitters: Vec<Arc<dyn Hittable>> = Vec::new();
+        for object_desc in &self.desc.objects {
+            let object: Arc<dyn Hittable> = self.top_level_object(object_desc.get_ref(), false, &mut animation)
+                .map_err(|err| self.located(object_desc.span().start, &err))?;
+            scene.add(object);
+            emitters.append(&mut self.emitters);
+        }
+
+        // Light sampling shapes only contribute their geometry, so their material may be omitted.
+        let mut lights: HittableList = HittableList::new();
+        for light_desc in &self.desc.lights {
+            let light: Arc<dyn Hittable> = self.top_level_object(light_desc.get_ref(), true, &mut animation)
+                .map_err(|err| self.located(light_desc.span().start, &err))?;
+            lights.add(light);
+        }
+        for emitter in emitters {
+            lights.add(emitter);
+        }
+
+        let c: &CameraDesc = &self.desc.camera;
+        let mut cam: Camera = Camera::new(
+            c.aspect_ratio, c.image_width, c.samples_per_pixel,
+            c.max_depth, &c.background, c.vertical_fov,
+            &c.lookfrom, &c.lookat, &c.vup,
+            c.defocus_angle, c.focus_dist
+        );
+        cam.shutter = Shutter::new(c.shutter_open, c.shutter_close, c.shutter_curve.clone())?;
+        cam.sampler = c.sampler;
+        cam.russian_roulette_depth = c.russian_roulette_depth;
+        if let Some(camera_animation) = &c.animation {
+            animation.camera = camera_animation.animation()?;
+        }
+
+        Ok((scene, lights, cam, animation))
+    }
+
+    fn located(&self, offset: usize, message: &str) -> String {
+        format!("{}: {}", line_column(self.contents, offset), message)
+    }
+
+    fn texture(&mut self, name: &str, visiting: &mut HashSet<String>) -> Result<Arc<dyn Texture>, String> {
+        // Textures may refer to each other, so they are built on first use.
+        if let Some(texture) = self.textures.get(name) {
+            return Ok(texture.clone());
+        }
+
+        let desc: &'a SceneDesc = self.desc;
+        let texture_desc: &Spanned<TextureDesc> = desc.textures.get(name)
+            .ok_or_else(|| format!("unknown texture '{}'", name))?;
+        if !visiting.insert(name.to_string()) {
+            return Err(self.located(texture_desc.span().start, &format!("texture '{}' refers to itself", name)));
+        }
+
+        let texture: Arc<dyn Texture> = match texture_desc.get_ref() {
+            TextureDesc::Solid { color } => Arc::new(Solid::new(color)),
+            TextureDesc::Checker { scale, even, odd } => {
+                let even: Arc<dyn Texture> = self.texture_source(even, visiting)?;
+                let odd: Arc<dyn Texture> = self.texture_source(odd, visiting)?;
+                Arc::new(Checker::new(*scale, even, odd))
+            },
+            TextureDesc::Image { path } => {
+                let image: Image = Image::read_image(&self.base_dir.join(path))
+                    .map_err(|err| self.located(texture_desc.span().start, &format!("{}: {}", path.display(), err)))?;
+                Arc::new(image)
+            },
+            TextureDesc::Noise { point_count, pattern, depth, scale, perlin } => {
+                let perlin_texture: PerlinTexture = match pattern {
+                    NoisePattern::Normal => PerlinTexture::Normal,
+                    NoisePattern::Turbulence => PerlinTexture::Turbulence(*depth),
+                    NoisePattern::Marble => PerlinTexture::Marble(*depth),
+                };
+                match perlin {
+                    Some(perlin) => {
+                        perlin.validate().map_err(|err| self.located(texture_desc.span().start, &err))?;
+                        Arc::new(Noise::from_perlin(perlin.clone(), perlin_texture, *scale))
+                    },
+                    None => Arc::new(Noise::new(*point_count, perlin_texture, *scale))
+                }
+            },
+            TextureDesc::Scaled { texture, scale } => Arc::new(Scaled::new(self.texture_source(texture, visiting)?, scale)),
+            TextureDesc::VertexColor { colors } => Arc::new(VertexColor::new(colors.clone()))
+        };
+
+        visiting.remove(name);
+        self.textures.insert(name.to_string(), texture.clone());
+        Ok(texture)
+    }
+
+    fn texture_source(&mut self, source: &TextureSource, visiting: &mut HashSet<String>) -> Result<Arc<dyn Texture>, String> {
+        match source {
+            TextureSource::Color(color) => Ok(Arc::new(Solid::new(color))),
+            TextureSource::Named(name) => self.texture(name, visiting)
+        }
+    }
+
+    fn named_texture(&self, source: &TextureSource) -> Result<Arc<dyn Texture>, String> {
+        match source {
+            TextureSource::Color(color) => Ok(Arc::new(Solid::new(color))),
+            TextureSource::Named(name) => self.textures.get(name).cloned()
+                .ok_or_else(|| format!("unknown texture '{}'", name))
+        }
+    }
+
+    fn material(&self, desc: &MaterialDesc) -> Result<Arc<dyn Material>, String> {
+        let material: Arc<dyn Material> = match desc {
+            MaterialDesc::Empty => self.empty_material.clone(),
+            MaterialDesc::Lambertian { albedo: TextureSource::Color(color) } => Arc::new(Lambertian::from_color(color)),
+            MaterialDesc::Lambertian { albedo } => Arc::new(Lambertian::from_texture(self.named_texture(albedo)?)),
+            MaterialDesc::Metal { albedo: TextureSource::Color(color), fuzz } => Arc::new(Metal::new(color, *fuzz)),
+            MaterialDesc::Metal { albedo, fuzz } => Arc::new(Metal::from_texture(self.named_texture(albedo)?, *fuzz)),
+            MaterialDesc::Dielectric { refractive_index } => Arc::new(Dielectric::new(*refractive_index)),
+            MaterialDesc::DiffuseLight { emit: TextureSource::Color(color) } => Arc::new(DiffuseLight::from_color(color)),
+            MaterialDesc::DiffuseLight { emit } => Arc::new(DiffuseLight::from_texture(self.named_texture(emit)?)),
+            MaterialDesc::Isotropic { albedo: TextureSource::Color(color) } => Arc::new(Isotropic::from_color(color)),
+            MaterialDesc::Isotropic { albedo } => Arc::new(Isotropic::from_texture(self.named_texture(albedo)?)),
+        };
+        Ok(material)
+    }
+
+    fn object_material(&self, name: &Option<String>, is_light: bool) -> Result<Arc<dyn Material>, String> {
+        match name {
+            Some(name) => self.materials.get(name).cloned().ok_or_else(|| format!("unknown material '{}'", name)),
+            None if is_light => Ok(self.empty_material.clone()),
+            None => Err("missing field `material`".to_string())
+        }
+    }
+
+    fn prototype(&mut self, name: &str) -> Result<Arc<dyn Hittable>, String> {
+        // Prototypes may place instances of each other, so they are built on first use.
+        if let Some(prototype) = self.prototypes.get(name) {
+            self.emitters.extend(self.prototype_emitters[name].iter().cloned());
+            return Ok(prototype.clone());
+        }
+
+        let desc: &'a SceneDesc = self.desc;
+        let prototype_desc: &Spanned<ObjectDesc> = desc.prototypes.get(name)
+            .ok_or_else(|| format!("unknown prototype '{}'", name))?;
+        if !self.visiting_prototypes.insert(name.to_string()) {
+            return Err(format!("prototype '{}' refers to itself", name));
+        }
+
+        let outer: Vec<Arc<dyn Hittable>> = std::mem::take(&mut self.emitters);
+        let prototype: Arc<dyn Hittable> = self.object(prototype_desc.get_ref(), false)
+            .map_err(|err| self.located(prototype_desc.span().start, &err))?;
+        let emitters: Vec<Arc<dyn Hittable>> = std::mem::replace(&mut self.emitters, outer);
+
+        self.visiting_prototypes.remove(name);
+        self.emitters.extend(emitters.iter().cloned());
+        self.prototypes.insert(name.to_string(), prototype.clone());
+        self.prototype_emitters.insert(name.to_string(), emitters);
+        Ok(prototype)
+    }
+
+    fn transform(descs: &[TransformDesc]) -> Result<Transform, String> {
+        let mut transform: Transform = Transform::IDENTITY;
+        for transform_desc in descs {
+            transform = transform.then(&transform_desc.transform()?);
+        }
+        Ok(transform)
+    }
+
+    fn top_level_object(&mut self, desc: &ObjectDesc, is_light: bool, animation: &mut Animation) -> Result<Arc<dyn Hittable>, String> {
+        // Animated objects are added at their placement at time 0, and their emitters move with them.
+        let object: Arc<dyn Hittable> = self.static_object(desc, is_light)?;
+        match &desc.animation {
+            Some(animation_desc) => {
+                for emitter in &mut self.emitters {
+                    let animated: AnimatedObject = AnimatedObject::new(emitter.clone(), animation_desc.animation()?)?;
+                    *emitter = animated.current().clone();
+                    animation.add(animated);
+                }
+                let animated: AnimatedObject = AnimatedObject::new(object, animation_desc.animation()?)?;
+                let current: Arc<dyn Hittable> = animated.current().clone();
+                animation.add(animated);
+                Ok(current)
+            },
+            None => Ok(object)
+        }
+    }
+
+    fn object(&mut self, desc: &ObjectDesc, is_light: bool) -> Result<Arc<dyn Hittable>, String> {
+        if desc.animation.is_some() {
+            return Err("only top-level objects and lights can be animated".to_string());
+        }
+        self.static_object(desc, is_light)
+    }
+
+    fn static_object(&mut self, desc: &ObjectDesc, is_light: bool) -> Result<Arc<dyn Hittable>, String> {
+        // The emitters found inside the object are placed like it and passed on to the object
+        // around it. Light sampling shapes and medium boundaries aren't lit, so theirs are dropped.
+        let outer: Vec<Arc<dyn Hittable>> = std::mem::take(&mut self.emitters);
+        let object: Result<Arc<dyn Hittable>, String> = self.placed_object(desc, is_light);
+        let emitters: Vec<Arc<dyn Hittable>> = std::mem::replace(&mut self.emitters, outer);
+        let object: Arc<dyn Hittable> = object?;
+        if !is_light {
+            for emitter in emitters {
+                let emitter: Arc<dyn Hittable> = Self::place(emitter, desc)?;
+                self.emitters.push(emitter);
+            }
+        }
+        Ok(object)
+    }
+
+    fn placed_object(&mut self, desc: &ObjectDesc, is_light: bool) -> Result<Arc<dyn Hittable>, String> {
+        let object: Arc<dyn Hittable> = match &desc.shape {
+            ShapeDesc::Sphere { center, center2, radius, material } => {
+                let mat: Arc<dyn Material> = self.object_material(material, is_light)?;
+                match center2 {
+                    Some(center2) => Arc::new(Sphere::new_moving(center, center2, *radius, mat)),
+                    None => Arc::new(Sphere::new_stationary(center, *radius, mat))
+                }
+            },
+            ShapeDesc::Quad { origin, u, v, material } => {
+                Arc::new(Quad::new(origin, u, v, self.object_material(material, is_light)?))
+            },
+            ShapeDesc::Tri { origin, u, v, normals, uvs, material } => {
+                let mat: Arc<dyn Material> = self.object_material(material, is_light)?;
+                Arc::new(Tri::with_vertex_attributes(origin, u, v, *normals, *uvs, mat))
+            },
+            ShapeDesc::Plane { origin, u, v, material } => {
+                Arc::new(Plane::new(origin, u, v, self.object_material(material, is_light)?))
+            },
+            ShapeDesc::Mesh { positions, normals, uvs, indices, material } => {
+                let mat: Arc<dyn Material> = self.object_material(material, is_light)?;
+                Arc::new(TriangleMesh::new(positions.clone(), normals.clone(), uvs.clone(), indices.clone(), mat)?)
+            },
+            ShapeDesc::Obj { path, material } => {
+                let override_mat: Option<Arc<dyn Material>> = match material {
+                    Some(_) => Some(self.object_material(material, is_light)?),
+                    None => None
+                };
+                let mut meshes: HittableList = obj::load(&self.base_dir.join(path), override_mat)?;
+                match meshes.objects.len() {
+                    1 => meshes.objects[0].clone(),
+                    _ => Arc::new(BVHNode::from_hittable_list(&mut meshes))
+                }
+            },
+            ShapeDesc::Ply { path, material } => {
+                let override_mat: Option<Arc<dyn Material>> = match material {
+                    Some(_) => Some(self.object_material(material, is_light)?),
+                    None => None
+                };
+                Arc::new(ply::load(&self.base_dir.join(path), override_mat)?)
+            },
+            ShapeDesc::Gltf { path } => {
+                let (mut meshes, lights, _) = gltf_scene::load(&self.base_dir.join(path))?;
+                self.emitters.extend(lights.objects);
+                match meshes.objects.len() {
+                    1 => meshes.objects[0].clone(),
+                    _ => Arc::new(BVHNode::from_hittable_list(&mut meshes))
+                }
+            },
+            ShapeDesc::Box { a, b, material } => {
+                Quad::new_box(a, b, self.object_material(material, is_light)?)
+            },
+            ShapeDesc::List { objects } => {
+                let mut list: HittableList = HittableList::new();
+                for object_desc in objects {
+                    list.add(self.object(object_desc, is_light)?);
+                }
+                Arc::new(list)
+            },
+            ShapeDesc::Bvh { objects } => {
+                let mut list: HittableList = HittableList::new();
+                for object_desc in objects {
+                    list.add(self.object(object_desc, is_light)?);
+                }
+                if list.objects.is_empty() {
+                    return Err("a bvh needs at least one object".to_string());
+                }
+                Arc::new(BVHNode::from_hittable_list(&mut list))
+            },
+            ShapeDesc::ConstantMedium { boundary, density, albedo } => {
+                let boundary: Arc<dyn Hittable> = self.object(boundary, true)?;
+                match albedo {
+                    TextureSource::Color(color) => Arc::new(ConstantMedium::from_color(boundary, *density, color)),
+                    TextureSource::Named(_) => Arc::new(ConstantMedium::from_texture(boundary, *density, self.named_texture(albedo)?))
+                }
+            },
+            ShapeDesc::Instance { prototype, material } => {
+                let prototype: Arc<dyn Hittable> = self.prototype(prototype)?;
+                let override_mat: Option<Arc<dyn Material>> = match material {
+                    Some(_) => Some(self.object_material(material, is_light)?),
+                    None => None
+                };
+                // A replaced material also replaces the prototype's emitters.
+                if override_mat.is_some() {
+                    self.emitters.clear();
+                }
+                // Instances carry their own transform instead of being wrapped in another one.
+                let instance: Arc<dyn Hittable> = Arc::new(Instance::new(prototype, &Self::transform(&desc.transform)?, override_mat));
+                return Self::motion(instance, &desc.motion);
+            }
+        };
+
+        Self::place(object, desc)
+    }
+
+    fn place(mut object: Arc<dyn Hittable>, desc: &ObjectDesc) -> Result<Arc<dyn Hittable>, String> {
+        // The whole chain becomes a single transform, so the object is only wrapped once.
+        if !desc.transform.is_empty() {
+            object = Arc::new(Transformed::new(object, &Self::transform(&desc.transform)?));
+        }
+        Self::motion(object, &desc.motion)
+    }
+
+    fn motion(object: Arc<dyn Hittable>, keys: &[MotionKeyDesc]) -> Result<Arc<dyn Hittable>, String> {
+        if keys.is_empty() {
+            return Ok(object);
+        }
+        let keyframes: Vec<Keyframe<Transform>> = keys.iter().map(
+            |key| Self::transform(&key.transform).map(|value| Keyframe { time: key.time, value })
+        ).collect::<Result<Vec<Keyframe<Transform>>, String>>()?;
+        Ok(Arc::new(MotionTransformed::new(object, keyframes)?))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::aabb::AABB;
+    use crate::animation::Replacements;
+    use crate::hittable::HitRecord;
+    use crate::interval::Interval;
+    use crate::scene_file::*;
+    use crate::scenes;
+    use crate::vec3::Axis;
+
+    fn assert_same_scene(
+        (scene, lights, cam): (HittableList, HittableList, Camera),
+        (expected_scene, expected_lights, expected_cam): (HittableList, HittableList, Camera)
+    ) {
+        assert_eq!(scene.objects.len(), expected_scene.objects.len());
+        assert_eq!(scene.bounding_box(), expected_scene.bounding_box());
+        for (object, expected_object) in scene.objects.iter().zip(&expected_scene.objects) {
+            assert_eq!(object.bounding_box(), expected_object.bounding_box());
+        }
+        assert_eq!(lights.objects.len(), expected_lights.objects.len());
+        assert_eq!(lights.bounding_box(), expected_lights.bounding_box());
+
+        assert_eq!(cam.aspect_ratio, expected_cam.aspect_ratio);
+        assert_eq!(cam.image_width, expected_cam.image_width);
+        assert_eq!(cam.samples_per_pixel, expected_cam.samples_per_pixel);
+        assert_eq!(cam.max_depth, expected_cam.max_depth);
+        assert_eq!(cam.russian_roulette_depth, expected_cam.russian_roulette_depth);
+        assert_eq!(cam.background, expected_cam.background);
+        assert_eq!(cam.vertical_fov, expected_cam.vertical_fov);
+        assert_eq!(cam.lookfrom, expected_cam.lookfrom);
+        assert_eq!(cam.lookat, expected_cam.lookat);
+        assert_eq!(cam.vup, expected_cam.vup);
+        assert_eq!(cam.defocus_angle, expected_cam.defocus_angle);
+        assert_eq!(cam.focus_dist, expected_cam.focus_dist);
+        assert_eq!(cam.shutter, expected_cam.shutter);
+        assert_eq!(cam.sampler, expected_cam.sampler);
+    }
+
+    #[test]
+    fn builtin_scene_files() {
+        // Every file in scenes/ describes the built-in scene with the same name.
+        let scene_dir: PathBuf = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes");
+        let mut scene_count: usize = 0;
+        for entry in fs::read_dir(scene_dir).unwrap() {
+            let filepath: PathBuf = entry.unwrap().path();
+            let name: &str = filepath.file_stem().unwrap().to_str().unwrap();
+            let builder: scenes::SceneBuilder = scenes::from_name(name).unwrap();
+            assert_same_scene(load(&filepath).unwrap(), builder());
+            scene_count += 1;
+        }
+        assert_eq!(scene_count, 8);
+    }
+
+    #[test]
+    fn export_round_trip() {
+        for name in ["bouncing_spheres", "cornell_box", "cornell_smoke", "final_scene_preview"] {
+            let (scene, lights, cam) = scenes::from_name(name).unwrap()();
+            let contents: String = to_string(&scene, &lights, &cam).unwrap();
+            let (reloaded_scene, reloaded_lights, reloaded_cam) = from_str(&contents, Path::new("")).unwrap();
+
+            // Reloading rebuilds BVHs, which may list their leaves in a different order.
+            if name != "final_scene_preview" {
+                assert_eq!(to_string(&reloaded_scene, &reloaded_lights, &reloaded_cam).unwrap(), contents);
+            }
+            assert_same_scene((reloaded_scene, reloaded_lights, reloaded_cam), (scene, lights, cam));
+        }
+    }
+
+    #[test]
+    fn instances() {
+        let contents: &str = r#"
+[camera]
+aspect_ratio = 1.0
+image_width = 100
+samples_per_pixel = 10
+max_depth = 10
+background = [0, 0, 0]
+vertical_fov = 40
+lookfrom = [0, 0, -5]
+lookat = [0, 0, 0]
+
+[materials.white]
+type = "lambertian"
+albedo = [0.73, 0.73, 0.73]
+
+[materials.mirror]
+type = "metal"
+albedo = [0.9, 0.9, 0.9]
+fuzz = 0
+
+[prototypes.crate]
+type = "box"
+a = [0, 0, 0]
+b = [1, 1, 1]
+material = "white"
+
+[prototypes.stack]
+type = "list"
+objects = [
+    { type = "instance", prototype = "crate" },
+    { type = "instance", prototype = "crate", transform = [{ translate = [0, 1, 0] }] },
+]
+
+[[objects]]
+type = "instance"
+prototype = "stack"
+transform = [{ scale = [2, 2, 2] }, { translate = [5, 0, 0] }]
+
+[[objects]]
+type = "instance"
+prototype = "crate"
+material = "mirror"
+"#;
+        let (scene, lights, cam) = from_str(contents, Path::new("")).unwrap();
+        assert_eq!(scene.objects.len(), 2);
+        let stack: &AABB = scene.objects[0].bounding_box();
+        assert!((stack.x.min - 5.0).abs() < 1e-3 && (stack.x.max - 7.0).abs() < 1e-3 && (stack.y.max - 4.0).abs() < 1e-3);
+
+        // Prototypes are exported once, however many instances refer to them.
+        let exported: String = to_string(&scene, &lights, &cam).unwrap();
+        assert_eq!(toml::from_str::<SceneDesc>(&exported).unwrap().prototypes.len(), 2);
+        let (reloaded, _, _) = from_str(&exported, Path::new("")).unwrap();
+        assert_eq!(reloaded.objects[0].bounding_box(), stack);
+
+        let cycle: String = contents.replace("prototype = \"crate\" }", "prototype = \"stack\" }");
+        let err: String = from_str(&cycle, Path::new("")).err().unwrap();
+        assert!(err.contains("prototype 'stack' refers to itself"), "{}", err);
+    }
+
+    #[test]
+    fn scaled_texture() {
+        // A metal whose albedo is a checker texture at half strength, which survives a round trip.
+        let contents: &str = r#"
+[camera]
+aspect_ratio = 1.0
+image_width = 100
+samples_per_pixel = 10
+max_depth = 10
+background = [0, 0, 0]
+vertical_fov = 40
+lookfrom = [0, 0, 5]
+lookat = [0, 0, 0]
+
+[textures.checker]
+type = "checker"
+scale = 0.3
+even = [0.2, 0.4, 0.6]
+odd = [0.8, 0.8, 0.8]
+
+[textures.dimmed]
+type = "scaled"
+texture = "checker"
+scale = [0.5, 0.5, 0.5]
+
+[materials.brushed]
+type = "metal"
+albedo = "dimmed"
+fuzz = 0
+
+[[objects]]
+type = "sphere"
+center = [0, 0, 0]
+radius = 1
+material = "brushed"
+"#;
+        let (scene, lights, cam) = from_str(contents, Path::new("")).unwrap();
+        let checker: Checker = Checker::from_color(0.3, &Color::new(0.2, 0.4, 0.6), &Color::new(0.8, 0.8, 0.8));
+        for x in [0.0, 0.2, 0.4] {
+            let ray: Ray = Ray::new(&Point3f::new(x, 0.1, 5.0), &-Vec3f::E3);
+            let rec: HitRecord = scene.hit(&ray, &Interval::new(0.001, f64::INFINITY)).unwrap();
+            let attenuation: Color = rec.mat.scatter(&ray, &rec, (0.5, 0.5)).unwrap().attenuation;
+            assert_eq!(attenuation, 0.5 * checker.value(rec.uv, &rec.point));
+        }
+
+        let exported: String = to_string(&scene, &lights, &cam).unwrap();
+        let (reloaded_scene, reloaded_lights, reloaded_cam) = from_str(&exported, Path::new("")).unwrap();
+        assert_eq!(to_string(&reloaded_scene, &reloaded_lights, &reloaded_cam).unwrap(), exported);
+    }
+
+    #[test]
+    fn animation() {
+        let contents: &str = r#"
+[camera]
+aspect_ratio = 1.0
+image_width = 100
+samples_per_pixel = 10
+max_depth = 10
+background = [0, 0, 0]
+vertical_fov = 40
+lookfrom = [0, 0, -5]
+lookat = [0, 0, 0]
+shutter_open = 0.25
+shutter_close = 0.25
+shutter_curve = "triangle"
+
+[camera.animation]
+interpolation = "smoothstep"
+lookfrom = [{ time = 0, value = [0, 0, -5] }, { time = 1, value = [0, 0, -10] }]
+
+[materials.white]
+type = "lambertian"
+albedo = [0.73, 0.73, 0.73]
+
+[[objects]]
+type = "sphere"
+center = [0, 0, 0]
+radius = 1
+material = "white"
+
+[[objects]]
+type = "sphere"
+center = [0, 0, 0]
+radius = 1
+material = "white"
+transform = [{ translate = [0, 2, 0] }]
+
+[objects.animation]
+translate = [{ time = 0, value = [1, 0, 0] }, { time = 2, value = [3, 0, 0] }]
+"#;
+        let (scene, _, mut cam, mut animation) = from_str_animated(contents, Path::new("")).unwrap();
+        assert!((scene.objects[1].bounding_box().centroid() - Point3f::new(1.0, 2.0, 0.0)).length() < 1e-9);
+
+        // The shutter opens a quarter of the way into the frame.
+        assert_eq!(cam.shutter.curve(), &ShutterCurve::Triangle);
+        let replacements: Replacements = animation.set_time(1.0, 1.0, &cam.shutter).unwrap();
+        assert_eq!(replacements.len(), 1);
+        assert!(replacements.get(&scene.objects[0]).is_none());
+        let moved: Arc<dyn Hittable> = replacements.get(&scene.objects[1]).unwrap();
+        assert!((moved.bounding_box().centroid() - Point3f::new(2.25, 2.0, 0.0)).length() < 1e-9);
+        animation.camera.apply(&mut cam, 0.5);
+        assert_eq!(cam.lookfrom, Point3f::new(0.0, 0.0, -7.5));
+
+        let nested: String = contents.replace("\n[objects.animation]\ntranslate", "\n[[objects]]\ntype = \"list\"\nobjects = [{ type = \"sphere\", center = [0, 0, 0], radius = 1, material = \"white\", animation = { translate");
+        let nested: String = nested.replace("[3, 0, 0] }]\n", "[3, 0, 0] }] } }]\n");
+        let err: String = from_str(&nested, Path::new("")).err().unwrap();
+        assert!(err.contains("only top-level objects and lights can be animated"), "{}", err);
+    }
+
+    #[test]
+    fn motion_round_trip() {
+        let contents: &str = r#"
+[camera]
+aspect_ratio = 1.0
+image_width = 100
+samples_per_pixel = 10
+max_depth = 10
+background = [0, 0, 0]
+vertical_fov = 40
+lookfrom = [0, 0, -5]
+lookat = [0, 0, 0]
+
+[materials.white]
+type = "lambertian"
+albedo = [0.73, 0.73, 0.73]
+
+[[objects]]
+type = "box"
+a = [0, 0, 0]
+b = [1, 1, 1]
+material = "white"
+transform = [{ translate = [2, 0, 0] }]
+motion = [
+    { time = 0, transform = [] },
+    { time = 0.5, transform = [{ rotate = [0, 45, 0] }] },
+    { time = 1, transform = [{ rotate = [0, 90, 0] }, { translate = [0, 1, 0] }] },
+]
+"#;
+        let (scene, lights, cam) = from_str(contents, Path::new("")).unwrap();
+        let moving: &AABB = scene.objects[0].bounding_box();
+        assert!(moving.y.max > 1.99 && moving.z.min < -2.99 && moving.x.max > 2.99);
+
+        let exported: String = to_string(&scene, &lights, &cam).unwrap();
+        let (reloaded, _, _) = from_str(&exported, Path::new("")).unwrap();
+        let reloaded: &AABB = reloaded.objects[0].bounding_box();
+        for &axis in Axis::iterator() {
+            let (a, b) = (moving.axis_interval(axis), reloaded.axis_interval(axis));
+            assert!((a.min - b.min).abs() < 1e-9 && (a.max - b.max).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn syntax_error_line() {
+        let contents: &str = "[camera]\naspect_ratio = 1.0\nimage_width = \"wide\"\n";
+        let err: String = from_str(contents, Path::new("")).err().unwrap();
+        assert!(err.starts_with("3:15: "), "{}", err);
+    }
+
+    #[test]
+    fn unknown_material_line() {
+        let contents: &str = r#"
+[camera]
+aspect_ratio = 1.0
+image_width = 100
+samples_per_pixel = 10
+max_depth = 10
+background = [0, 0, 0]
+vertical_fov = 40
+lookfrom = [0, 0, -5]
+lookat = [0, 0, 0]
+
+[materials.white]
+type = "lambertian"
+albedo = [0.73, 0.73, 0.73]
+
+[[objects]]
+type = "sphere"
+center = [0, 0, 0]
+radius = 1
+material = "white"
+
+[[objects]]
+type = "sphere"
+center = [0, 2, 0]
+radius = 1
+material = "black"
+"#;
+        let err: String = from_str(contents, Path::new("")).err().unwrap();
+        assert!(err.starts_with("22:1: "), "{}", err);
+        assert!(err.contains("unknown material 'black'"), "{}", err);
+    }
+
+    #[test]
+    fn unknown_object_key_line() {
+        let contents: &str = r#"
+[camera]
+aspect_ratio = 1.0
+image_width = 100
+samples_per_pixel = 10
+max_depth = 10
+background = [0, 0, 0]
+vertical_fov = 40
+lookfrom = [0, 0, -5]
+lookat = [0, 0, 0]
+
+[[objects]]
+type = "sphere"
+center = [0, 0, 0]
+radius = 1
+
+[[objects]]
+type = "sphere"
+center = [2, 0, 0]
+radius = 1
+tranzform = [{ translate = [2, 0, 0] }]
+"#;
+        // The error is reported at the object holding the bad key, not at the first object.
+        let err: String = from_str(contents, Path::new("")).err().unwrap();
+        assert!(err.starts_with("17:1: "), "{}", err);
+        assert!(err.contains("unknown field `tranzform`"), "{}", err);
+    }
+
+    #[test]
+    fn gltf_lights() {
+        // A glTF file's emissive triangles are light sampled wherever the file is placed, unless an
+        // instance replaces their material.
+        let dir: PathBuf = std::env::temp_dir().join("raytracing_rs_scene_file_gltf_lights");
+        crate::gltf_scene::tests::write_triangle_gltf(&dir);
+        let contents: &str = r#"
+[camera]
+aspect_ratio = 1.0
+image_width = 100
+samples_per_pixel = 10
+max_depth = 10
+background = [0, 0, 0]
+vertical_fov = 40
+lookfrom = [0, 0, 1]
+lookat = [0, 0, 0]
+
+[materials.white]
+type = "lambertian"
+albedo = [0.73, 0.73, 0.73]
+
+[prototypes.triangles]
+type = "gltf"
+path = "triangle.gltf"
+
+[[objects]]
+type = "gltf"
+path = "triangle.gltf"
+transform = [{ translate = [0, 10, 0] }]
+
+[[objects]]
+type = "instance"
+prototype = "triangles"
+transform = [{ translate = [0, -10, 0] }]
+
+[[objects]]
+type = "instance"
+prototype = "triangles"
+material = "white"
+"#;
+        let loaded = from_str(contents, &dir);
+        fs::remove_dir_all(&dir).unwrap();
+        let (_, lights, _) = loaded.unwrap();
+        assert_eq!(lights.objects.len(), 4);
+
+        let ray_t: Interval = Interval::new(0.001, f64::INFINITY);
+        for y in [10.25, -9.75] {
+            let rec: HitRecord = lights.hit(&Ray::new(&Point3f::ZERO, &Vec3f::new(0.25, y, -5.0)), &ray_t).unwrap();
+            assert!((rec.t - 1.0).abs() < 1e-12);
+        }
+        assert!(lights.hit(&Ray::new(&Point3f::ZERO, &Vec3f::new(0.25, 0.25, -5.0)), &ray_t).is_none());
+    }
+}
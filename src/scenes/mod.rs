@@ -11,10 +11,35 @@ use crate::perlin::PerlinTexture;
 use crate::plane::Quad;
 use crate::sphere::Sphere;
 use crate::texture::{Checker, Image, Noise};
-use crate::transform::{Translation, EulerRotation};
+use crate::transform::{Transform, Transformed};
 use crate::utilities;
 use crate::vec3::{Point3f, Vec3f};
 
+pub type SceneBuilder = fn() -> (HittableList, HittableList, Camera);
+
+// Built-in scenes selectable by name from the command line.
+pub const SCENES: [(&str, SceneBuilder); 11] = [
+    ("simple_spheres", simple_spheres),
+    ("bouncing_spheres", bouncing_spheres),
+    ("checkered_spheres", checkered_spheres),
+    ("earth", earth),
+    ("perlin_spheres", perlin_spheres),
+    ("quads", quads),
+    ("simple_light", simple_light),
+    ("cornell_box", cornell_box),
+    ("cornell_smoke", cornell_smoke),
+    ("final_scene", || final_scene(800, 10000, 40)),
+    ("final_scene_preview", || final_scene(400, 250, 4)),
+];
+
+pub fn names() -> impl Iterator<Item = &'static str> {
+    SCENES.iter().map(|(name, _)| *name)
+}
+
+pub fn from_name(name: &str) -> Option<SceneBuilder> {
+    SCENES.iter().find(|(scene_name, _)| *scene_name == name).map(|(_, builder)| *builder)
+}
+
 #[allow(dead_code)]
 pub fn simple_spheres() -> (HittableList, HittableList, Camera) {
     // Scene
@@ -506,15 +531,12 @@ pub fn cornell_box() -> (HittableList, HittableList, Camera) {
         &Point3f::new(165.0, 330.0, 165.0),
         white.clone()
     );
-    let rotated_box1:Arc<EulerRotation>  = Arc::new(EulerRotation::new(
+    let transformed_box1: Arc<Transformed> = Arc::new(Transformed::new(
         box1,
-        &Vec3f::new(0.0, 15.0, 0.0),
+        &Transform::rotate(&Vec3f::new(0.0, 15.0, 0.0))
+            .then(&Transform::translate(&Vec3f::new(265.0, 0.0, 295.0))),
     ));
-    let translated_box1:Arc<Translation>  = Arc::new(Translation::new(
-        rotated_box1,
-        &Point3f::new(265.0, 0.0, 295.0),
-    ));
-    scene.add(translated_box1);
+    scene.add(transformed_box1);
 
     // Glass Sphere
     let glass: Arc<Dielectric> = Arc::new(Dielectric::new(1.5));
@@ -633,30 +655,24 @@ pub fn cornell_smoke() -> (HittableList, HittableList, Camera) {
         &Point3f::new(165.0, 330.0, 165.0),
         white.clone()
     );
-    let rotated_box1:Arc<EulerRotation>  = Arc::new(EulerRotation::new(
+    let transformed_box1: Arc<Transformed> = Arc::new(Transformed::new(
         box1,
-        &Vec3f::new(0.0, 15.0, 0.0),
-    ));
-    let translated_box1:Arc<Translation>  = Arc::new(Translation::new(
-        rotated_box1,
-        &Point3f::new(265.0, 0.0, 295.0),
+        &Transform::rotate(&Vec3f::new(0.0, 15.0, 0.0))
+            .then(&Transform::translate(&Vec3f::new(265.0, 0.0, 295.0))),
     ));
-    scene.add(Arc::new(ConstantMedium::from_color(translated_box1, 0.01, &Color::ZERO)));
+    scene.add(Arc::new(ConstantMedium::from_color(transformed_box1, 0.01, &Color::ZERO)));
 
     let box2: Arc<HittableList> = Quad::new_box(
         &Point3f::new(0.0, 0.0, 0.0),
         &Point3f::new(165.0, 165.0, 165.0),
         white.clone()
     );
-    let translated_box2:Arc<Translation> = Arc::new(Translation::new(
+    let transformed_box2: Arc<Transformed> = Arc::new(Transformed::new(
         box2,
-        &Point3f::new(130.0, 0.0, 65.0),
-    ));
-    let rotated_box2:Arc<EulerRotation>  = Arc::new(EulerRotation::new(
-        translated_box2,
-        &Vec3f::new(0.0, -18.0, 0.0),
+        &Transform::translate(&Vec3f::new(130.0, 0.0, 65.0))
+            .then(&Transform::rotate(&Vec3f::new(0.0, -18.0, 0.0))),
     ));
-    scene.add(Arc::new(ConstantMedium::from_color(rotated_box2, 0.01, &Color::ONE)));
+    scene.add(Arc::new(ConstantMedium::from_color(transformed_box2, 0.01, &Color::ONE)));
 
     // Camera
     let aspect_ratio: f64       = 1.0;
@@ -721,6 +737,13 @@ pub fn final_scene(image_width: u32, samples_per_pixel: u32, max_depth: u32) ->
         &Vec3f::new(0.0, 0.0, 265.0),
         light,
     )));
+    let mut lights: HittableList = HittableList::new();
+    lights.add(Arc::new(Quad::new(
+        &Point3f::new(123.0, 554.0, 147.0),
+        &Vec3f::new(300.0, 0.0, 0.0),
+        &Vec3f::new(0.0, 0.0, 265.0),
+        Arc::new(Empty),
+    )));
 
     let center1: Vec3f = Point3f::new(400.0, 400.0, 200.0);
     let center2: Vec3f = center1 + Vec3f::new(30.0, 0.0, 0.0);
@@ -793,12 +816,10 @@ pub fn final_scene(image_width: u32, samples_per_pixel: u32, max_depth: u32) ->
         )));
     }
 
-    scene.add(Arc::new(Translation::new(
-        Arc::new(EulerRotation::new(
-            Arc::new(BVHNode::from_hittable_list(&mut boxes2)),
-            &Vec3f::new(0.0, 15.0, 0.0),
-        )),
-        &Vec3f::new(-100.0, 270.0, 395.0),
+    scene.add(Arc::new(Transformed::new(
+        Arc::new(BVHNode::from_hittable_list(&mut boxes2)),
+        &Transform::rotate(&Vec3f::new(0.0, 15.0, 0.0))
+            .then(&Transform::translate(&Vec3f::new(-100.0, 270.0, 395.0))),
     )));
 
     // Camera
@@ -823,5 +844,5 @@ pub fn final_scene(image_width: u32, samples_per_pixel: u32, max_depth: u32) ->
         defocus_angle, focus_dist
     );
 
-    (scene, HittableList::new(), cam)
+    (scene, lights, cam)
 }
\ No newline at end of file
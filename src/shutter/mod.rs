@@ -0,0 +1,134 @@
+use serde::{Deserialize, Serialize};
+
+// When the camera shutter is open within a frame, and how far. Times are fractions of the frame
+// duration from the start of the frame, the same units as Ray::time, so [0, 0.5] is a 180 degree
+// shutter whatever the frame rate.
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShutterCurve {
+    // Fully open from open to close.
+    #[default]
+    Box,
+    // Opens linearly until halfway, then closes again.
+    Triangle,
+    // Relative openness at evenly spaced times from open to close, linearly interpolated.
+    Custom(Vec<f64>),
+}
+
+impl ShutterCurve {
+    fn weights(&self) -> Vec<f64> {
+        match self {
+            ShutterCurve::Box => vec![1.0, 1.0],
+            ShutterCurve::Triangle => vec![0.0, 1.0, 0.0],
+            ShutterCurve::Custom(weights) => weights.clone()
+        }
+    }
+}
+
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Shutter {
+    open: f64,
+    close: f64,
+    curve: ShutterCurve,
+    weights: Vec<f64>,
+    cdf: Vec<f64>                   // Normalized exposure up to the end of each curve segment
+}
+
+impl Default for Shutter {
+    fn default() -> Self {
+        Shutter::new(0.0, 1.0, ShutterCurve::Box).expect("the default shutter is valid")
+    }
+}
+
+impl Shutter {
+    pub fn new(open: f64, close: f64, curve: ShutterCurve) -> Result<Self, String> {
+        if !(open.is_finite() && close.is_finite() && open <= close) {
+            return Err(format!("the shutter must open before it closes, not at {} and {}", open, close));
+        }
+        let weights: Vec<f64> = curve.weights();
+        if weights.len() < 2 || weights.iter().any(|weight| !(weight.is_finite() && *weight >= 0.0)) {
+            return Err("a shutter curve needs at least two weights, none of them negative".to_string());
+        }
+
+        // Each segment's exposure is the area of a trapezoid.
+        let mut cdf: Vec<f64> = Vec::with_capacity(weights.len() - 1);
+        let mut total: f64 = 0.0;
+        for pair in weights.windows(2) {
+            total += 0.5 * (pair[0] + pair[1]);
+            cdf.push(total);
+        }
+        if total == 0.0 {
+            return Err("a shutter curve needs a positive weight".to_string());
+        }
+        cdf.iter_mut().for_each(|value| *value /= total);
+        Ok(Self { open, close, curve, weights, cdf })
+    }
+
+    #[inline]
+    pub fn open(&self) -> f64 {
+        self.open
+    }
+
+    #[inline]
+    pub fn close(&self) -> f64 {
+        self.close
+    }
+
+    pub fn curve(&self) -> &ShutterCurve {
+        &self.curve
+    }
+
+    pub fn sample(&self, u: f64) -> f64 {
+        // Maps a uniform u in [0, 1) to a time weighted by the curve, by inverting its piecewise
+        // quadratic cumulative exposure.
+        let segment: usize = self.cdf.partition_point(|&value| value <= u).min(self.cdf.len() - 1);
+        let segment_start: f64 = if segment == 0 { 0.0 } else { self.cdf[segment - 1] };
+        let segment_size: f64 = self.cdf[segment] - segment_start;
+        let (a, b) = (self.weights[segment], self.weights[segment + 1]);
+
+        // Solve a x + (b - a) x^2 / 2 = area for x in [0, 1], the position within the segment.
+        let area: f64 = if segment_size > 0.0 { (u - segment_start) / segment_size * 0.5 * (a + b) } else { 0.0 };
+        let x: f64 = if (b - a).abs() < 1e-9 * (a + b) {
+            area / a
+        }
+        else {
+            (f64::sqrt((a * a + 2.0 * (b - a) * area).max(0.0)) - a) / (b - a)
+        };
+        let segment_duration: f64 = (self.close - self.open) / self.cdf.len() as f64;
+        self.open + (segment as f64 + x.clamp(0.0, 1.0)) * segment_duration
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::shutter::*;
+
+    fn samples(shutter: &Shutter) -> Vec<f64> {
+        (0..1000).map(|i| shutter.sample((i as f64 + 0.5) / 1000.0)).collect()
+    }
+
+    #[test]
+    fn curves() {
+        let box_shutter: Shutter = Shutter::new(0.25, 0.75, ShutterCurve::Box).unwrap();
+        assert!((box_shutter.sample(0.5) - 0.5).abs() < 1e-12);
+        assert!((box_shutter.sample(0.1) - 0.3).abs() < 1e-12);
+
+        // An eighth of the exposure of a triangle is in its first quarter, and half in its first half.
+        let triangle: Vec<f64> = samples(&Shutter::new(0.0, 1.0, ShutterCurve::Triangle).unwrap());
+        assert!(triangle.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert_eq!(triangle.iter().filter(|&&time| time < 0.25).count(), 125);
+        assert_eq!(triangle.iter().filter(|&&time| time < 0.5).count(), 500);
+
+        // Closed for the first third, opening over the second, then fully open.
+        let custom: Vec<f64> = samples(&Shutter::new(0.0, 1.0, ShutterCurve::Custom(vec![0.0, 0.0, 1.0, 1.0])).unwrap());
+        assert!(custom.iter().all(|&time| time >= 1.0 / 3.0));
+        assert_eq!(custom.iter().filter(|&&time| time < 2.0 / 3.0).count(), 333);
+
+        assert!(Shutter::new(1.0, 0.0, ShutterCurve::Box).is_err());
+        assert!(Shutter::new(0.0, 1.0, ShutterCurve::Custom(vec![1.0])).is_err());
+        assert!(Shutter::new(0.0, 1.0, ShutterCurve::Custom(vec![0.0, 0.0])).is_err());
+    }
+}
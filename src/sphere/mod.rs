@@ -3,12 +3,11 @@ use std::fmt;
 use std::sync::Arc;
 
 use crate::aabb::AABB;
-use crate::hittable::{HitRecord, Hittable};
+use crate::hittable::{HitRecord, Hittable, SurfaceSample};
 use crate::interval::Interval;
 use crate::material::Material;
 use crate::onb::ONB;
 use crate::ray::Ray;
-use crate::utilities;
 use crate::vec3::{Axis, Point3f, Vec3f};
 
 #[derive(Clone)]
@@ -64,9 +63,8 @@ impl Sphere {
         (phi / (2.0 * f64::consts::PI), theta / f64::consts::PI)
     }
 
-    fn random_to_sphere(radius: f64, distance_squared: f64) -> Vec3f {
-        let r1: f64 = utilities::random();
-        let r2: f64 = utilities::random();
+    fn random_to_sphere(radius: f64, distance_squared: f64, u: (f64, f64)) -> Vec3f {
+        let (r1, r2) = u;
         let z: f64 = 1.0 + r2 * (f64::sqrt(1.0 - radius*radius / distance_squared) - 1.0);
 
         let phi: f64 = 2.0 * f64::consts::PI * r1;
@@ -75,6 +73,18 @@ impl Sphere {
 
         Vec3f::new(x, y, z)
     }
+
+    pub fn center(&self) -> &Ray {
+        &self.center
+    }
+
+    pub fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    pub fn material(&self) -> &Arc<dyn Material> {
+        &self.mat
+    }
 }
 
 impl Hittable for Sphere {
@@ -121,11 +131,10 @@ impl Hittable for Sphere {
         &self.bounding_box
     }
 
-    fn pdf_value(&self, origin: &Point3f, direction: &Vec3f) -> f64 {
-        // Only works for stationary spheres
-
-        if let Some(_rec) = self.hit(&Ray::new(origin, direction), &Interval::new(0.001, f64::INFINITY)) {
-            let dist_squared: f64 = (self.center.at(0.0) - origin).length_squared();
+    fn pdf_value(&self, origin: &Point3f, direction: &Vec3f, time: f64) -> f64 {
+        // A moving sphere is sampled where it is at time.
+        if let Some(_rec) = self.hit(&Ray::with_time(origin, direction, time), &Interval::new(0.001, f64::INFINITY)) {
+            let dist_squared: f64 = (self.center.at(time) - origin).length_squared();
             let cos_theta_max: f64 = f64::sqrt(1.0 - self.radius * self.radius / dist_squared);
             let solid_angle: f64 = 2.0 * f64::consts::PI * (1.0 - cos_theta_max);
 
@@ -134,10 +143,26 @@ impl Hittable for Sphere {
         0.0
     }
 
-    fn random(&self, origin: &Point3f) -> Vec3f {
-        let direction: Vec3f = self.center.at(0.0) - origin;
+    fn random(&self, origin: &Point3f, u: (f64, f64), time: f64) -> Vec3f {
+        let direction: Vec3f = self.center.at(time) - origin;
         let distance_squared: f64 = direction.length_squared();
         let uvw: ONB = ONB::new(&direction);
-        uvw.transform(&Self::random_to_sphere(self.radius, distance_squared))
+        uvw.transform(&Self::random_to_sphere(self.radius, distance_squared, u))
+    }
+
+    fn sample_surface(&self, u: (f64, f64), time: f64) -> Option<SurfaceSample> {
+        let normal: Vec3f = Vec3f::sample_unit_sphere(u);
+        Some(SurfaceSample {
+            point: self.center.at(time) + self.radius * normal,
+            normal,
+            pdf: 1.0 / (4.0 * f64::consts::PI * self.radius * self.radius)
+        })
+    }
+
+    fn surface_pdf(&self, origin: &Point3f, direction: &Vec3f, time: f64) -> f64 {
+        match self.hit(&Ray::with_time(origin, direction, time), &Interval::new(0.001, f64::INFINITY)) {
+            Some(_) => 1.0 / (4.0 * f64::consts::PI * self.radius * self.radius),
+            None => 0.0
+        }
     }
 }
\ No newline at end of file
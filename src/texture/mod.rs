@@ -1,16 +1,24 @@
+use std::any::Any;
 use std::fmt;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use image::{DynamicImage, GenericImageView};
 
 use crate::color::Color;
+use crate::hittable::HitRecord;
 use crate::interval::Interval;
 use crate::perlin::{Perlin, PerlinTexture};
 use crate::vec3::{Axis, Point3f};
 
-pub trait Texture: Send + Sync + fmt::Display {
+pub trait Texture: Any + Send + Sync + fmt::Display {
     fn value(&self, uv: (f64, f64), point: &Point3f) -> Color;
+
+    fn value_at(&self, rec: &HitRecord) -> Color {
+        // Materials look textures up through the whole hit record, so textures that depend on
+        // more than the uv and point, like per-vertex colors, can override this.
+        self.value(rec.uv, &rec.point)
+    }
 }
 
 
@@ -32,6 +40,10 @@ impl Solid {
     pub fn from_rgb(r: f64, g: f64, b: f64) -> Self {
         Self::new(&Color::new(r, g, b))
     }
+
+    pub fn albedo(&self) -> &Color {
+        &self.albedo
+    }
 }
 
 impl Texture for Solid {
@@ -42,6 +54,7 @@ impl Texture for Solid {
 
 
 pub struct Checker {
+    scale: f64,
     inv_scale: f64,
     even: Arc<dyn Texture>,
     odd: Arc<dyn Texture>
@@ -55,7 +68,7 @@ impl fmt::Display for Checker {
 
 impl Checker {
     pub fn new(scale: f64, even: Arc<dyn Texture>, odd: Arc<dyn Texture>) -> Self {
-        Self { inv_scale: 1.0 / scale, even, odd }
+        Self { scale, inv_scale: 1.0 / scale, even, odd }
     }
 
     pub fn from_color(scale: f64, color1: &Color, color2: &Color) -> Self {
@@ -65,24 +78,47 @@ impl Checker {
             Arc::new(Solid::new(color2))
         )
     }
-}
 
-impl Texture for Checker {
-    fn value(&self, uv: (f64, f64), point: &Point3f) -> Color {
+    fn is_even(&self, point: &Point3f) -> bool {
         let x_int: i32 = (self.inv_scale * point.component(Axis::X)).floor() as i32;
         let y_int: i32 = (self.inv_scale * point.component(Axis::Y)).floor() as i32;
         let z_int: i32 = (self.inv_scale * point.component(Axis::Z)).floor() as i32;
+        (x_int + y_int + z_int) % 2 == 0
+    }
+
+    pub fn scale(&self) -> f64 {
+        self.scale
+    }
 
-        if (x_int + y_int + z_int) % 2 == 0 {
+    pub fn even(&self) -> &Arc<dyn Texture> {
+        &self.even
+    }
+
+    pub fn odd(&self) -> &Arc<dyn Texture> {
+        &self.odd
+    }
+}
+
+impl Texture for Checker {
+    fn value(&self, uv: (f64, f64), point: &Point3f) -> Color {
+        if self.is_even(point) {
             return self.even.value(uv, point);
         }
         self.odd.value(uv, point)
     }
+
+    fn value_at(&self, rec: &HitRecord) -> Color {
+        if self.is_even(&rec.point) {
+            return self.even.value_at(rec);
+        }
+        self.odd.value_at(rec)
+    }
 }
 
 
 pub struct Image {
-    img: DynamicImage
+    img: DynamicImage,
+    filepath: Option<PathBuf>       // Source file, if the image was not embedded in another file
 }
 
 impl fmt::Display for Image {
@@ -94,13 +130,21 @@ impl fmt::Display for Image {
 impl Image {
     pub fn read_image(filepath: &Path) -> Result<Self, String> {
         let img: DynamicImage = image::open(filepath).map_err(|err| err.to_string())?;
-        Ok(Self { img })
+        Ok(Self::from_image(img, Some(filepath.to_path_buf())))
+    }
+
+    pub fn from_image(img: DynamicImage, filepath: Option<PathBuf>) -> Self {
+        Self { img, filepath }
+    }
+
+    pub fn filepath(&self) -> Option<&Path> {
+        self.filepath.as_deref()
     }
 }
 
 impl Texture for Image {
     fn value(&self, uv: (f64, f64), _point: &Point3f) -> Color {
-        if self.img.height() == 0 {
+        if self.img.width() == 0 || self.img.height() == 0 {
             return Color::new(0.0, 1.0, 1.0);
         }
 
@@ -108,8 +152,9 @@ impl Texture for Image {
         let clamped_u: f64 = Interval::UNIT.clamp(uv.0);
         let clamped_v: f64 = 1.0 - Interval::UNIT.clamp(uv.1);  // Flip V to image coordinates
 
-        let x: u32 = (clamped_u * self.img.width() as f64) as u32;
-        let y: u32 = (clamped_v * self.img.height() as f64) as u32;
+        // A coordinate of exactly 1 belongs to the last pixel.
+        let x: u32 = ((clamped_u * self.img.width() as f64) as u32).min(self.img.width() - 1);
+        let y: u32 = ((clamped_v * self.img.height() as f64) as u32).min(self.img.height() - 1);
         let pixel = self.img.get_pixel(x, y);
 
         let color_scale: f64 = 1.0 / 255.0;
@@ -122,6 +167,91 @@ impl Texture for Image {
 }
 
 
+// Another texture multiplied by a constant color, like glTF's base color factor applied to its
+// base color texture.
+pub struct Scaled {
+    texture: Arc<dyn Texture>,
+    scale: Color
+}
+
+impl fmt::Display for Scaled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Scaled Texture Scale: {}; Texture: {}", self.scale, self.texture)
+    }
+}
+
+impl Scaled {
+    pub fn new(texture: Arc<dyn Texture>, scale: &Color) -> Self {
+        Self { texture, scale: *scale }
+    }
+
+    pub fn texture(&self) -> &Arc<dyn Texture> {
+        &self.texture
+    }
+
+    pub fn scale(&self) -> &Color {
+        &self.scale
+    }
+}
+
+impl Texture for Scaled {
+    fn value(&self, uv: (f64, f64), point: &Point3f) -> Color {
+        self.scale * self.texture.value(uv, point)
+    }
+
+    fn value_at(&self, rec: &HitRecord) -> Color {
+        self.scale * self.texture.value_at(rec)
+    }
+}
+
+
+pub struct VertexColor {
+    colors: Vec<Color>,
+    mean: Color     // Returned where there is no mesh face to interpolate over
+}
+
+impl fmt::Display for VertexColor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Vertex Color Texture Vertices: {}", self.colors.len())
+    }
+}
+
+impl VertexColor {
+    pub fn new(colors: Vec<Color>) -> Self {
+        // One color per vertex of the mesh this texture is applied to.
+        let mean: Color = if colors.is_empty() {
+            Color::ONE
+        }
+        else {
+            colors.iter().copied().sum::<Color>() / colors.len() as f64
+        };
+        Self { colors, mean }
+    }
+
+    pub fn colors(&self) -> &[Color] {
+        &self.colors
+    }
+}
+
+impl Texture for VertexColor {
+    fn value(&self, _uv: (f64, f64), _point: &Point3f) -> Color {
+        // Without a mesh face there is nothing to interpolate.
+        self.mean
+    }
+
+    fn value_at(&self, rec: &HitRecord) -> Color {
+        match rec.face {
+            Some(face) if face.vertices.iter().all(|vertex| *vertex < self.colors.len()) => {
+                face.vertices.iter().zip(face.barycentric).map(
+                    |(vertex, weight)| weight * self.colors[*vertex]
+                ).sum()
+            },
+            _ => self.value(rec.uv, &rec.point)
+        }
+    }
+}
+
+
 pub struct Noise {
     noise: Perlin,
     perlin_texture: PerlinTexture,
@@ -136,7 +266,23 @@ impl fmt::Display for Noise {
 
 impl Noise {
     pub fn new(point_count: usize, perlin_texture: PerlinTexture, scale: f64) -> Self {
-        Self { noise: Perlin::new(point_count), perlin_texture, scale }
+        Self::from_perlin(Perlin::new(point_count), perlin_texture, scale)
+    }
+
+    pub fn from_perlin(noise: Perlin, perlin_texture: PerlinTexture, scale: f64) -> Self {
+        Self { noise, perlin_texture, scale }
+    }
+
+    pub fn noise(&self) -> &Perlin {
+        &self.noise
+    }
+
+    pub fn perlin_texture(&self) -> &PerlinTexture {
+        &self.perlin_texture
+    }
+
+    pub fn scale(&self) -> f64 {
+        self.scale
     }
 }
 
@@ -1,145 +1,588 @@
 use std::sync::Arc;
 
 use crate::aabb::AABB;
-use crate::hittable::{Hittable, HitRecord};
+use crate::animation::{Interpolate, Interpolation, Keyframe, Track};
+use crate::hittable::{Hittable, HitRecord, SurfaceSample};
 use crate::interval::Interval;
+use crate::matrix::Matrix4;
+use crate::quaternion::Quaternion;
 use crate::ray::Ray;
 use crate::utilities;
 use crate::vec3::{Axis, Point3f, Vec3f};
 
-pub struct Translation {
+// An invertible affine transform from object space to world space, kept together with its inverse
+// so neither direction has to be recomputed per ray.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    matrix: Matrix4,
+    inverse: Matrix4
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Transform::IDENTITY
+    }
+}
+
+impl Transform {
+    pub const IDENTITY: Transform = Transform { matrix: Matrix4::IDENTITY, inverse: Matrix4::IDENTITY };
+
+    pub fn new(matrix: &Matrix4) -> Result<Self, String> {
+        let inverse: Matrix4 = matrix.inverse().ok_or(format!("transform matrix {} is not invertible", matrix))?;
+        Ok(Self { matrix: *matrix, inverse })
+    }
+
+    pub fn translate(offset: &Vec3f) -> Self {
+        Self { matrix: Matrix4::translation(offset), inverse: Matrix4::translation(&-*offset) }
+    }
+
+    pub fn rotate(degrees: &Vec3f) -> Self {
+        // Rotation matrices are orthogonal, so the inverse is the transpose.
+        let matrix: Matrix4 = Matrix4::euler_rotation(degrees);
+        Self { matrix, inverse: matrix.transpose() }
+    }
+
+    pub fn rotate_axis(axis: Axis, degrees: f64) -> Self {
+        let matrix: Matrix4 = Matrix4::rotation(axis, degrees);
+        Self { matrix, inverse: matrix.transpose() }
+    }
+
+    pub fn rotate_quaternion(rotation: &Quaternion) -> Self {
+        let matrix: Matrix4 = rotation.to_matrix();
+        Self { matrix, inverse: matrix.transpose() }
+    }
+
+    pub fn scale(factors: &Vec3f) -> Result<Self, String> {
+        Self::new(&Matrix4::scaling(factors))
+    }
+
+    pub fn shear(xy: f64, xz: f64, yx: f64, yz: f64, zx: f64, zy: f64) -> Result<Self, String> {
+        Self::new(&Matrix4::shearing(xy, xz, yx, yz, zx, zy))
+    }
+
+    pub fn then(&self, next: &Transform) -> Transform {
+        // Applies self first, then next.
+        Transform { matrix: next.matrix * self.matrix, inverse: self.inverse * next.inverse }
+    }
+
+    #[inline]
+    pub fn matrix(&self) -> &Matrix4 {
+        &self.matrix
+    }
+
+    #[inline]
+    pub fn inverse(&self) -> Transform {
+        Transform { matrix: self.inverse, inverse: self.matrix }
+    }
+
+    pub fn is_identity(&self) -> bool {
+        self.matrix == Matrix4::IDENTITY
+    }
+
+    #[inline]
+    pub fn point(&self, point: &Point3f) -> Point3f {
+        self.matrix.transform_point(point)
+    }
+
+    #[inline]
+    pub fn vector(&self, v: &Vec3f) -> Vec3f {
+        self.matrix.transform_vector(v)
+    }
+
+    #[inline]
+    pub fn normal(&self, normal: &Vec3f) -> Vec3f {
+        // Normals stay perpendicular to transformed surfaces under the inverse transpose. The
+        // result is not normalized.
+        self.inverse.transform_vector_transposed(normal)
+    }
+
+    pub fn ray(&self, ray: &Ray) -> Ray {
+        Ray::with_time(&self.point(ray.origin()), &self.vector(ray.direction()), ray.time())
+    }
+
+    pub fn bounding_box(&self, bounding_box: &AABB) -> AABB {
+        matrix_bounding_box(&self.matrix, bounding_box)
+    }
+}
+
+fn matrix_bounding_box(matrix: &Matrix4, bounding_box: &AABB) -> AABB {
+    // Each world axis spans the translation plus, per object axis, the smaller and larger of
+    // the scaled interval ends (Arvo's method). This matches the box around the 8 transformed
+    // corners, without transforming them.
+    if Axis::iterator().any(|&axis| bounding_box.axis_interval(axis).size() < 0.0) {
+        return AABB::EMPTY;
+    }
+
+    let mut intervals: [Interval; 3] = [Interval::EMPTY; 3];
+    for &row in Axis::iterator() {
+        let translation: f64 = matrix.get(row as usize, 3);
+        let mut interval: Interval = Interval::new(translation, translation);
+        for &column in Axis::iterator() {
+            let factor: f64 = matrix.get(row as usize, column as usize);
+            if factor == 0.0 {
+                // Skipped so that infinite extents do not turn into NaN.
+                continue;
+            }
+            let span: Interval = bounding_box.axis_interval(column);
+            let (a, b) = (factor * span.min, factor * span.max);
+            interval.min += a.min(b);
+            interval.max += a.max(b);
+        }
+        intervals[row as usize] = interval;
+    }
+    let [x, y, z] = intervals;
+    AABB::from_interval(x, y, z)
+}
+
+
+// An object placed by a transform. Transformed and MotionTransformed share these, the latter with
+// the transform at the time of each ray.
+
+fn local_hit(object: &Arc<dyn Hittable>, transform: &Transform, ray: &Ray, ray_t: &Interval) -> Option<(Ray, HitRecord)> {
+    // The object space ray keeps the unnormalized direction, so t is the same in both spaces.
+    let local_ray: Ray = transform.inverse().ray(ray);
+    let rec: HitRecord = object.hit(&local_ray, ray_t)?;
+    Some((local_ray, rec))
+}
+
+fn transformed_hit(object: &Arc<dyn Hittable>, transform: &Transform, ray: &Ray, ray_t: &Interval) -> Option<HitRecord> {
+    let (_, mut rec) = local_hit(object, transform, ray, ray_t)?;
+
+    // The inverse transpose keeps dot(normal, direction) unchanged, so front_face still holds.
+    rec.point = transform.point(&rec.point);
+    rec.normal = Vec3f::unit_vector(&transform.normal(&rec.normal));
+    rec.geometric_normal = Vec3f::unit_vector(&transform.normal(&rec.geometric_normal));
+    Some(rec)
+}
+
+fn transformed_pdf_value(object: &Arc<dyn Hittable>, transform: &Transform, origin: &Point3f, direction: &Vec3f, time: f64) -> f64 {
+    let (local_ray, rec) = match local_hit(object, transform, &Ray::with_time(origin, direction, time), &Interval::new(0.001, f64::INFINITY)) {
+        Some(hit) => hit,
+        None => return 0.0
+    };
+    let local_pdf: f64 = object.pdf_value(local_ray.origin(), local_ray.direction(), time);
+    if local_pdf == 0.0 {
+        return 0.0;
+    }
+
+    // Convert the object space solid angle density to a density over surface area, rescale it by
+    // how much the transform stretches the surface there, and convert back to world space solid
+    // angle. For rigid transforms every factor cancels out.
+    let to_area = |dir: &Vec3f, normal: &Vec3f| -> f64 {
+        let cos_theta: f64 = f64::abs(Vec3f::dot(dir, normal) / dir.length());
+        cos_theta / (rec.t * rec.t * dir.length_squared())
+    };
+    let world_normal: Vec3f = transform.normal(&rec.geometric_normal);
+    let world_area_pdf: f64 = local_pdf * to_area(local_ray.direction(), &rec.geometric_normal) / area_scale(transform, &rec.geometric_normal);
+    let world_to_area: f64 = to_area(direction, &Vec3f::unit_vector(&world_normal));
+    if world_to_area == 0.0 {
+        return 0.0;
+    }
+    world_area_pdf / world_to_area
+}
+
+fn area_scale(transform: &Transform, local_normal: &Vec3f) -> f64 {
+    // How much the transform stretches a small patch of surface with the given unit normal.
+    f64::abs(transform.matrix().determinant3()) * transform.normal(local_normal).length()
+}
+
+fn transformed_sample_surface(object: &Arc<dyn Hittable>, transform: &Transform, u: (f64, f64), time: f64) -> Option<SurfaceSample> {
+    let sample: SurfaceSample = object.sample_surface(u, time)?;
+    Some(SurfaceSample {
+        point: transform.point(&sample.point),
+        normal: Vec3f::unit_vector(&transform.normal(&sample.normal)),
+        pdf: sample.pdf / area_scale(transform, &sample.normal)
+    })
+}
+
+fn transformed_surface_pdf(object: &Arc<dyn Hittable>, transform: &Transform, origin: &Point3f, direction: &Vec3f, time: f64) -> f64 {
+    match local_hit(object, transform, &Ray::with_time(origin, direction, time), &Interval::new(0.001, f64::INFINITY)) {
+        Some((local_ray, rec)) => {
+            object.surface_pdf(local_ray.origin(), local_ray.direction(), time) / area_scale(transform, &rec.geometric_normal)
+        },
+        None => 0.0
+    }
+}
+
+fn transformed_random(object: &Arc<dyn Hittable>, transform: &Transform, origin: &Point3f, u: (f64, f64), time: f64) -> Vec3f {
+    let local_origin: Point3f = transform.inverse().point(origin);
+    let local_target: Point3f = local_origin + object.random(&local_origin, u, time);
+    transform.point(&local_target) - *origin
+}
+
+
+pub struct Transformed {
     object: Arc<dyn Hittable>,
-    offset: Vec3f,
+    transform: Transform,
     bounding_box: AABB
 }
 
-impl Translation {
-    pub fn new(object: Arc<dyn Hittable>, offset: &Vec3f) -> Self {
-        let bounding_box: AABB = object.bounding_box() + offset;
-        Self { object, offset: *offset, bounding_box }
+impl Transformed {
+    pub fn new(object: Arc<dyn Hittable>, transform: &Transform) -> Self {
+        let bounding_box: AABB = transform.bounding_box(object.bounding_box());
+        Self { object, transform: *transform, bounding_box }
+    }
+
+    pub fn object(&self) -> &Arc<dyn Hittable> {
+        &self.object
+    }
+
+    pub fn transform(&self) -> &Transform {
+        &self.transform
     }
 }
 
-impl Hittable for Translation {
+impl Hittable for Transformed {
     fn hit(&self, ray: &Ray, ray_t: &Interval) -> Option<HitRecord> {
-        // Move the ray backwards by the offset
-        let offset_r: Ray = Ray::with_time(&(ray.origin() - self.offset), ray.direction(), ray.time());
-
-        // Determine whether an intersection exists along the offset ray (and if so, where)
-        if let Some(mut rec) = self.object.hit(&offset_r, ray_t) {
-            // Move the intersection point forwards by the offset
-            rec.point += self.offset;
-            return Some(rec);
-        }
-        None
+        transformed_hit(&self.object, &self.transform, ray, ray_t)
     }
 
     fn bounding_box(&self) -> &AABB {
         &self.bounding_box
     }
+
+    fn pdf_value(&self, origin: &Point3f, direction: &Vec3f, time: f64) -> f64 {
+        transformed_pdf_value(&self.object, &self.transform, origin, direction, time)
+    }
+
+    fn random(&self, origin: &Point3f, u: (f64, f64), time: f64) -> Vec3f {
+        transformed_random(&self.object, &self.transform, origin, u, time)
+    }
+
+    fn sample_surface(&self, u: (f64, f64), time: f64) -> Option<SurfaceSample> {
+        transformed_sample_surface(&self.object, &self.transform, u, time)
+    }
+
+    fn surface_pdf(&self, origin: &Point3f, direction: &Vec3f, time: f64) -> f64 {
+        transformed_surface_pdf(&self.object, &self.transform, origin, direction, time)
+    }
 }
 
 
-#[derive(Clone, Copy)]
-pub struct AxisRotation;
-
-impl AxisRotation {
-    fn rotate(axis: Axis, point: &Point3f, radian: f64) -> Point3f {
-        match axis {
-            Axis::X => {
-                Point3f::new(
-                    point.component(Axis::X),
-                    radian.cos().mul_add(point.component(Axis::Y), -radian.sin() * point.component(Axis::Z)),
-                    radian.sin().mul_add(point.component(Axis::Y), radian.cos() * point.component(Axis::Z))
-                )
-            },
-            Axis::Y => {
-                Point3f::new(
-                    radian.cos().mul_add(point.component(Axis::X), radian.sin() * point.component(Axis::Z)),
-                    point.component(Axis::Y),
-                    (-radian.sin()).mul_add(point.component(Axis::X), radian.cos() * point.component(Axis::Z))
-                )
-            },
-            Axis::Z => {
-                Point3f::new(
-                    radian.cos().mul_add(point.component(Axis::X), -radian.sin() * point.component(Axis::Y)),
-                    radian.sin().mul_add(point.component(Axis::X), radian.cos() * point.component(Axis::Y)),
-                    point.component(Axis::Z)
-                )
+// A transform split into a translation, a rotation and the remaining scale and shear, which are
+// interpolated separately so that rotations do not distort the object in between.
+#[derive(Clone, Copy, Debug)]
+struct Decomposed {
+    translation: Vec3f,
+    rotation: Quaternion,
+    stretch: Matrix4
+}
+
+impl Decomposed {
+    fn new(transform: &Transform) -> Self {
+        let rows: [[f64; 4]; 4] = transform.matrix().rows();
+        let translation: Vec3f = Vec3f::new(rows[0][3], rows[1][3], rows[2][3]);
+        let mut linear_rows: [[f64; 4]; 4] = Matrix4::IDENTITY.rows();
+        for row in 0..3 {
+            linear_rows[row][..3].copy_from_slice(&rows[row][..3]);
+        }
+        let linear: Matrix4 = Matrix4::new(linear_rows);
+
+        // Polar decomposition: averaging a matrix with its inverse transpose converges to the
+        // nearest rotation. A mirroring transform is flipped first, and the flip stays in stretch.
+        let sign: f64 = if linear.determinant3() < 0.0 { -1.0 } else { 1.0 };
+        let mut rotation: [[f64; 4]; 4] = linear_rows.map(|row| row.map(|value| sign * value));
+        rotation[3][3] = 1.0;
+        for _ in 0..100 {
+            let inverse_transpose: Matrix4 = match Matrix4::new(rotation).inverse() {
+                Some(inverse) => inverse.transpose(),
+                None => break
+            };
+            let mut change: f64 = 0.0;
+            for (row, values) in rotation.iter_mut().enumerate().take(3) {
+                for (column, value) in values.iter_mut().enumerate().take(3) {
+                    let next: f64 = 0.5 * (*value + inverse_transpose.get(row, column));
+                    change = change.max((next - *value).abs());
+                    *value = next;
+                }
+            }
+            if change < 1e-12 {
+                break;
+            }
+        }
+        let rotation: Matrix4 = Matrix4::new(rotation);
+        Self { translation, rotation: Quaternion::from_matrix(&rotation), stretch: rotation.transpose() * linear }
+    }
+
+    fn matrix(&self) -> Matrix4 {
+        Matrix4::translation(&self.translation) * self.rotation.to_matrix() * self.stretch
+    }
+
+    fn transform(&self) -> Option<Transform> {
+        // Inverted part by part, which is much cheaper per ray than inverting the whole matrix.
+        // None when the stretch is singular.
+        let rotation: Matrix4 = self.rotation.to_matrix();
+        let inverse: Matrix4 = self.stretch.inverse3()? * rotation.transpose() * Matrix4::translation(&-self.translation);
+        Some(Transform { matrix: Matrix4::translation(&self.translation) * rotation * self.stretch, inverse })
+    }
+
+    fn stretched_radius(&self, bounding_box: &AABB) -> f64 {
+        // The furthest any point of the box gets from the origin before rotating and translating.
+        // The length of a linear map of a point is convex, so a corner is furthest.
+        let mut radius: f64 = 0.0;
+        for corner in 0..8 {
+            let point: Point3f = Point3f::new(
+                if corner & 1 == 0 { bounding_box.x.min } else { bounding_box.x.max },
+                if corner & 2 == 0 { bounding_box.y.min } else { bounding_box.y.max },
+                if corner & 4 == 0 { bounding_box.z.min } else { bounding_box.z.max }
+            );
+            radius = radius.max(self.stretch.transform_vector(&point).length());
+        }
+        radius
+    }
+}
+
+impl Interpolate for Decomposed {
+    fn lerp(a: &Decomposed, b: &Decomposed, t: f64) -> Decomposed {
+        let (a_rows, b_rows) = (a.stretch.rows(), b.stretch.rows());
+        let mut stretch: [[f64; 4]; 4] = a_rows;
+        for row in 0..4 {
+            for column in 0..4 {
+                stretch[row][column] = f64::lerp(&a_rows[row][column], &b_rows[row][column], t);
             }
         }
+        Decomposed {
+            translation: Vec3f::lerp(&a.translation, &b.translation, t),
+            rotation: a.rotation.slerp(&b.rotation, t),
+            stretch: Matrix4::new(stretch)
+        }
     }
 }
 
-pub struct EulerRotation {
+
+// An object moving over the shutter interval, from times 0 to 1, between keyframed transforms.
+// Translation, rotation and scale are interpolated separately, so a rotating object turns rather
+// than shrinking through the matrix average, and rays see the object where it is at their time.
+pub struct MotionTransformed {
     object: Arc<dyn Hittable>,
-    euler_angles: Vec3f,
+    keyframes: Vec<Keyframe<Transform>>,
+    motion: Track<Decomposed>,
     bounding_box: AABB
 }
 
-impl EulerRotation {
-    pub fn new(object: Arc<dyn Hittable>, angles: &Vec3f) -> Self {
-        let mut euler_angles: Vec3f = Default::default();
-        for &axis in Axis::iterator() {
-            euler_angles.set_component(axis, utilities::degrees_to_radians(angles.component(axis)));
-        }
+impl MotionTransformed {
+    // Largest rotation in degrees between the placements used to bound the motion.
+    const BOUNDING_STEP_ANGLE: f64 = 1.0;
 
-        let bounding_box: &AABB = object.bounding_box();
-        let mut point_min: Point3f = Point3f::INFINITY;
-        let mut point_max: Point3f = -Point3f::INFINITY;
-
-        #[allow(clippy::needless_range_loop)]
-        for i in 0..2 {
-            for j in 0..2 {
-                for k in 0..2 {
-                    let x: f64 = (i as f64).mul_add(bounding_box.x.max, ((1 - i) as f64) * bounding_box.x.min);
-                    let y: f64 = (j as f64).mul_add(bounding_box.y.max, ((1 - j) as f64) * bounding_box.y.min);
-                    let z: f64 = (k as f64).mul_add(bounding_box.z.max, ((1 - k) as f64) * bounding_box.z.min);
-
-                    let mut rotated_point: Point3f = Point3f::new(x, y, z);
-                    for &axis in Axis::iterator() {
-                        rotated_point = AxisRotation::rotate(axis, &rotated_point, euler_angles.component(axis));
-                    }
-
-                    for &axis in Axis::iterator() {
-                        point_min.set_component(axis, f64::min(point_min.component(axis), rotated_point.component(axis)));
-                        point_max.set_component(axis, f64::max(point_max.component(axis), rotated_point.component(axis)));
-                    }
+    pub fn new(object: Arc<dyn Hittable>, keyframes: Vec<Keyframe<Transform>>) -> Result<Self, String> {
+        let decomposed: Vec<Keyframe<Decomposed>> = keyframes.iter().map(
+            |keyframe| Keyframe { time: keyframe.time, value: Decomposed::new(&keyframe.value) }
+        ).collect();
+        let motion: Track<Decomposed> = Track::new(decomposed, Interpolation::Linear)?;
+        let bounding_box: AABB = Self::motion_bounding_box(object.bounding_box(), &motion);
+        Ok(Self { object, keyframes, motion, bounding_box })
+    }
+
+    pub fn object(&self) -> &Arc<dyn Hittable> {
+        &self.object
+    }
+
+    pub fn keyframes(&self) -> &[Keyframe<Transform>] {
+        &self.keyframes
+    }
+
+    pub fn transform_at(&self, time: f64) -> Option<Transform> {
+        // None while the object is scaled to nothing.
+        self.motion.at(time).transform()
+    }
+
+    fn motion_bounding_box(object_box: &AABB, motion: &Track<Decomposed>) -> AABB {
+        // Each step between two placements a and b is bounded by the boxes at a, and at b with the
+        // rotation of a, since translation and stretch change linearly. The rest of the rotation
+        // moves a point at distance r by at most 2 r sin(angle / 2), which pads the step's box.
+        let keyframes: &[Keyframe<Decomposed>] = motion.keyframes();
+        let mut bounding_box: AABB = matrix_bounding_box(&keyframes[0].value.matrix(), object_box);
+        for pair in keyframes.windows(2) {
+            let (a, b) = (&pair[0].value, &pair[1].value);
+            let angle: f64 = a.rotation.angle_to(&b.rotation);
+            let steps: usize = (angle / utilities::degrees_to_radians(Self::BOUNDING_STEP_ANGLE)).ceil().max(1.0) as usize;
+            let step_angle: f64 = angle / steps as f64;
+            for step in 0..steps {
+                let start: Decomposed = Decomposed::lerp(a, b, step as f64 / steps as f64);
+                let end: Decomposed = Decomposed {
+                    rotation: start.rotation, ..Decomposed::lerp(a, b, (step + 1) as f64 / steps as f64)
+                };
+                let mut step_box: AABB = AABB::from_bounding_box(
+                    &matrix_bounding_box(&start.matrix(), object_box), &matrix_bounding_box(&end.matrix(), object_box)
+                );
+                if step_angle > 0.0 {
+                    let radius: f64 = start.stretched_radius(object_box).max(end.stretched_radius(object_box));
+                    let padding: f64 = 2.0 * radius * (0.5 * step_angle).sin();
+                    step_box = AABB::from_interval(
+                        Interval::new(step_box.x.min - padding, step_box.x.max + padding),
+                        Interval::new(step_box.y.min - padding, step_box.y.max + padding),
+                        Interval::new(step_box.z.min - padding, step_box.z.max + padding)
+                    );
                 }
+                bounding_box = AABB::from_bounding_box(&bounding_box, &step_box);
             }
         }
-
-        Self { object, euler_angles, bounding_box: AABB::from_point(&point_min, &point_max) }
+        bounding_box
     }
 }
 
-impl Hittable for EulerRotation {
+impl Hittable for MotionTransformed {
     fn hit(&self, ray: &Ray, ray_t: &Interval) -> Option<HitRecord> {
-        // Transform the ray from world space to object space.
-        let mut rotated_origin: Point3f = *ray.origin();
-        let mut rotated_direction: Vec3f = *ray.direction();
-        for &axis in Axis::iterator() {
-            rotated_origin = AxisRotation::rotate(axis, &rotated_origin, -self.euler_angles.component(axis));
-            rotated_direction = AxisRotation::rotate(axis, &rotated_direction, -self.euler_angles.component(axis));
+        transformed_hit(&self.object, &self.transform_at(ray.time())?, ray, ray_t)
+    }
+
+    fn bounding_box(&self) -> &AABB {
+        &self.bounding_box
+    }
+
+    // Lights are sampled where they are at the time of the shadow ray.
+    fn pdf_value(&self, origin: &Point3f, direction: &Vec3f, time: f64) -> f64 {
+        match self.transform_at(time) {
+            Some(transform) => transformed_pdf_value(&self.object, &transform, origin, direction, time),
+            None => 0.0
         }
+    }
 
-        let rotated_ray: Ray = Ray::new(&rotated_origin, &rotated_direction);
+    fn random(&self, origin: &Point3f, u: (f64, f64), time: f64) -> Vec3f {
+        match self.transform_at(time) {
+            Some(transform) => transformed_random(&self.object, &transform, origin, u, time),
+            None => Vec3f::E1
+        }
+    }
 
-        // Determine whether an intersection exists in object space (and if so, where).
-        if let Some(mut rec) = self.object.hit(&rotated_ray, ray_t) {
-            // Transform the intersection from object space back to world space.
+    fn sample_surface(&self, u: (f64, f64), time: f64) -> Option<SurfaceSample> {
+        transformed_sample_surface(&self.object, &self.transform_at(time)?, u, time)
+    }
 
-            for &axis in Axis::iterator() {
-                rec.point = AxisRotation::rotate(axis, &rec.point , self.euler_angles.component(axis));
-                rec.normal = AxisRotation::rotate(axis, &rec.normal, self.euler_angles.component(axis));
-            }
+    fn surface_pdf(&self, origin: &Point3f, direction: &Vec3f, time: f64) -> f64 {
+        match self.transform_at(time) {
+            Some(transform) => transformed_surface_pdf(&self.object, &transform, origin, direction, time),
+            None => 0.0
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::color::Color;
+    use crate::material::{DiffuseLight, Lambertian};
+    use crate::plane::Quad;
+    use crate::sphere::Sphere;
+    use crate::transform::*;
+
+    #[test]
+    fn transformed_hit() {
+        let sphere: Arc<dyn Hittable> = Arc::new(Sphere::new_stationary(
+            &Point3f::ZERO, 1.0, Arc::new(DiffuseLight::from_color(&Color::ONE))
+        ));
+        let transform: Transform = Transform::scale(&Vec3f::new(2.0, 1.0, 1.0)).unwrap()
+            .then(&Transform::rotate_axis(Axis::Z, 90.0))
+            .then(&Transform::translate(&Vec3f::new(0.0, 0.0, -5.0)));
+        let ellipsoid: Transformed = Transformed::new(sphere, &transform);
+
+        let bbox: &AABB = ellipsoid.bounding_box();
+        assert!((bbox.x.min + 1.0).abs() < 1e-9 && (bbox.x.max - 1.0).abs() < 1e-9);
+        assert!((bbox.y.min + 2.0).abs() < 1e-9 && (bbox.y.max - 2.0).abs() < 1e-9);
+        assert!((bbox.z.min + 6.0).abs() < 1e-9 && (bbox.z.max + 4.0).abs() < 1e-9);
+
+        // The long axis now points along Y.
+        let ray: Ray = Ray::with_time(&Point3f::new(0.0, 1.5, 0.0), &-Vec3f::E3, 0.25);
+        let rec: HitRecord = ellipsoid.hit(&ray, &Interval::new(0.001, f64::INFINITY)).unwrap();
+        assert!((rec.point - ray.at(rec.t)).length() < 1e-9);
+        assert!(rec.front_face);
+        assert!((rec.normal.length() - 1.0).abs() < 1e-9);
+
+        // The normal is perpendicular to the surface, not just the transformed sphere normal.
+        let expected: Vec3f = Vec3f::unit_vector(&Vec3f::new(0.0, rec.point.component(Axis::Y) / 4.0, rec.point.component(Axis::Z) + 5.0));
+        assert!((rec.normal - expected).length() < 1e-9);
+        assert!(ellipsoid.hit(&Ray::new(&Point3f::new(1.5, 0.0, 0.0), &-Vec3f::E3), &Interval::UNIVERSE).is_none());
+    }
 
-            return Some(rec);
+    #[test]
+    fn transformed_pdf() {
+        let light = Arc::new(DiffuseLight::from_color(&Color::ONE));
+        let unit: Arc<dyn Hittable> = Arc::new(Quad::new(&Point3f::ZERO, &Vec3f::E1, &Vec3f::E3, light.clone()));
+        let scaled: Transformed = Transformed::new(unit, &Transform::scale(&Vec3f::new(2.0, 1.0, 3.0)).unwrap()
+            .then(&Transform::translate(&Vec3f::new(-1.0, 4.0, -1.5))));
+        let direct: Quad = Quad::new(&Point3f::new(-1.0, 4.0, -1.5), &(2.0 * Vec3f::E1), &(3.0 * Vec3f::E3), light);
+
+        let origin: Point3f = Point3f::ZERO;
+        for direction in [Vec3f::E2, Vec3f::new(0.2, 1.0, -0.2), Vec3f::new(-0.2, 2.0, 0.5)] {
+            let expected: f64 = direct.pdf_value(&origin, &direction, 0.0);
+            assert!(expected > 0.0);
+            assert!((scaled.pdf_value(&origin, &direction, 0.0) - expected).abs() < 1e-9 * expected);
         }
-        None
+
+        let sample: Vec3f = scaled.random(&origin, (0.3, 0.6), 0.0);
+        assert!((sample.component(Axis::Y) - 4.0).abs() < 1e-9);
+
+        // Surface points are spread over the scaled area, with the normal staying perpendicular.
+        let surface: SurfaceSample = scaled.sample_surface((0.3, 0.6), 0.0).unwrap();
+        let expected: SurfaceSample = direct.sample_surface((0.3, 0.6), 0.0).unwrap();
+        assert!((surface.point - expected.point).length() < 1e-9);
+        assert!((surface.normal - expected.normal).length() < 1e-9);
+        assert!((surface.pdf - 1.0 / 6.0).abs() < 1e-12 && (expected.pdf - 1.0 / 6.0).abs() < 1e-12);
+        assert!((scaled.surface_pdf(&origin, &Vec3f::E2, 0.0) - 1.0 / 6.0).abs() < 1e-12);
     }
 
-    fn bounding_box(&self) -> &AABB {
-        &self.bounding_box
+    #[test]
+    fn decomposition() {
+        let transforms: [Transform; 3] = [
+            Transform::rotate(&Vec3f::new(10.0, -70.0, 30.0)).then(&Transform::translate(&Vec3f::new(1.0, 2.0, 3.0))),
+            Transform::shear(0.5, 0.0, 0.2, 0.0, 0.0, 0.1).unwrap().then(&Transform::rotate_axis(Axis::X, 120.0)),
+            Transform::scale(&Vec3f::new(-2.0, 1.0, 0.5)).unwrap().then(&Transform::rotate_axis(Axis::Z, 45.0))
+        ];
+        for transform in transforms {
+            let decomposed: Decomposed = Decomposed::new(&transform);
+            let (matrix, expected) = (decomposed.matrix(), transform.matrix());
+            let identity: Matrix4 = *decomposed.transform().unwrap().inverse().matrix() * matrix;
+            for row in 0..4 {
+                for column in 0..4 {
+                    assert!((matrix.get(row, column) - expected.get(row, column)).abs() < 1e-9, "{} != {}", matrix, expected);
+                    assert!((identity.get(row, column) - Matrix4::IDENTITY.get(row, column)).abs() < 1e-9, "{}", identity);
+                }
+            }
+        }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn motion_blur() {
+        let white = Arc::new(Lambertian::from_color(&Color::ONE));
+        let unit_box: Arc<dyn Hittable> = Quad::new_box(&Point3f::new(1.0, 0.0, 0.0), &Point3f::new(3.0, 1.0, 1.0), white);
+        let start: Transform = Transform::IDENTITY;
+        let end: Transform = Transform::rotate_axis(Axis::Y, 90.0).then(&Transform::translate(&Vec3f::new(0.0, 2.0, 0.0)));
+        let moving: MotionTransformed = MotionTransformed::new(unit_box.clone(), vec![
+            Keyframe { time: 0.0, value: start }, Keyframe { time: 1.0, value: end }
+        ]).unwrap();
+
+        // At each key time, rays see the object as a plain transformed one.
+        let ray_t: Interval = Interval::new(0.001, f64::INFINITY);
+        for (time, transform) in [(0.0, start), (1.0, end)] {
+            let expected: Transformed = Transformed::new(unit_box.clone(), &transform);
+            for origin in [Point3f::new(2.0, 0.5, 10.0), Point3f::new(0.5, 2.5, -10.0), Point3f::new(-2.5, 2.5, 10.0)] {
+                let direction: Vec3f = Point3f::new(0.0, origin.component(Axis::Y), 0.0) - origin;
+                let ray: Ray = Ray::with_time(&origin, &direction, time);
+                assert_eq!(moving.hit(&ray, &ray_t).map(|rec| rec.t), expected.hit(&ray, &ray_t).map(|rec| rec.t));
+            }
+
+            // Light sampling sees it there too.
+            let origin: Point3f = Point3f::new(0.0, 10.0, 0.0);
+            let sample: SurfaceSample = moving.sample_surface((0.3, 0.6), time).unwrap();
+            assert!((sample.point - expected.sample_surface((0.3, 0.6), time).unwrap().point).length() < 1e-9);
+            let direction: Vec3f = moving.random(&origin, (0.3, 0.6), time);
+            assert!((direction - expected.random(&origin, (0.3, 0.6), time)).length() < 1e-9);
+            let pdf: f64 = expected.pdf_value(&origin, &direction, time);
+            assert!(pdf > 0.0 && (moving.pdf_value(&origin, &direction, time) - pdf).abs() < 1e-9 * pdf);
+        }
+
+        // Halfway, the box has turned 45 degrees without shrinking, and every placement lies in the
+        // bounding box.
+        let halfway: Transform = moving.transform_at(0.5).unwrap();
+        let expected: Transform = Transform::rotate_axis(Axis::Y, 45.0).then(&Transform::translate(&Vec3f::new(0.0, 1.0, 0.0)));
+        assert!((halfway.point(&Point3f::new(3.0, 0.0, 0.0)) - expected.point(&Point3f::new(3.0, 0.0, 0.0))).length() < 1e-9);
+        for i in 0..=1000 {
+            let placed: AABB = moving.transform_at(i as f64 / 1000.0).unwrap().bounding_box(unit_box.bounding_box());
+            for &axis in Axis::iterator() {
+                let (bounds, interval) = (moving.bounding_box().axis_interval(axis), placed.axis_interval(axis));
+                assert!(bounds.min <= interval.min && interval.max <= bounds.max, "{:?} outside {:?}", placed, moving.bounding_box());
+            }
+        }
+        assert!(moving.bounding_box().x.max < 3.25 && moving.bounding_box().z.max < 3.25, "{:?}", moving.bounding_box());
+    }
+}
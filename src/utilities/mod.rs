@@ -1,17 +1,53 @@
 use core::f64;
-use rand::Rng;
+use std::cell::RefCell;
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+// Every thread draws from its own generator. Seeding it before each piece of work, such as a
+// pixel sample, makes the numbers drawn independent of which thread rayon runs the work on.
+// ChaCha8 gives the same numbers for a seed on every platform and rand version, unlike `StdRng`/
+// `thread_rng`, whose algorithm may change between rand versions.
+thread_local! {
+    static RNG: RefCell<ChaCha8Rng> = RefCell::new(ChaCha8Rng::from_entropy());
+}
 
 #[inline]
 pub fn degrees_to_radians(degrees: f64) -> f64 {
     degrees * f64::consts::PI / 180.0
 }
 
+pub fn seed(seed: u64) {
+    // Restarts the current thread's random sequence.
+    RNG.with(|rng| *rng.borrow_mut() = ChaCha8Rng::seed_from_u64(seed));
+}
+
+#[inline]
+pub fn mix_bits(value: u64) -> u64 {
+    // SplitMix64 finalizer: every input bit affects every output bit.
+    let mut z: u64 = value.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+pub fn hash(values: &[u64]) -> u64 {
+    // Combines values into a well mixed seed, so nearby inputs such as neighbouring pixels get
+    // unrelated sequences.
+    values.iter().fold(0x9e37_79b9_7f4a_7c15, |state: u64, value: &u64| mix_bits(state ^ value))
+}
+
+#[inline]
+pub fn with_rng<T>(f: impl FnOnce(&mut ChaCha8Rng) -> T) -> T {
+    RNG.with(|rng| f(&mut rng.borrow_mut()))
+}
+
 #[inline]
 pub fn random() -> f64 {
-    rand::thread_rng().gen_range(0.0..1.0)
+    with_rng(|rng| rng.gen_range(0.0..1.0))
 }
 
 #[inline]
 pub fn random_f64_range(min: f64, max: f64) -> f64 {
-    rand::thread_rng().gen_range(min..max)
-}
\ No newline at end of file
+    with_rng(|rng| rng.gen_range(min..max))
+}
@@ -7,6 +7,7 @@ use rand::{
     distributions::{Distribution, Standard},
     Rng,
 };
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 use crate::utilities::{self, random};
 
@@ -56,6 +57,20 @@ impl Default for Vec3f {
     }
 }
 
+impl Serialize for Vec3f {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where S: Serializer {
+        (self.x, self.y, self.z).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Vec3f {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error> where D: Deserializer<'de> {
+        // Vectors are written as a three element array, [x, y, z].
+        let (x, y, z) = <(f64, f64, f64)>::deserialize(deserializer)?;
+        Ok(Vec3f::new(x, y, z))
+    }
+}
+
 impl<T> Vec3<T> where T: Copy + Clone {
     pub fn new(x: T, y: T, z: T) -> Self {
         Self { x, y, z }
@@ -140,6 +155,10 @@ impl Vec3f {
         self.length_squared().sqrt()
     }
 
+    pub fn max_component(&self) -> f64 {
+        self.x.max(self.y).max(self.z)
+    }
+
     pub fn near_zero(&self) -> bool {
         // Return true if the vector is close to zero in all dimensions.
         let eps: f64 = 1e-8;
@@ -187,8 +206,14 @@ impl Vec3f {
 
     #[inline]
     pub fn random_cosine_direction() -> Vec3f  {
-        let r1: f64 = random();
-        let r2: f64 = random();
+        Self::sample_cosine_direction((random(), random()))
+    }
+
+    // Deterministic mappings of a 2D sample in [0, 1)^2, for numbers supplied by a Sampler.
+
+    #[inline]
+    pub fn sample_cosine_direction(u: (f64, f64)) -> Vec3f  {
+        let (r1, r2) = u;
     
         let phi: f64 = 2.0 * f64::consts::PI * r1;
         let x = f64::cos(phi) * f64::sqrt(r2);
@@ -198,6 +223,30 @@ impl Vec3f {
         Vec3f::new(x, y, z)
     }
 
+    #[inline]
+    pub fn sample_unit_sphere(u: (f64, f64)) -> Vec3f {
+        let z: f64 = 1.0 - 2.0 * u.0;
+        let r: f64 = f64::sqrt(f64::max(0.0, 1.0 - z * z));
+        let phi: f64 = 2.0 * f64::consts::PI * u.1;
+        Vec3f::new(r * f64::cos(phi), r * f64::sin(phi), z)
+    }
+
+    #[inline]
+    pub fn sample_unit_disk(u: (f64, f64)) -> Vec3f {
+        // Shirley's concentric mapping, which keeps strata of the square together on the disk.
+        let (x, y) = (2.0 * u.0 - 1.0, 2.0 * u.1 - 1.0);
+        if x == 0.0 && y == 0.0 {
+            return Vec3f::ZERO;
+        }
+        let (r, theta) = if x.abs() > y.abs() {
+            (x, f64::consts::FRAC_PI_4 * (y / x))
+        }
+        else {
+            (y, f64::consts::FRAC_PI_2 - f64::consts::FRAC_PI_4 * (x / y))
+        };
+        Vec3f::new(r * f64::cos(theta), r * f64::sin(theta), 0.0)
+    }
+
     #[inline]
     pub fn reflect(v: &Vec3f, n: &Vec3f) -> Vec3f {
         v - 2.0 * Self::dot(v, n) * n